@@ -31,25 +31,18 @@ sol! {
     event Transfer(address indexed from, address indexed to, uint256 value);
     event Approval(address indexed owner, address indexed spender, uint256 value);
 
+    #[derive(Debug)]
     error InsufficientBalance(address from, uint256 have, uint256 want);
+    #[derive(Debug)]
     error InsufficientAllowance(address owner, address spender, uint256 have, uint256 want);
 }
 
+#[derive(SolidityError, Debug)]
 pub enum Erc20Error {
     InsufficientBalance(InsufficientBalance),
     InsufficientAllowance(InsufficientAllowance),
 }
 
-// We will soon provide a #[derive(SolidityError)] to clean this up
-impl From<Erc20Error> for Vec<u8> {
-    fn from(err: Erc20Error) -> Vec<u8> {
-        match err {
-            Erc20Error::InsufficientBalance(e) => e.abi_encode(),
-            Erc20Error::InsufficientAllowance(e) => e.abi_encode(),
-        }
-    }
-}
-
 // These methods aren't exposed to other contracts
 impl<T: Erc20Params> Erc20<T> {
     pub fn transfer_impl(
@@ -66,18 +59,51 @@ impl<T: Erc20Params> Erc20<T> {
                 want: value,
             }));
         }
-        self.balances.get(from).set(old_sender_balance - value);
+        self.balances.setter(from).set(old_sender_balance - value);
         let new_to_balance = self.balances.get(to) + value;
-        self.balances.get(to).set(new_to_balance);
-        // Note: Event logging would be handled by the VM in a real implementation
+        self.balances.setter(to).set(new_to_balance);
+        log(self.vm(), Transfer { from, to, value });
+        Ok(())
+    }
+
+    pub fn approve_impl(&mut self, owner: Address, spender: Address, value: U256) {
+        self.allowances.setter(owner).insert(spender, value);
+        log(self.vm(), Approval { owner, spender, value });
+    }
+
+    /// Deducts `value` from the allowance `owner` gave `spender`; an allowance of
+    /// `U256::MAX` is treated as infinite and left untouched
+    pub fn spend_allowance(
+        &mut self,
+        owner: Address,
+        spender: Address,
+        value: U256,
+    ) -> Result<(), Erc20Error> {
+        let old_allowance = self.allowances.get(owner).get(spender);
+        if old_allowance == U256::MAX {
+            return Ok(());
+        }
+        if old_allowance < value {
+            return Err(Erc20Error::InsufficientAllowance(InsufficientAllowance {
+                owner,
+                spender,
+                have: old_allowance,
+                want: value,
+            }));
+        }
+        self.allowances.setter(owner).insert(spender, old_allowance - value);
         Ok(())
     }
 
     pub fn mint(&mut self, address: Address, value: U256) {
         let new_balance = self.balances.get(address) + value;
-        self.balances.get(address).set(new_balance);
+        self.balances.setter(address).set(new_balance);
         self.total_supply.set(self.total_supply.get() + value);
-        // Note: Event logging would be handled by the VM in a real implementation
+        log(self.vm(), Transfer {
+            from: Address::ZERO,
+            to: address,
+            value,
+        });
     }
 
     pub fn burn(&mut self, address: Address, value: U256) -> Result<(), Erc20Error> {
@@ -89,9 +115,13 @@ impl<T: Erc20Params> Erc20<T> {
                 want: value,
             }));
         }
-        self.balances.get(address).set(old_balance - value);
+        self.balances.setter(address).set(old_balance - value);
         self.total_supply.set(self.total_supply.get() - value);
-        // Note: Event logging would be handled by the VM in a real implementation
+        log(self.vm(), Transfer {
+            from: address,
+            to: Address::ZERO,
+            value,
+        });
         Ok(())
     }
 }
@@ -99,15 +129,16 @@ impl<T: Erc20Params> Erc20<T> {
 // These methods are external to other contracts
 #[public]
 impl<T: Erc20Params> Erc20<T> {
-    pub fn name() -> Result<String, Erc20Error> {
+    // Metadata getters take `&self` so inheriting contracts can override them with views
+    pub fn name(&self) -> Result<String, Erc20Error> {
         Ok(T::NAME.into())
     }
 
-    pub fn symbol() -> Result<String, Erc20Error> {
+    pub fn symbol(&self) -> Result<String, Erc20Error> {
         Ok(T::SYMBOL.into())
     }
 
-    pub fn decimals() -> Result<u8, Erc20Error> {
+    pub fn decimals(&self) -> Result<u8, Erc20Error> {
         Ok(T::DECIMALS)
     }
 
@@ -123,8 +154,7 @@ impl<T: Erc20Params> Erc20<T> {
 
     pub fn approve(&mut self, spender: Address, value: U256) -> Result<bool, Erc20Error> {
         let owner = self.vm().msg_sender();
-        self.allowances.get(owner).set(spender, value);
-        // Note: Event logging would be handled by the VM in a real implementation
+        self.approve_impl(owner, spender, value);
         Ok(true)
     }
 
@@ -135,16 +165,7 @@ impl<T: Erc20Params> Erc20<T> {
         value: U256,
     ) -> Result<bool, Erc20Error> {
         let spender = self.vm().msg_sender();
-        let old_allowance = self.allowances.get(from).get(spender);
-        if old_allowance < value {
-            return Err(Erc20Error::InsufficientAllowance(InsufficientAllowance {
-                owner: from,
-                spender,
-                have: old_allowance,
-                want: value,
-            }));
-        }
-        self.allowances.get(from).set(spender, old_allowance - value);
+        self.spend_allowance(from, spender, value)?;
         self.transfer_impl(from, to, value)?;
         Ok(true)
     }
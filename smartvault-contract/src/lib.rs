@@ -7,14 +7,27 @@ extern crate alloc;
 // Main contract entry point
 
 // Import modules
+mod erc20;
+mod math;
+mod mock_tokens;
+mod ownable;
+mod pausable;
+mod reentrancy_guard;
+mod safe_erc20;
+mod strategies;
 mod vault_factory;
 mod user_vault;
 
+#[cfg(test)]
+mod test_host;
 #[cfg(test)]
 mod tests;
 
 // Re-export the VaultFactory as the main contract
 pub use vault_factory::VaultFactory;
 
+// Mock asset tokens for local devnets (deployed with the `mock-*` features)
+pub use mock_tokens::{MockFeeToken, MockRebasingToken, MockStablecoin, MockWeth};
+
 // The VaultFactory is now the main contract
 // All functionality is implemented in the vault_factory module
\ No newline at end of file
@@ -7,5 +7,19 @@ pub extern "C" fn main() {}
 #[cfg(feature = "export-abi")]
 fn main() {
     use stylus_sdk::abi::export::print_from_args;
+    #[cfg(feature = "mock-stablecoin")]
+    print_from_args::<vault_factory::MockStablecoin>();
+    #[cfg(feature = "mock-weth")]
+    print_from_args::<vault_factory::MockWeth>();
+    #[cfg(feature = "mock-fee-token")]
+    print_from_args::<vault_factory::MockFeeToken>();
+    #[cfg(feature = "mock-rebasing-token")]
+    print_from_args::<vault_factory::MockRebasingToken>();
+    #[cfg(not(any(
+        feature = "mock-stablecoin",
+        feature = "mock-weth",
+        feature = "mock-fee-token",
+        feature = "mock-rebasing-token"
+    )))]
     print_from_args::<vault_factory::VaultFactory>();
 }
@@ -0,0 +1,45 @@
+use stylus_sdk::{
+    alloy_primitives::{U256, U512},
+    alloy_sol_types::sol,
+    prelude::*,
+};
+
+// Declare Solidity error types
+sol! {
+    #[derive(Debug)]
+    error MathOverflowedMulDiv();
+}
+
+#[derive(SolidityError, Debug)]
+pub enum MathError {
+    OverflowedMulDiv(MathOverflowedMulDiv),
+}
+
+/// Rounding direction for share/asset conversions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round towards zero
+    Floor,
+    /// Round away from zero
+    Ceil,
+}
+
+/// Computes `x * y / denominator` with the given rounding. The product is kept
+/// in 512 bits, so this only fails when `denominator` is zero or the rounded
+/// result does not fit in 256 bits.
+pub fn mul_div(x: U256, y: U256, denominator: U256, rounding: Rounding) -> Result<U256, MathError> {
+    if denominator == U256::ZERO {
+        return Err(MathError::OverflowedMulDiv(MathOverflowedMulDiv {}));
+    }
+
+    let product: U512 = x.widening_mul(y);
+    let denominator = U512::from(denominator);
+    let (mut quotient, remainder) = product.div_rem(denominator);
+    if rounding == Rounding::Ceil && remainder != U512::ZERO {
+        // Cannot wrap: x * y is at most 2^512 - 2^257 + 1
+        quotient += U512::from(1);
+    }
+
+    U256::checked_from_limbs_slice(quotient.as_limbs())
+        .ok_or(MathError::OverflowedMulDiv(MathOverflowedMulDiv {}))
+}
@@ -0,0 +1,312 @@
+use alloc::vec::Vec;
+use stylus_sdk::{
+    alloy_primitives::{Address, U256},
+    prelude::*,
+    storage::StorageAddress,
+};
+
+use crate::erc20::{Erc20, Erc20Params};
+use crate::math::{mul_div, Rounding};
+
+/// Basis point denominator for the mock fee token
+const BPS: u64 = 10_000;
+
+/// Rebasing index that maps one internal unit to one token
+const ONE: u64 = 1_000_000_000_000_000_000;
+
+/// Parameters for the mock stablecoin (USDC-style, 6 decimals)
+pub struct MockStablecoinParams;
+
+impl Erc20Params for MockStablecoinParams {
+    const NAME: &'static str = "Mock USD Coin";
+    const SYMBOL: &'static str = "mUSDC";
+    const DECIMALS: u8 = 6;
+}
+
+/// Parameters for the mock wrapped ether (WETH-style, 18 decimals)
+pub struct MockWethParams;
+
+impl Erc20Params for MockWethParams {
+    const NAME: &'static str = "Mock Wrapped Ether";
+    const SYMBOL: &'static str = "mWETH";
+    const DECIMALS: u8 = 18;
+}
+
+/// Parameters for the mock fee-on-transfer token
+pub struct MockFeeTokenParams;
+
+impl Erc20Params for MockFeeTokenParams {
+    const NAME: &'static str = "Mock Fee Token";
+    const SYMBOL: &'static str = "mFEE";
+    const DECIMALS: u8 = 18;
+}
+
+/// Parameters for the mock rebasing token
+pub struct MockRebasingTokenParams;
+
+impl Erc20Params for MockRebasingTokenParams {
+    const NAME: &'static str = "Mock Rebasing Token";
+    const SYMBOL: &'static str = "mREB";
+    const DECIMALS: u8 = 18;
+}
+
+sol_storage! {
+    /// Test asset token deployable with the `mock-stablecoin` feature
+    #[cfg_attr(feature = "mock-stablecoin", entrypoint)]
+    pub struct MockStablecoin {
+        /// Shared ERC-20 implementation
+        #[borrow]
+        Erc20<MockStablecoinParams> erc20;
+        /// Address allowed to mint
+        address owner;
+    }
+}
+
+sol_storage! {
+    /// Test asset token deployable with the `mock-weth` feature
+    #[cfg_attr(feature = "mock-weth", entrypoint)]
+    pub struct MockWeth {
+        /// Shared ERC-20 implementation
+        #[borrow]
+        Erc20<MockWethParams> erc20;
+        /// Address allowed to mint
+        address owner;
+    }
+}
+
+sol_storage! {
+    /// Test asset that burns a fee on every transfer, deployable with the `mock-fee-token` feature
+    #[cfg_attr(feature = "mock-fee-token", entrypoint)]
+    pub struct MockFeeToken {
+        /// Shared ERC-20 implementation
+        #[borrow]
+        Erc20<MockFeeTokenParams> erc20;
+        /// Address allowed to mint and set the fee
+        address owner;
+        /// Fee burned from each transfer, in basis points
+        uint256 fee_bps;
+    }
+}
+
+sol_storage! {
+    /// Test asset whose balances scale with a global index, deployable with the
+    /// `mock-rebasing-token` feature. The shared ERC-20 balances hold internal units.
+    #[cfg_attr(feature = "mock-rebasing-token", entrypoint)]
+    pub struct MockRebasingToken {
+        /// Shared ERC-20 implementation, in internal units
+        #[borrow]
+        Erc20<MockRebasingTokenParams> erc20;
+        /// Address allowed to mint and rebase
+        address owner;
+        /// Tokens per internal unit, scaled by 1e18
+        uint256 index;
+    }
+}
+
+#[public]
+#[inherit(Erc20<MockStablecoinParams>)]
+impl MockStablecoin {
+    /// Constructor - sets the minting owner at deployment, so it can't be front-run
+    #[constructor]
+    pub fn constructor(&mut self, owner: Address) -> Result<(), Vec<u8>> {
+        set_owner(&mut self.owner, owner)
+    }
+
+    /// Mints tokens to an account (owner only)
+    pub fn mint(&mut self, to: Address, value: U256) -> Result<(), Vec<u8>> {
+        if self.vm().msg_sender() != self.owner.get() {
+            return Err("Not owner".into());
+        }
+        self.erc20.mint(to, value);
+        Ok(())
+    }
+
+    /// Burns tokens from the caller
+    pub fn burn(&mut self, value: U256) -> Result<(), Vec<u8>> {
+        let from = self.vm().msg_sender();
+        self.erc20.burn(from, value)?;
+        Ok(())
+    }
+
+    /// Gets the minting owner
+    pub fn owner(&self) -> Result<Address, Vec<u8>> {
+        Ok(self.owner.get())
+    }
+}
+
+#[public]
+#[inherit(Erc20<MockWethParams>)]
+impl MockWeth {
+    /// Constructor - sets the minting owner at deployment, so it can't be front-run
+    #[constructor]
+    pub fn constructor(&mut self, owner: Address) -> Result<(), Vec<u8>> {
+        set_owner(&mut self.owner, owner)
+    }
+
+    /// Mints tokens to an account (owner only)
+    pub fn mint(&mut self, to: Address, value: U256) -> Result<(), Vec<u8>> {
+        if self.vm().msg_sender() != self.owner.get() {
+            return Err("Not owner".into());
+        }
+        self.erc20.mint(to, value);
+        Ok(())
+    }
+
+    /// Burns tokens from the caller
+    pub fn burn(&mut self, value: U256) -> Result<(), Vec<u8>> {
+        let from = self.vm().msg_sender();
+        self.erc20.burn(from, value)?;
+        Ok(())
+    }
+
+    /// Gets the minting owner
+    pub fn owner(&self) -> Result<Address, Vec<u8>> {
+        Ok(self.owner.get())
+    }
+}
+
+#[public]
+#[inherit(Erc20<MockFeeTokenParams>)]
+impl MockFeeToken {
+    /// Constructor - sets the minting owner and the transfer fee at deployment
+    #[constructor]
+    pub fn constructor(&mut self, owner: Address, fee_bps: U256) -> Result<(), Vec<u8>> {
+        set_owner(&mut self.owner, owner)?;
+        self.store_fee_bps(fee_bps)
+    }
+
+    /// Mints tokens to an account (owner only)
+    pub fn mint(&mut self, to: Address, value: U256) -> Result<(), Vec<u8>> {
+        if self.vm().msg_sender() != self.owner.get() {
+            return Err("Not owner".into());
+        }
+        self.erc20.mint(to, value);
+        Ok(())
+    }
+
+    /// Updates the transfer fee (owner only)
+    pub fn set_fee_bps(&mut self, fee_bps: U256) -> Result<(), Vec<u8>> {
+        if self.vm().msg_sender() != self.owner.get() {
+            return Err("Not owner".into());
+        }
+        self.store_fee_bps(fee_bps)
+    }
+
+    /// Transfers `value` minus the fee, burning the fee from the sender
+    pub fn transfer(&mut self, to: Address, value: U256) -> Result<bool, Vec<u8>> {
+        let from = self.vm().msg_sender();
+        self.transfer_with_fee(from, to, value)?;
+        Ok(true)
+    }
+
+    /// Transfers `value` minus the fee using the caller's allowance
+    pub fn transfer_from(&mut self, from: Address, to: Address, value: U256) -> Result<bool, Vec<u8>> {
+        let spender = self.vm().msg_sender();
+        self.erc20.spend_allowance(from, spender, value)?;
+        self.transfer_with_fee(from, to, value)?;
+        Ok(true)
+    }
+
+    /// Gets the minting owner
+    pub fn owner(&self) -> Result<Address, Vec<u8>> {
+        Ok(self.owner.get())
+    }
+}
+
+impl MockFeeToken {
+    fn store_fee_bps(&mut self, fee_bps: U256) -> Result<(), Vec<u8>> {
+        if fee_bps > U256::from(BPS) {
+            return Err("Fee too high".into());
+        }
+        self.fee_bps.set(fee_bps);
+        Ok(())
+    }
+
+    fn transfer_with_fee(&mut self, from: Address, to: Address, value: U256) -> Result<(), Vec<u8>> {
+        let fee = mul_div(value, self.fee_bps.get(), U256::from(BPS), Rounding::Floor)?;
+        self.erc20.transfer_impl(from, to, value - fee)?;
+        self.erc20.burn(from, fee)?;
+        Ok(())
+    }
+}
+
+#[public]
+#[inherit(Erc20<MockRebasingTokenParams>)]
+impl MockRebasingToken {
+    /// Constructor - sets the minting owner at deployment and the index to 1.0
+    #[constructor]
+    pub fn constructor(&mut self, owner: Address) -> Result<(), Vec<u8>> {
+        set_owner(&mut self.owner, owner)?;
+        self.index.set(U256::from(ONE));
+        Ok(())
+    }
+
+    /// Mints tokens to an account (owner only)
+    pub fn mint(&mut self, to: Address, value: U256) -> Result<(), Vec<u8>> {
+        if self.vm().msg_sender() != self.owner.get() {
+            return Err("Not owner".into());
+        }
+        let units = self.to_units(value)?;
+        self.erc20.mint(to, units);
+        Ok(())
+    }
+
+    /// Scales every balance to `index / 1e18` tokens per unit (owner only)
+    pub fn rebase(&mut self, index: U256) -> Result<(), Vec<u8>> {
+        if self.vm().msg_sender() != self.owner.get() {
+            return Err("Not owner".into());
+        }
+        if index == U256::ZERO {
+            return Err("Invalid index".into());
+        }
+        self.index.set(index);
+        Ok(())
+    }
+
+    pub fn balance_of(&self, account: Address) -> Result<U256, Vec<u8>> {
+        self.to_tokens(self.erc20.balances.get(account))
+    }
+
+    pub fn total_supply(&self) -> Result<U256, Vec<u8>> {
+        self.to_tokens(self.erc20.total_supply.get())
+    }
+
+    pub fn transfer(&mut self, to: Address, value: U256) -> Result<bool, Vec<u8>> {
+        let from = self.vm().msg_sender();
+        let units = self.to_units(value)?;
+        self.erc20.transfer_impl(from, to, units)?;
+        Ok(true)
+    }
+
+    pub fn transfer_from(&mut self, from: Address, to: Address, value: U256) -> Result<bool, Vec<u8>> {
+        let spender = self.vm().msg_sender();
+        self.erc20.spend_allowance(from, spender, value)?;
+        let units = self.to_units(value)?;
+        self.erc20.transfer_impl(from, to, units)?;
+        Ok(true)
+    }
+
+    /// Gets the minting owner
+    pub fn owner(&self) -> Result<Address, Vec<u8>> {
+        Ok(self.owner.get())
+    }
+}
+
+impl MockRebasingToken {
+    fn to_units(&self, tokens: U256) -> Result<U256, Vec<u8>> {
+        Ok(mul_div(tokens, U256::from(ONE), self.index.get(), Rounding::Floor)?)
+    }
+
+    fn to_tokens(&self, units: U256) -> Result<U256, Vec<u8>> {
+        Ok(mul_div(units, self.index.get(), U256::from(ONE), Rounding::Floor)?)
+    }
+}
+
+/// Stores the minting owner chosen at deployment
+fn set_owner(slot: &mut StorageAddress, owner: Address) -> Result<(), Vec<u8>> {
+    if owner == Address::ZERO {
+        return Err("Invalid owner".into());
+    }
+    slot.set(owner);
+    Ok(())
+}
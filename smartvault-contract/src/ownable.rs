@@ -0,0 +1,103 @@
+use alloc::vec::Vec;
+use stylus_sdk::{
+    alloy_primitives::Address,
+    alloy_sol_types::sol,
+    prelude::*,
+};
+
+sol_storage! {
+    /// Ownable implements single-owner access control with two-step transfers.
+    pub struct Ownable {
+        /// Current owner
+        address owner;
+        /// Owner nominated by `transfer_ownership`, pending acceptance
+        address pending_owner;
+    }
+}
+
+// Declare events and Solidity error types
+sol! {
+    event OwnershipTransferStarted(address indexed previousOwner, address indexed newOwner);
+    event OwnershipTransferred(address indexed previousOwner, address indexed newOwner);
+
+    #[derive(Debug)]
+    error OwnableUnauthorizedAccount(address account);
+    #[derive(Debug)]
+    error OwnableInvalidOwner(address owner);
+}
+
+#[derive(SolidityError, Debug)]
+pub enum OwnableError {
+    UnauthorizedAccount(OwnableUnauthorizedAccount),
+    InvalidOwner(OwnableInvalidOwner),
+}
+
+// These methods aren't exposed to other contracts
+impl Ownable {
+    /// Reverts unless the caller is the current owner
+    pub fn only_owner(&self) -> Result<(), OwnableError> {
+        let account = self.vm().msg_sender();
+        if account != self.owner.get() {
+            return Err(OwnableError::UnauthorizedAccount(OwnableUnauthorizedAccount {
+                account,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Sets the owner immediately and clears any pending transfer
+    pub fn transfer_ownership_impl(&mut self, new_owner: Address) {
+        let previous_owner = self.owner.get();
+        self.owner.set(new_owner);
+        self.pending_owner.set(Address::ZERO);
+        log(self.vm(), OwnershipTransferred {
+            previousOwner: previous_owner,
+            newOwner: new_owner,
+        });
+    }
+}
+
+// These methods are external to other contracts
+#[public]
+impl Ownable {
+    pub fn owner(&self) -> Address {
+        self.owner.get()
+    }
+
+    pub fn pending_owner(&self) -> Address {
+        self.pending_owner.get()
+    }
+
+    /// Starts a two-step transfer; `new_owner` must call `accept_ownership`
+    pub fn transfer_ownership(&mut self, new_owner: Address) -> Result<(), OwnableError> {
+        self.only_owner()?;
+        if new_owner == Address::ZERO {
+            return Err(OwnableError::InvalidOwner(OwnableInvalidOwner {
+                owner: new_owner,
+            }));
+        }
+        self.pending_owner.set(new_owner);
+        log(self.vm(), OwnershipTransferStarted {
+            previousOwner: self.owner.get(),
+            newOwner: new_owner,
+        });
+        Ok(())
+    }
+
+    pub fn accept_ownership(&mut self) -> Result<(), OwnableError> {
+        let account = self.vm().msg_sender();
+        if account != self.pending_owner.get() {
+            return Err(OwnableError::UnauthorizedAccount(OwnableUnauthorizedAccount {
+                account,
+            }));
+        }
+        self.transfer_ownership_impl(account);
+        Ok(())
+    }
+
+    pub fn renounce_ownership(&mut self) -> Result<(), OwnableError> {
+        self.only_owner()?;
+        self.transfer_ownership_impl(Address::ZERO);
+        Ok(())
+    }
+}
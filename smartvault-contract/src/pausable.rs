@@ -0,0 +1,74 @@
+use alloc::vec::Vec;
+use stylus_sdk::{alloy_sol_types::sol, prelude::*};
+
+sol_storage! {
+    /// Pausable implements an emergency stop that contracts check before acting.
+    pub struct Pausable {
+        /// Whether the contract is paused
+        bool paused;
+    }
+}
+
+// Declare events and Solidity error types
+sol! {
+    event Paused(address account);
+    event Unpaused(address account);
+
+    #[derive(Debug)]
+    error EnforcedPause();
+    #[derive(Debug)]
+    error ExpectedPause();
+}
+
+#[derive(SolidityError, Debug)]
+pub enum PausableError {
+    EnforcedPause(EnforcedPause),
+    ExpectedPause(ExpectedPause),
+}
+
+// These methods aren't exposed to other contracts
+impl Pausable {
+    /// Reverts if the contract is paused
+    pub fn when_not_paused(&self) -> Result<(), PausableError> {
+        if self.paused.get() {
+            return Err(PausableError::EnforcedPause(EnforcedPause {}));
+        }
+        Ok(())
+    }
+
+    /// Reverts unless the contract is paused
+    pub fn when_paused(&self) -> Result<(), PausableError> {
+        if !self.paused.get() {
+            return Err(PausableError::ExpectedPause(ExpectedPause {}));
+        }
+        Ok(())
+    }
+
+    /// Pauses the contract; callers are responsible for access control
+    pub fn pause_impl(&mut self) -> Result<(), PausableError> {
+        self.when_not_paused()?;
+        self.paused.set(true);
+        log(self.vm(), Paused {
+            account: self.vm().msg_sender(),
+        });
+        Ok(())
+    }
+
+    /// Unpauses the contract; callers are responsible for access control
+    pub fn unpause_impl(&mut self) -> Result<(), PausableError> {
+        self.when_paused()?;
+        self.paused.set(false);
+        log(self.vm(), Unpaused {
+            account: self.vm().msg_sender(),
+        });
+        Ok(())
+    }
+}
+
+// These methods are external to other contracts
+#[public]
+impl Pausable {
+    pub fn paused(&self) -> bool {
+        self.paused.get()
+    }
+}
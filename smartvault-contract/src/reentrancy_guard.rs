@@ -0,0 +1,47 @@
+use alloc::vec::Vec;
+use stylus_sdk::{alloy_sol_types::sol, prelude::*};
+
+sol_storage! {
+    /// ReentrancyGuard locks state-changing entry points while they run.
+    /// It has no external methods, so contracts embed it with `#[borrow]` and
+    /// wrap their bodies in `enter` / `exit`.
+    pub struct ReentrancyGuard {
+        /// Whether an entry point is currently executing
+        bool entered;
+    }
+}
+
+// Declare Solidity error types
+sol! {
+    #[derive(Debug)]
+    error ReentrancyGuardReentrantCall();
+}
+
+#[derive(SolidityError, Debug)]
+pub enum ReentrancyGuardError {
+    ReentrantCall(ReentrancyGuardReentrantCall),
+}
+
+impl ReentrancyGuard {
+    /// Takes the lock, reverting if it is already held
+    pub fn enter(&mut self) -> Result<(), ReentrancyGuardError> {
+        self.when_not_entered()?;
+        self.entered.set(true);
+        Ok(())
+    }
+
+    /// Reverts if the lock is held, for entry points that never call out
+    pub fn when_not_entered(&self) -> Result<(), ReentrancyGuardError> {
+        if self.entered.get() {
+            return Err(ReentrancyGuardError::ReentrantCall(
+                ReentrancyGuardReentrantCall {},
+            ));
+        }
+        Ok(())
+    }
+
+    /// Releases the lock
+    pub fn exit(&mut self) {
+        self.entered.set(false);
+    }
+}
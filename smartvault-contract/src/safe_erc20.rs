@@ -0,0 +1,101 @@
+use alloc::vec::Vec;
+use stylus_sdk::{
+    alloy_primitives::{Address, U256},
+    alloy_sol_types::{sol, SolCall, SolError},
+    stylus_core::{
+        calls::{context::Call, errors::Error},
+        Host,
+    },
+};
+
+// Declare the ERC-20 calls made on external tokens and the Solidity error types
+sol! {
+    interface IERC20 {
+        function transfer(address to, uint256 value) external returns (bool);
+        function transferFrom(address from, address to, uint256 value) external returns (bool);
+        function approve(address spender, uint256 value) external returns (bool);
+        function balanceOf(address account) external view returns (uint256);
+    }
+
+    #[derive(Debug)]
+    error SafeErc20FailedOperation(address token);
+    #[derive(Debug)]
+    error FailedCall(address target);
+}
+
+/// Transfers `value` tokens from this contract to `to`
+pub fn safe_transfer(vm: &dyn Host, token: Address, to: Address, value: U256) -> Result<(), Vec<u8>> {
+    let data = IERC20::transferCall { to, value }.abi_encode();
+    call_optional_return(vm, token, &data)
+}
+
+/// Transfers `value` tokens from `from` to `to` using this contract's allowance
+pub fn safe_transfer_from(
+    vm: &dyn Host,
+    token: Address,
+    from: Address,
+    to: Address,
+    value: U256,
+) -> Result<(), Vec<u8>> {
+    let data = IERC20::transferFromCall { from, to, value }.abi_encode();
+    call_optional_return(vm, token, &data)
+}
+
+/// Sets the allowance of `spender` to `value`. Tokens that refuse to change a
+/// non-zero allowance (USDT) are reset to zero first.
+pub fn force_approve(vm: &dyn Host, token: Address, spender: Address, value: U256) -> Result<(), Vec<u8>> {
+    let data = IERC20::approveCall { spender, value }.abi_encode();
+    if call_optional_return(vm, token, &data).is_ok() {
+        return Ok(());
+    }
+    let reset = IERC20::approveCall { spender, value: U256::ZERO }.abi_encode();
+    call_optional_return(vm, token, &reset)?;
+    call_optional_return(vm, token, &data)
+}
+
+/// Reads the token balance of `account`
+pub fn balance_of(vm: &dyn Host, token: Address, account: Address) -> Result<U256, Vec<u8>> {
+    let data = IERC20::balanceOfCall { account }.abi_encode();
+    let returned = match vm.static_call(&Call::new(), token, &data) {
+        Ok(returned) => returned,
+        Err(Error::Revert(reason)) if !reason.is_empty() => return Err(reason),
+        Err(_) => return Err(FailedCall { target: token }.abi_encode()),
+    };
+    if returned.len() < 32 {
+        return Err(FailedCall { target: token }.abi_encode());
+    }
+    Ok(U256::from_be_slice(&returned[..32]))
+}
+
+/// Calls a contract and bubbles up its revert reason, returning the raw return data
+pub fn function_call(vm: &dyn Host, target: Address, data: &[u8]) -> Result<Vec<u8>, Vec<u8>> {
+    match vm.call(&Call::new(), target, data) {
+        Ok(returned) => Ok(returned),
+        Err(Error::Revert(reason)) if !reason.is_empty() => Err(reason),
+        Err(_) => Err(FailedCall { target }.abi_encode()),
+    }
+}
+
+/// Static-calls a view on `target` and decodes what it returns
+pub fn view<C: SolCall>(vm: &dyn Host, target: Address, call: C) -> Result<C::Return, Vec<u8>> {
+    let returned = vm
+        .static_call(&Call::new(), target, &call.abi_encode())
+        .map_err(|_| FailedCall { target }.abi_encode())?;
+    C::abi_decode_returns(&returned, true).map_err(|_| FailedCall { target }.abi_encode())
+}
+
+/// Calls a token and accepts either no return data or a single `true`.
+/// Empty return data is only trusted when the token has code, since calls to
+/// accounts without code always succeed.
+fn call_optional_return(vm: &dyn Host, token: Address, data: &[u8]) -> Result<(), Vec<u8>> {
+    let returned = function_call(vm, token, data)?;
+    let succeeded = if returned.is_empty() {
+        vm.code_size(token) > 0
+    } else {
+        returned.len() >= 32 && U256::from_be_slice(&returned[..32]) == U256::ONE
+    };
+    if !succeeded {
+        return Err(SafeErc20FailedOperation { token }.abi_encode());
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use alloc::{vec, vec::Vec};
+use stylus_sdk::{
+    alloy_primitives::{Address, U256},
+    alloy_sol_types::{sol, SolCall},
+};
+
+use super::{entry_point, rewards_config, Strategy, StrategyHost};
+use crate::safe_erc20;
+
+sol! {
+    interface IAavePool {
+        struct ReserveData {
+            uint256 configuration;
+            uint128 liquidityIndex;
+            uint128 currentLiquidityRate;
+            uint128 variableBorrowIndex;
+            uint128 currentVariableBorrowRate;
+            uint128 currentStableBorrowRate;
+            uint40 lastUpdateTimestamp;
+            uint16 id;
+            address aTokenAddress;
+            address stableDebtTokenAddress;
+            address variableDebtTokenAddress;
+            address interestRateStrategyAddress;
+            uint128 accruedToTreasury;
+            uint128 unbacked;
+            uint128 isolationModeTotalDebt;
+        }
+
+        function supply(address asset, uint256 amount, address onBehalfOf, uint16 referralCode) external;
+        function withdraw(address asset, uint256 amount, address to) external returns (uint256);
+        function getReserveData(address asset) external view returns (ReserveData memory);
+    }
+
+    interface IAaveRewardsController {
+        function claimRewards(address[] assets, uint256 amount, address to, address reward) external returns (uint256);
+    }
+}
+
+/// Cache key of the aToken for the vault asset
+const A_TOKEN_KEY: &str = "aave_atoken";
+
+/// Aave v3 lending pool. The position is the vault's aToken balance, which
+/// accrues 1:1 with the underlying.
+pub struct AaveV3Strategy;
+
+impl Strategy for AaveV3Strategy {
+    fn id(&self) -> &'static str {
+        "aave"
+    }
+
+    fn deposit(&self, host: &mut dyn StrategyHost, amount: U256) -> Result<(), Vec<u8>> {
+        let pool = entry_point(host, self.id(), "Aave")?;
+        let asset = host.asset_token();
+        safe_erc20::force_approve(host.host(), asset, pool, amount)?;
+
+        let data = IAavePool::supplyCall {
+            asset,
+            amount,
+            onBehalfOf: host.vault_address(),
+            referralCode: 0,
+        }
+        .abi_encode();
+        safe_erc20::function_call(host.host(), pool, &data)?;
+
+        // The aToken balance values the position from now on
+        if host.cached_address(A_TOKEN_KEY) == Address::ZERO {
+            let a_token = fetch_a_token(host, pool)?;
+            host.cache_address(A_TOKEN_KEY, a_token);
+        }
+        Ok(())
+    }
+
+    fn withdraw(&self, host: &mut dyn StrategyHost, amount: U256) -> Result<(), Vec<u8>> {
+        let pool = entry_point(host, self.id(), "Aave")?;
+        // Aave treats `type(uint256).max` as the full balance
+        let data = IAavePool::withdrawCall {
+            asset: host.asset_token(),
+            amount,
+            to: host.vault_address(),
+        }
+        .abi_encode();
+        safe_erc20::function_call(host.host(), pool, &data)?;
+        Ok(())
+    }
+
+    fn total_value(&self, host: &dyn StrategyHost) -> Result<U256, Vec<u8>> {
+        let a_token = host.cached_address(A_TOKEN_KEY);
+        if a_token == Address::ZERO {
+            return Ok(U256::ZERO);
+        }
+        safe_erc20::balance_of(host.host(), a_token, host.vault_address())
+    }
+
+    /// The aToken belongs to the old pool, so the next deposit looks it up again
+    fn forget_entry_point(&self, host: &mut dyn StrategyHost) {
+        host.cache_address(A_TOKEN_KEY, Address::ZERO);
+    }
+
+    fn is_harvestable(&self, host: &dyn StrategyHost) -> Result<bool, Vec<u8>> {
+        Ok(rewards_config(host, self.id()).is_ok() && self.total_value(host)? != U256::ZERO)
+    }
+
+    fn harvest(&self, host: &mut dyn StrategyHost) -> Result<(Address, U256), Vec<u8>> {
+        entry_point(host, self.id(), "Aave")?;
+        let (controller, reward_token) = rewards_config(host, self.id())?;
+        let vault = host.vault_address();
+
+        let data = IAaveRewardsController::claimRewardsCall {
+            assets: vec![host.cached_address(A_TOKEN_KEY)],
+            amount: U256::MAX,
+            to: vault,
+            reward: reward_token,
+        }
+        .abi_encode();
+        let before = safe_erc20::balance_of(host.host(), reward_token, vault)?;
+        safe_erc20::function_call(host.host(), controller, &data)?;
+        let after = safe_erc20::balance_of(host.host(), reward_token, vault)?;
+        Ok((reward_token, after.saturating_sub(before)))
+    }
+}
+
+/// Looks up the aToken of the vault asset on an Aave pool
+fn fetch_a_token(host: &dyn StrategyHost, pool: Address) -> Result<Address, Vec<u8>> {
+    let call = IAavePool::getReserveDataCall { asset: host.asset_token() };
+    let reserve = safe_erc20::view(host.host(), pool, call)?._0;
+    if reserve.aTokenAddress == Address::ZERO {
+        return Err("Aave reserve not found".into());
+    }
+    Ok(reserve.aTokenAddress)
+}
@@ -0,0 +1,80 @@
+use alloc::vec::Vec;
+use stylus_sdk::{
+    alloy_primitives::{Address, U256},
+    alloy_sol_types::{sol, SolCall},
+};
+
+use super::{entry_point, rewards_config, Strategy, StrategyHost};
+use crate::safe_erc20;
+
+sol! {
+    interface IComet {
+        function supply(address asset, uint256 amount) external;
+        function withdraw(address asset, uint256 amount) external;
+    }
+
+    interface ICometRewards {
+        function claim(address comet, address src, bool shouldAccrue) external;
+    }
+}
+
+/// Compound v3 (Comet) market. Comet's `balanceOf` is the supplied base asset
+/// plus accrued interest, so the vault asset must be the market's base token.
+pub struct CometStrategy;
+
+impl Strategy for CometStrategy {
+    fn id(&self) -> &'static str {
+        "compound"
+    }
+
+    fn deposit(&self, host: &mut dyn StrategyHost, amount: U256) -> Result<(), Vec<u8>> {
+        let comet = entry_point(host, self.id(), "Compound")?;
+        let asset = host.asset_token();
+        safe_erc20::force_approve(host.host(), asset, comet, amount)?;
+
+        let data = IComet::supplyCall { asset, amount }.abi_encode();
+        safe_erc20::function_call(host.host(), comet, &data)?;
+        Ok(())
+    }
+
+    fn withdraw(&self, host: &mut dyn StrategyHost, amount: U256) -> Result<(), Vec<u8>> {
+        let comet = entry_point(host, self.id(), "Compound")?;
+        // Comet treats `type(uint256).max` as the full base balance
+        let data = IComet::withdrawCall {
+            asset: host.asset_token(),
+            amount,
+        }
+        .abi_encode();
+        safe_erc20::function_call(host.host(), comet, &data)?;
+        Ok(())
+    }
+
+    fn total_value(&self, host: &dyn StrategyHost) -> Result<U256, Vec<u8>> {
+        let comet = host.cached_address(self.id());
+        if comet == Address::ZERO {
+            return Ok(U256::ZERO);
+        }
+        safe_erc20::balance_of(host.host(), comet, host.vault_address())
+    }
+
+    fn is_harvestable(&self, host: &dyn StrategyHost) -> Result<bool, Vec<u8>> {
+        Ok(rewards_config(host, self.id()).is_ok() && self.total_value(host)? != U256::ZERO)
+    }
+
+    fn harvest(&self, host: &mut dyn StrategyHost) -> Result<(Address, U256), Vec<u8>> {
+        let comet = entry_point(host, self.id(), "Compound")?;
+        let (distributor, reward_token) = rewards_config(host, self.id())?;
+        let vault = host.vault_address();
+
+        let data = ICometRewards::claimCall {
+            comet,
+            src: vault,
+            shouldAccrue: true,
+        }
+        .abi_encode();
+        let before = safe_erc20::balance_of(host.host(), reward_token, vault)?;
+        safe_erc20::function_call(host.host(), distributor, &data)?;
+        let after = safe_erc20::balance_of(host.host(), reward_token, vault)?;
+        Ok((reward_token, after.saturating_sub(before)))
+    }
+}
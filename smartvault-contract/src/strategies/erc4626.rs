@@ -0,0 +1,138 @@
+use alloc::vec::Vec;
+use stylus_sdk::{
+    alloy_primitives::{Address, U256},
+    alloy_sol_types::{sol, SolCall},
+};
+
+use super::{entry_point, Strategy, StrategyHost};
+use crate::safe_erc20;
+
+sol! {
+    interface IERC4626 {
+        function asset() external view returns (address);
+        function deposit(uint256 assets, address receiver) external returns (uint256);
+        function withdraw(uint256 assets, address receiver, address owner) external returns (uint256);
+        function redeem(uint256 shares, address receiver, address owner) external returns (uint256);
+        function convertToAssets(uint256 shares) external view returns (uint256);
+    }
+
+    interface IStrategyVault {
+        function getProtocolAddress(string protocol) external view returns (address);
+    }
+}
+
+/// Vaults followed when looking for a path back to this one
+const MAX_CHAIN_DEPTH: usize = 8;
+
+/// Any external ERC-4626 vault on the same asset, including another
+/// `UserVault`. The position is the vault's share balance in the target,
+/// valued with the target's `convertToAssets`; yield compounds in the target's
+/// share price, so there is nothing to claim on harvest.
+///
+/// Allocating into a vault that (directly or through further vaults) allocates
+/// back into this one would let each count the other's shares as assets, so
+/// the target's own ERC-4626 target is followed and any path back is refused.
+pub struct Erc4626Strategy;
+
+impl Strategy for Erc4626Strategy {
+    fn id(&self) -> &'static str {
+        "erc4626"
+    }
+
+    fn set_target(&self, host: &mut dyn StrategyHost, target: Address) -> Result<(), Vec<u8>> {
+        if target != Address::ZERO {
+            check_not_circular(host, target)?;
+            let underlying = safe_erc20::view(host.host(), target, IERC4626::assetCall {})?._0;
+            if underlying != host.asset_token() {
+                return Err("Asset mismatch".into());
+            }
+        }
+        host.cache_address(self.id(), target);
+        Ok(())
+    }
+
+    fn deposit(&self, host: &mut dyn StrategyHost, amount: U256) -> Result<(), Vec<u8>> {
+        let target = entry_point(host, self.id(), "ERC-4626 vault")?;
+        check_not_circular(host, target)?;
+        safe_erc20::force_approve(host.host(), host.asset_token(), target, amount)?;
+
+        let data = IERC4626::depositCall {
+            assets: amount,
+            receiver: host.vault_address(),
+        }
+        .abi_encode();
+        safe_erc20::function_call(host.host(), target, &data)?;
+        Ok(())
+    }
+
+    fn withdraw(&self, host: &mut dyn StrategyHost, amount: U256) -> Result<(), Vec<u8>> {
+        let target = entry_point(host, self.id(), "ERC-4626 vault")?;
+        let vault = host.vault_address();
+        // Redeeming every share leaves no dust behind, which withdrawing the
+        // rounded-down share value could
+        let data = if amount == U256::MAX {
+            IERC4626::redeemCall {
+                shares: safe_erc20::balance_of(host.host(), target, vault)?,
+                receiver: vault,
+                owner: vault,
+            }
+            .abi_encode()
+        } else {
+            IERC4626::withdrawCall {
+                assets: amount,
+                receiver: vault,
+                owner: vault,
+            }
+            .abi_encode()
+        };
+        safe_erc20::function_call(host.host(), target, &data)?;
+        Ok(())
+    }
+
+    fn total_value(&self, host: &dyn StrategyHost) -> Result<U256, Vec<u8>> {
+        let target = host.cached_address(self.id());
+        if target == Address::ZERO {
+            return Ok(U256::ZERO);
+        }
+        let shares = safe_erc20::balance_of(host.host(), target, host.vault_address())?;
+        if shares == U256::ZERO {
+            return Ok(U256::ZERO);
+        }
+        Ok(safe_erc20::view(host.host(), target, IERC4626::convertToAssetsCall { shares })?._0)
+    }
+
+    fn harvest(&self, host: &mut dyn StrategyHost) -> Result<(Address, U256), Vec<u8>> {
+        entry_point(host, self.id(), "ERC-4626 vault")?;
+        Ok((host.asset_token(), U256::ZERO))
+    }
+
+    /// Yield accrues in the share price, so there is never anything to claim
+    fn is_harvestable(&self, _host: &dyn StrategyHost) -> Result<bool, Vec<u8>> {
+        Ok(false)
+    }
+}
+
+/// Follows each vault's "erc4626" target from `target` and refuses the chain
+/// if it leads back to this vault. A target that doesn't expose
+/// `getProtocolAddress` (any vault other than a `UserVault`) ends the chain.
+fn check_not_circular(host: &dyn StrategyHost, target: Address) -> Result<(), Vec<u8>> {
+    let vault = host.vault_address();
+    let mut next = target;
+    for _ in 0..MAX_CHAIN_DEPTH {
+        if next == vault {
+            return Err("Circular allocation".into());
+        }
+        let call = IStrategyVault::getProtocolAddressCall {
+            protocol: Erc4626Strategy.id().into(),
+        };
+        next = match safe_erc20::view(host.host(), next, call) {
+            Ok(returned) => returned._0,
+            Err(_) => return Ok(()),
+        };
+        if next == Address::ZERO {
+            return Ok(());
+        }
+    }
+    Err("Allocation chain too deep".into())
+}
+
@@ -0,0 +1,124 @@
+//! Protocol adapters used by `UserVault` to put idle assets to work.
+//!
+//! Each adapter is a stateless module implementing `Strategy`; whatever it
+//! needs to remember (protocol entry points, receipt tokens, reward
+//! distributors) lives in the vault's address cache and is reached through
+//! `StrategyHost`. The vault measures what actually moves, keeps allocations
+//! and emits events, so a new protocol only needs an adapter and an entry in
+//! `STRATEGIES`.
+
+use alloc::vec::Vec;
+use stylus_sdk::{
+    alloy_primitives::{Address, U256},
+    stylus_core::Host,
+};
+
+pub mod aave;
+pub mod compound;
+pub mod erc4626;
+pub mod tick_math;
+pub mod uniswap;
+
+pub use aave::AaveV3Strategy;
+pub use compound::CometStrategy;
+pub use erc4626::Erc4626Strategy;
+pub use uniswap::UniswapStrategy;
+
+/// What an adapter can see of the vault it works for
+pub trait StrategyHost {
+    /// Host used for external calls
+    fn host(&self) -> &dyn Host;
+    /// The vault's underlying asset
+    fn asset_token(&self) -> Address;
+    /// The vault itself, which owns every position
+    fn vault_address(&self) -> Address;
+    /// Reads an address from the vault's protocol address cache
+    fn cached_address(&self, key: &str) -> Address;
+    /// Writes an address to the vault's protocol address cache
+    fn cache_address(&mut self, key: &str, value: Address);
+    /// Reads a number from the vault's strategy value cache
+    fn cached_value(&self, key: &str) -> U256;
+    /// Writes a number to the vault's strategy value cache
+    fn cache_value(&mut self, key: &str, value: U256);
+}
+
+/// A yield source the vault can allocate to
+pub trait Strategy {
+    /// Identifier used by the vault (`"aave"`, `"compound"`, ...); also the
+    /// cache key of the protocol entry point
+    fn id(&self) -> &'static str;
+
+    /// Points the strategy at an owner-chosen contract; protocols whose
+    /// addresses come from the factory are not configurable
+    fn set_target(&self, _host: &mut dyn StrategyHost, _target: Address) -> Result<(), Vec<u8>> {
+        Err("Target not configurable".into())
+    }
+
+    /// Whether the vault knows where to deposit, i.e. the protocol entry point is set
+    fn is_configured(&self, host: &dyn StrategyHost) -> bool {
+        host.cached_address(self.id()) != Address::ZERO
+    }
+
+    /// Moves `amount` of the vault's idle assets into the protocol
+    fn deposit(&self, host: &mut dyn StrategyHost, amount: U256) -> Result<(), Vec<u8>>;
+
+    /// Returns `amount` of assets to the vault; `U256::MAX` withdraws the whole position
+    fn withdraw(&self, host: &mut dyn StrategyHost, amount: U256) -> Result<(), Vec<u8>>;
+
+    /// Value of the vault's position, in the underlying asset
+    fn total_value(&self, host: &dyn StrategyHost) -> Result<U256, Vec<u8>>;
+
+    /// Claims incentive rewards to the vault, returning the reward token and amount claimed
+    fn harvest(&self, host: &mut dyn StrategyHost) -> Result<(Address, U256), Vec<u8>>;
+
+    /// Whether `harvest` has anything to claim, so batch harvests can skip the rest
+    fn is_harvestable(&self, host: &dyn StrategyHost) -> Result<bool, Vec<u8>> {
+        Ok(self.total_value(host)? != U256::ZERO)
+    }
+
+    /// Drops whatever was looked up through the old entry point, once the
+    /// vault has moved the (empty) position to a new one
+    fn forget_entry_point(&self, _host: &mut dyn StrategyHost) {}
+
+    /// Pulls everything back to the vault, e.g. when the protocol is at risk
+    fn emergency_exit(&self, host: &mut dyn StrategyHost) -> Result<(), Vec<u8>> {
+        self.withdraw(host, U256::MAX)
+    }
+}
+
+/// Every strategy the vault can allocate to
+pub const STRATEGIES: &[&dyn Strategy] = &[&AaveV3Strategy, &CometStrategy, &UniswapStrategy, &Erc4626Strategy];
+
+/// Looks up a registered strategy by id
+pub fn get(id: &str) -> Option<&'static dyn Strategy> {
+    STRATEGIES.iter().copied().find(|strategy| strategy.id() == id)
+}
+
+/// Cache key of a protocol's reward distributor
+pub fn rewards_key(id: &str) -> alloc::string::String {
+    alloc::format!("{id}_rewards")
+}
+
+/// Cache key of the token a protocol pays its rewards in
+pub fn reward_token_key(id: &str) -> alloc::string::String {
+    alloc::format!("{id}_reward_token")
+}
+
+/// Reads the protocol entry point, failing with `"<Name> address not set"`
+fn entry_point(host: &dyn StrategyHost, id: &str, name: &str) -> Result<Address, Vec<u8>> {
+    let address = host.cached_address(id);
+    if address == Address::ZERO {
+        return Err(alloc::format!("{name} address not set").into_bytes());
+    }
+    Ok(address)
+}
+
+/// Reads a protocol's (reward distributor, reward token)
+fn rewards_config(host: &dyn StrategyHost, id: &str) -> Result<(Address, Address), Vec<u8>> {
+    let rewards = host.cached_address(&rewards_key(id));
+    let reward_token = host.cached_address(&reward_token_key(id));
+    if rewards == Address::ZERO || reward_token == Address::ZERO {
+        return Err("Rewards not configured".into());
+    }
+    Ok((rewards, reward_token))
+}
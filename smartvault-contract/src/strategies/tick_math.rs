@@ -0,0 +1,147 @@
+//! Ports of Uniswap v3 `TickMath.getSqrtRatioAtTick` and the
+//! `LiquidityAmounts` library, used to value concentrated-liquidity positions.
+//! Prices are `sqrt(token1 / token0)` as Q64.96 fixed point.
+
+use alloc::vec::Vec;
+use stylus_sdk::alloy_primitives::U256;
+
+use crate::math::{mul_div, Rounding};
+
+/// Smallest tick a position can use
+pub const MIN_TICK: i32 = -887_272;
+/// Largest tick a position can use
+pub const MAX_TICK: i32 = 887_272;
+
+/// `2^128 / sqrt(1.0001)^(2^i)` for each bit `i` of a tick, as Q128.128
+const TICK_RATIOS: [u128; 19] = [
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+    0x48a170391f7dc42444e8fa2,
+];
+
+fn q96() -> U256 {
+    U256::ONE << 96
+}
+
+/// Returns `sqrt(1.0001^tick)` as Q64.96, rounded up like the pool does
+pub fn sqrt_ratio_at_tick(tick: i32) -> Result<U256, Vec<u8>> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return Err("Tick out of range".into());
+    }
+
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = if abs_tick & 1 != 0 {
+        U256::from(0xfffcb933bd6fad37aa2d162d1a594001_u128)
+    } else {
+        U256::ONE << 128
+    };
+    for (bit, factor) in TICK_RATIOS.iter().enumerate() {
+        if abs_tick & (2 << bit) != 0 {
+            ratio = (ratio * U256::from(*factor)) >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    // Q128.128 to Q64.96, rounding up so the result is never below the true price
+    let rounded_up = if ratio % (U256::ONE << 32) == U256::ZERO { 0 } else { 1 };
+    Ok((ratio >> 32) + U256::from(rounded_up))
+}
+
+/// Sorts a price range
+fn sorted(sqrt_a: U256, sqrt_b: U256) -> (U256, U256) {
+    if sqrt_a > sqrt_b {
+        (sqrt_b, sqrt_a)
+    } else {
+        (sqrt_a, sqrt_b)
+    }
+}
+
+/// Token0 held by `liquidity` across a price range
+pub fn amount0_for_liquidity(sqrt_a: U256, sqrt_b: U256, liquidity: U256) -> Result<U256, Vec<u8>> {
+    let (sqrt_a, sqrt_b) = sorted(sqrt_a, sqrt_b);
+    if sqrt_a == U256::ZERO {
+        return Err("Invalid price".into());
+    }
+    Ok(mul_div(liquidity << 96, sqrt_b - sqrt_a, sqrt_b, Rounding::Floor)? / sqrt_a)
+}
+
+/// Token1 held by `liquidity` across a price range
+pub fn amount1_for_liquidity(sqrt_a: U256, sqrt_b: U256, liquidity: U256) -> Result<U256, Vec<u8>> {
+    let (sqrt_a, sqrt_b) = sorted(sqrt_a, sqrt_b);
+    Ok(mul_div(liquidity, sqrt_b - sqrt_a, q96(), Rounding::Floor)?)
+}
+
+/// (token0, token1) held by `liquidity` in `[sqrt_a, sqrt_b]` at price `sqrt_price`
+pub fn amounts_for_liquidity(
+    sqrt_price: U256,
+    sqrt_a: U256,
+    sqrt_b: U256,
+    liquidity: U256,
+) -> Result<(U256, U256), Vec<u8>> {
+    let (sqrt_a, sqrt_b) = sorted(sqrt_a, sqrt_b);
+    if sqrt_price <= sqrt_a {
+        Ok((amount0_for_liquidity(sqrt_a, sqrt_b, liquidity)?, U256::ZERO))
+    } else if sqrt_price < sqrt_b {
+        Ok((
+            amount0_for_liquidity(sqrt_price, sqrt_b, liquidity)?,
+            amount1_for_liquidity(sqrt_a, sqrt_price, liquidity)?,
+        ))
+    } else {
+        Ok((U256::ZERO, amount1_for_liquidity(sqrt_a, sqrt_b, liquidity)?))
+    }
+}
+
+/// Liquidity `amount0` can provide across a price range
+pub fn liquidity_for_amount0(sqrt_a: U256, sqrt_b: U256, amount0: U256) -> Result<U256, Vec<u8>> {
+    let (sqrt_a, sqrt_b) = sorted(sqrt_a, sqrt_b);
+    if sqrt_a == sqrt_b {
+        return Err("Invalid price".into());
+    }
+    let intermediate = mul_div(sqrt_a, sqrt_b, q96(), Rounding::Floor)?;
+    Ok(mul_div(amount0, intermediate, sqrt_b - sqrt_a, Rounding::Floor)?)
+}
+
+/// Liquidity `amount1` can provide across a price range
+pub fn liquidity_for_amount1(sqrt_a: U256, sqrt_b: U256, amount1: U256) -> Result<U256, Vec<u8>> {
+    let (sqrt_a, sqrt_b) = sorted(sqrt_a, sqrt_b);
+    if sqrt_a == sqrt_b {
+        return Err("Invalid price".into());
+    }
+    Ok(mul_div(amount1, q96(), sqrt_b - sqrt_a, Rounding::Floor)?)
+}
+
+/// Most liquidity `amount0` and `amount1` can provide in `[sqrt_a, sqrt_b]` at price `sqrt_price`
+pub fn liquidity_for_amounts(
+    sqrt_price: U256,
+    sqrt_a: U256,
+    sqrt_b: U256,
+    amount0: U256,
+    amount1: U256,
+) -> Result<U256, Vec<u8>> {
+    let (sqrt_a, sqrt_b) = sorted(sqrt_a, sqrt_b);
+    if sqrt_price <= sqrt_a {
+        liquidity_for_amount0(sqrt_a, sqrt_b, amount0)
+    } else if sqrt_price < sqrt_b {
+        Ok(liquidity_for_amount0(sqrt_price, sqrt_b, amount0)?.min(liquidity_for_amount1(sqrt_a, sqrt_price, amount1)?))
+    } else {
+        liquidity_for_amount1(sqrt_a, sqrt_b, amount1)
+    }
+}
@@ -0,0 +1,571 @@
+use alloc::vec::Vec;
+use stylus_sdk::{
+    alloy_primitives::{
+        aliases::{I24, U160, U24},
+        Address, Bytes, U256,
+    },
+    alloy_sol_types::{sol, SolCall, SolError},
+};
+
+use super::{entry_point, tick_math, Strategy, StrategyHost};
+use crate::math::{mul_div, Rounding};
+use crate::safe_erc20::{self, FailedCall};
+
+sol! {
+    interface ISwapRouter {
+        struct ExactInputSingleParams {
+            address tokenIn;
+            address tokenOut;
+            uint24 fee;
+            address recipient;
+            uint256 amountIn;
+            uint256 amountOutMinimum;
+            uint160 sqrtPriceLimitX96;
+        }
+
+        struct ExactInputParams {
+            bytes path;
+            address recipient;
+            uint256 amountIn;
+            uint256 amountOutMinimum;
+        }
+
+        function exactInputSingle(ExactInputSingleParams params) external payable returns (uint256 amountOut);
+        function exactInput(ExactInputParams params) external payable returns (uint256 amountOut);
+        function positionManager() external view returns (address);
+    }
+
+    interface IUniswapV3Pool {
+        function token0() external view returns (address);
+        function token1() external view returns (address);
+        function fee() external view returns (uint24);
+        function tickSpacing() external view returns (int24);
+        function observe(uint32[] secondsAgos) external view returns (int56[] tickCumulatives, uint160[] secondsPerLiquidityCumulativeX128s);
+        function slot0() external view returns (uint160 sqrtPriceX96, int24 tick, uint16 observationIndex, uint16 observationCardinality, uint16 observationCardinalityNext, uint8 feeProtocol, bool unlocked);
+    }
+
+    interface INonfungiblePositionManager {
+        struct MintParams {
+            address token0;
+            address token1;
+            uint24 fee;
+            int24 tickLower;
+            int24 tickUpper;
+            uint256 amount0Desired;
+            uint256 amount1Desired;
+            uint256 amount0Min;
+            uint256 amount1Min;
+            address recipient;
+            uint256 deadline;
+        }
+
+        struct IncreaseLiquidityParams {
+            uint256 tokenId;
+            uint256 amount0Desired;
+            uint256 amount1Desired;
+            uint256 amount0Min;
+            uint256 amount1Min;
+            uint256 deadline;
+        }
+
+        struct DecreaseLiquidityParams {
+            uint256 tokenId;
+            uint128 liquidity;
+            uint256 amount0Min;
+            uint256 amount1Min;
+            uint256 deadline;
+        }
+
+        struct CollectParams {
+            uint256 tokenId;
+            address recipient;
+            uint128 amount0Max;
+            uint128 amount1Max;
+        }
+
+        function mint(MintParams params) external payable returns (uint256 tokenId, uint128 liquidity, uint256 amount0, uint256 amount1);
+        function increaseLiquidity(IncreaseLiquidityParams params) external payable returns (uint128 liquidity, uint256 amount0, uint256 amount1);
+        function decreaseLiquidity(DecreaseLiquidityParams params) external payable returns (uint256 amount0, uint256 amount1);
+        function collect(CollectParams params) external payable returns (uint256 amount0, uint256 amount1);
+        function positions(uint256 tokenId) external view returns (uint96 nonce, address operator, address token0, address token1, uint24 fee, int24 tickLower, int24 tickUpper, uint128 liquidity, uint256 feeGrowthInside0LastX128, uint256 feeGrowthInside1LastX128, uint128 tokensOwed0, uint128 tokensOwed1);
+    }
+}
+
+/// Cache key of the pool the vault provides liquidity to
+const POOL_KEY: &str = "uniswap_pool";
+/// Cache key of the position manager holding the vault's position
+const POSITIONS_KEY: &str = "uniswap_positions";
+/// Cache key of the pool's other token
+const PAIR_KEY: &str = "uniswap_pair";
+/// Value-cache key of the vault's position NFT (0 = none)
+const TOKEN_ID_KEY: &str = "uniswap_token_id";
+
+/// Half-width of a new position's range, in ticks (about 1%)
+const RANGE_TICKS: i32 = 100;
+/// Furthest the pool may trade from 1:1 when liquidity is added (about 0.5%)
+const MAX_PEG_TICKS: i32 = 50;
+/// Window of the time-weighted average price positions are valued at
+const TWAP_SECONDS: u32 = 1_800;
+/// Furthest the pool may trade from its TWAP when liquidity is added (about 0.1%)
+const MAX_TWAP_DEVIATION_TICKS: i32 = 10;
+/// Slippage accepted against the expected price when swapping between the
+/// pair's tokens, and against the expected amounts when liquidity moves
+const SWAP_SLIPPAGE_BPS: u64 = 50;
+/// Basis-point denominator
+const MAX_BPS: u64 = 10_000;
+
+/// Length of an address in a swap path
+const ADDRESS_LEN: usize = 20;
+/// Length of an address plus a pool fee in a swap path
+const HOP_LEN: usize = 23;
+
+/// Uniswap v3 concentrated liquidity in an owner-chosen stable pool (set
+/// with the pool as strategy target). Deposits swap half the assets to the
+/// pair token at par and provide both around the current price, so they
+/// only go in while the pool trades near 1:1 and close to its TWAP. The
+/// position, pair token included, is valued at the pool's TWAP rather than
+/// its spot price, and withdrawals take their minimum amounts from it, so
+/// both keep working if the pair loses its peg. Harvest collects trading fees.
+pub struct UniswapStrategy;
+
+impl Strategy for UniswapStrategy {
+    fn id(&self) -> &'static str {
+        "uniswap"
+    }
+
+    fn set_target(&self, host: &mut dyn StrategyHost, pool: Address) -> Result<(), Vec<u8>> {
+        let (positions, pair) = if pool == Address::ZERO {
+            (Address::ZERO, Address::ZERO)
+        } else {
+            let router = entry_point(host, self.id(), "Uniswap")?;
+            let positions = safe_erc20::view(host.host(), router, ISwapRouter::positionManagerCall {})?._0;
+            let token0 = safe_erc20::view(host.host(), pool, IUniswapV3Pool::token0Call {})?._0;
+            let token1 = safe_erc20::view(host.host(), pool, IUniswapV3Pool::token1Call {})?._0;
+            let asset = host.asset_token();
+            let pair = match (token0 == asset, token1 == asset) {
+                (true, false) => token1,
+                (false, true) => token0,
+                _ => return Err("Asset mismatch".into()),
+            };
+            (positions, pair)
+        };
+
+        host.cache_address(POOL_KEY, pool);
+        host.cache_address(POSITIONS_KEY, positions);
+        host.cache_address(PAIR_KEY, pair);
+        host.cache_value(TOKEN_ID_KEY, U256::ZERO);
+        Ok(())
+    }
+
+    /// Deposits need the pool as well as the router
+    fn is_configured(&self, host: &dyn StrategyHost) -> bool {
+        host.cached_address(self.id()) != Address::ZERO && host.cached_address(POOL_KEY) != Address::ZERO
+    }
+
+    fn deposit(&self, host: &mut dyn StrategyHost, amount: U256) -> Result<(), Vec<u8>> {
+        let pool = Pool::load(host)?;
+        let (_, tick) = pool.checked_price(host)?;
+
+        // Provide half as the pair token, then sell back whatever the position did not take
+        let half = amount / U256::from(2);
+        let (asset_before, pair_before) = pool.balances(host)?;
+        swap_at_par(host, pool.asset, pool.pair, pool.fee, half)?;
+        let (asset_after, pair_after) = pool.balances(host)?;
+        let asset_amount = amount.saturating_sub(asset_before.saturating_sub(asset_after));
+        let pair_amount = pair_after.saturating_sub(pair_before);
+
+        safe_erc20::force_approve(host.host(), pool.asset, pool.positions, asset_amount)?;
+        safe_erc20::force_approve(host.host(), pool.pair, pool.positions, pair_amount)?;
+        let (amount0, amount1) = pool.ordered(asset_amount, pair_amount);
+        let deadline = U256::from(host.host().block_timestamp());
+
+        // The swap moved the price, so expect amounts at the price it left
+        let token_id = host.cached_value(TOKEN_ID_KEY);
+        let (tick_lower, tick_upper) = if token_id == U256::ZERO {
+            pool.range_around(host, tick)?
+        } else {
+            let position = pool.position(host, token_id)?;
+            (position.tick_lower, position.tick_upper)
+        };
+        let (sqrt_price, _) = pool.slot0(host)?;
+        let (sqrt_lower, sqrt_upper) = (
+            tick_math::sqrt_ratio_at_tick(tick_lower)?,
+            tick_math::sqrt_ratio_at_tick(tick_upper)?,
+        );
+        let liquidity = tick_math::liquidity_for_amounts(sqrt_price, sqrt_lower, sqrt_upper, amount0, amount1)?;
+        let (amount0_min, amount1_min) = min_amounts(sqrt_price, sqrt_lower, sqrt_upper, liquidity)?;
+
+        if token_id == U256::ZERO {
+            let data = INonfungiblePositionManager::mintCall {
+                params: INonfungiblePositionManager::MintParams {
+                    token0: pool.token0,
+                    token1: pool.token1,
+                    fee: U24::from(pool.fee),
+                    tickLower: I24::try_from(tick_lower).map_err(|_| b"Tick out of range".to_vec())?,
+                    tickUpper: I24::try_from(tick_upper).map_err(|_| b"Tick out of range".to_vec())?,
+                    amount0Desired: amount0,
+                    amount1Desired: amount1,
+                    amount0Min: amount0_min,
+                    amount1Min: amount1_min,
+                    recipient: host.vault_address(),
+                    deadline,
+                },
+            }
+            .abi_encode();
+            let returned = safe_erc20::function_call(host.host(), pool.positions, &data)?;
+            let minted = INonfungiblePositionManager::mintCall::abi_decode_returns(&returned, true)
+                .map_err(|_| FailedCall { target: pool.positions }.abi_encode())?;
+            host.cache_value(TOKEN_ID_KEY, minted.tokenId);
+        } else {
+            let data = INonfungiblePositionManager::increaseLiquidityCall {
+                params: INonfungiblePositionManager::IncreaseLiquidityParams {
+                    tokenId: token_id,
+                    amount0Desired: amount0,
+                    amount1Desired: amount1,
+                    amount0Min: amount0_min,
+                    amount1Min: amount1_min,
+                    deadline,
+                },
+            }
+            .abi_encode();
+            safe_erc20::function_call(host.host(), pool.positions, &data)?;
+        }
+        // Leftover assets stay idle in the vault
+        safe_erc20::force_approve(host.host(), pool.asset, pool.positions, U256::ZERO)?;
+        safe_erc20::force_approve(host.host(), pool.pair, pool.positions, U256::ZERO)?;
+
+        let leftover = pool.balances(host)?.1.saturating_sub(pair_before);
+        swap_at_par(host, pool.pair, pool.asset, pool.fee, leftover)
+    }
+
+    fn withdraw(&self, host: &mut dyn StrategyHost, amount: U256) -> Result<(), Vec<u8>> {
+        let pool = Pool::load(host)?;
+        let token_id = host.cached_value(TOKEN_ID_KEY);
+        if token_id == U256::ZERO {
+            return Err("No Uniswap position".into());
+        }
+
+        // No peg check: depositors must be able to leave a depegged pool
+        let sqrt_price = tick_math::sqrt_ratio_at_tick(pool.twap_tick(host)?)?;
+        let position = pool.position(host, token_id)?;
+        let liquidity = if amount == U256::MAX {
+            position.liquidity
+        } else {
+            let value = self.total_value(&*host)?;
+            if amount >= value {
+                position.liquidity
+            } else {
+                mul_div(position.liquidity, amount, value, Rounding::Ceil)?.min(position.liquidity)
+            }
+        };
+
+        if liquidity != U256::ZERO {
+            let (amount0_min, amount1_min) = min_amounts(
+                sqrt_price,
+                tick_math::sqrt_ratio_at_tick(position.tick_lower)?,
+                tick_math::sqrt_ratio_at_tick(position.tick_upper)?,
+                liquidity,
+            )?;
+            let data = INonfungiblePositionManager::decreaseLiquidityCall {
+                params: INonfungiblePositionManager::DecreaseLiquidityParams {
+                    tokenId: token_id,
+                    liquidity: liquidity.to::<u128>(),
+                    amount0Min: amount0_min,
+                    amount1Min: amount1_min,
+                    deadline: U256::from(host.host().block_timestamp()),
+                },
+            }
+            .abi_encode();
+            safe_erc20::function_call(host.host(), pool.positions, &data)?;
+        }
+        collect_to_asset(host, &pool, token_id)?;
+        Ok(())
+    }
+
+    fn total_value(&self, host: &dyn StrategyHost) -> Result<U256, Vec<u8>> {
+        let token_id = host.cached_value(TOKEN_ID_KEY);
+        if token_id == U256::ZERO {
+            return Ok(U256::ZERO);
+        }
+        let pool = Pool::load(host)?;
+        let position = pool.position(host, token_id)?;
+        // Valued at the TWAP, which a flash loan can't move
+        let sqrt_price = tick_math::sqrt_ratio_at_tick(pool.twap_tick(host)?)?;
+        let (amount0, amount1) = tick_math::amounts_for_liquidity(
+            sqrt_price,
+            tick_math::sqrt_ratio_at_tick(position.tick_lower)?,
+            tick_math::sqrt_ratio_at_tick(position.tick_upper)?,
+            position.liquidity,
+        )?;
+        // `ordered` swaps (token0, token1) back to (asset, pair) as well
+        let (asset_amount, pair_amount) = pool.ordered(amount0 + position.owed0, amount1 + position.owed1);
+        Ok(asset_amount + pool.pair_in_asset(sqrt_price, pair_amount)?)
+    }
+
+    fn harvest(&self, host: &mut dyn StrategyHost) -> Result<(Address, U256), Vec<u8>> {
+        let pool = Pool::load(host)?;
+        let token_id = host.cached_value(TOKEN_ID_KEY);
+        if token_id == U256::ZERO {
+            return Ok((pool.asset, U256::ZERO));
+        }
+        let fees = collect_to_asset(host, &pool, token_id)?;
+        Ok((pool.asset, fees))
+    }
+}
+
+/// The configured pool and the vault's side of it
+struct Pool {
+    address: Address,
+    positions: Address,
+    asset: Address,
+    pair: Address,
+    token0: Address,
+    token1: Address,
+    fee: u32,
+}
+
+/// The parts of a position NFT the strategy uses
+struct Position {
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity: U256,
+    owed0: U256,
+    owed1: U256,
+}
+
+impl Pool {
+    fn load(host: &dyn StrategyHost) -> Result<Self, Vec<u8>> {
+        let address = host.cached_address(POOL_KEY);
+        if address == Address::ZERO {
+            return Err("Uniswap pool not set".into());
+        }
+        let asset = host.asset_token();
+        let pair = host.cached_address(PAIR_KEY);
+        let (token0, token1) = if asset < pair { (asset, pair) } else { (pair, asset) };
+        Ok(Self {
+            address,
+            positions: host.cached_address(POSITIONS_KEY),
+            asset,
+            pair,
+            token0,
+            token1,
+            fee: safe_erc20::view(host.host(), address, IUniswapV3Pool::feeCall {})?._0.to::<u32>(),
+        })
+    }
+
+    /// Current (sqrt price, tick)
+    fn slot0(&self, host: &dyn StrategyHost) -> Result<(U256, i32), Vec<u8>> {
+        let slot0 = safe_erc20::view(host.host(), self.address, IUniswapV3Pool::slot0Call {})?;
+        Ok((U256::from(slot0.sqrtPriceX96), slot0.tick.as_i32()))
+    }
+
+    /// Current (sqrt price, tick) for adding liquidity, refused unless the
+    /// pool trades near 1:1 and within `MAX_TWAP_DEVIATION_TICKS` of its TWAP
+    fn checked_price(&self, host: &dyn StrategyHost) -> Result<(U256, i32), Vec<u8>> {
+        let (sqrt_price, tick) = self.slot0(host)?;
+        check_peg(tick)?;
+        if (tick - self.twap_tick(host)?).abs() > MAX_TWAP_DEVIATION_TICKS {
+            return Err("Pool price deviates from TWAP".into());
+        }
+        Ok((sqrt_price, tick))
+    }
+
+    /// Average tick over the last `TWAP_SECONDS`, rounded down like `OracleLibrary.consult`
+    fn twap_tick(&self, host: &dyn StrategyHost) -> Result<i32, Vec<u8>> {
+        let call = IUniswapV3Pool::observeCall {
+            secondsAgos: alloc::vec![TWAP_SECONDS, 0],
+        };
+        let cumulatives = safe_erc20::view(host.host(), self.address, call)?.tickCumulatives;
+        if cumulatives.len() != 2 {
+            return Err(FailedCall { target: self.address }.abi_encode());
+        }
+        let delta = cumulatives[1].as_i64() - cumulatives[0].as_i64();
+        i32::try_from(delta.div_euclid(i64::from(TWAP_SECONDS))).map_err(|_| b"Tick out of range".to_vec())
+    }
+
+    /// Value in assets of `pair_amount` of the pair token at `sqrt_price`
+    fn pair_in_asset(&self, sqrt_price: U256, pair_amount: U256) -> Result<U256, Vec<u8>> {
+        let q96 = U256::ONE << 96;
+        // The pool prices token0 in token1
+        let (numerator, denominator) = if self.asset == self.token0 {
+            (q96, sqrt_price)
+        } else {
+            (sqrt_price, q96)
+        };
+        let scaled = mul_div(pair_amount, numerator, denominator, Rounding::Floor)?;
+        Ok(mul_div(scaled, numerator, denominator, Rounding::Floor)?)
+    }
+
+    /// The vault's (asset, pair token) balances
+    fn balances(&self, host: &dyn StrategyHost) -> Result<(U256, U256), Vec<u8>> {
+        let vault = host.vault_address();
+        Ok((
+            safe_erc20::balance_of(host.host(), self.asset, vault)?,
+            safe_erc20::balance_of(host.host(), self.pair, vault)?,
+        ))
+    }
+
+    /// Orders (asset, pair) amounts as (token0, token1)
+    fn ordered(&self, asset_amount: U256, pair_amount: U256) -> (U256, U256) {
+        if self.asset == self.token0 {
+            (asset_amount, pair_amount)
+        } else {
+            (pair_amount, asset_amount)
+        }
+    }
+
+    /// A range of about `RANGE_TICKS` either side of `tick`, on the pool's tick spacing
+    fn range_around(&self, host: &dyn StrategyHost, tick: i32) -> Result<(i32, i32), Vec<u8>> {
+        let spacing = safe_erc20::view(host.host(), self.address, IUniswapV3Pool::tickSpacingCall {})?._0.as_i32();
+        if spacing <= 0 {
+            return Err("Invalid tick spacing".into());
+        }
+        let lower = (tick - RANGE_TICKS).div_euclid(spacing) * spacing;
+        let upper = (tick + RANGE_TICKS + spacing - 1).div_euclid(spacing) * spacing;
+        Ok((lower, upper))
+    }
+
+    fn position(&self, host: &dyn StrategyHost, token_id: U256) -> Result<Position, Vec<u8>> {
+        let position = safe_erc20::view(host.host(), self.positions, INonfungiblePositionManager::positionsCall { tokenId: token_id })?;
+        Ok(Position {
+            tick_lower: position.tickLower.as_i32(),
+            tick_upper: position.tickUpper.as_i32(),
+            liquidity: U256::from(position.liquidity),
+            owed0: U256::from(position.tokensOwed0),
+            owed1: U256::from(position.tokensOwed1),
+        })
+    }
+}
+
+/// Collects everything owed to the position and sells the pair token at
+/// about the TWAP, returning the assets gained
+fn collect_to_asset(host: &mut dyn StrategyHost, pool: &Pool, token_id: U256) -> Result<U256, Vec<u8>> {
+    let (asset_before, pair_before) = pool.balances(host)?;
+    let data = INonfungiblePositionManager::collectCall {
+        params: INonfungiblePositionManager::CollectParams {
+            tokenId: token_id,
+            recipient: host.vault_address(),
+            amount0Max: u128::MAX,
+            amount1Max: u128::MAX,
+        },
+    }
+    .abi_encode();
+    safe_erc20::function_call(host.host(), pool.positions, &data)?;
+
+    let pair_collected = pool.balances(host)?.1.saturating_sub(pair_before);
+    if pair_collected != U256::ZERO {
+        let sqrt_price = tick_math::sqrt_ratio_at_tick(pool.twap_tick(host)?)?;
+        let min_amount_out = less_slippage(pool.pair_in_asset(sqrt_price, pair_collected)?)?;
+        swap_exact_input_single(host, pool.pair, pool.asset, pool.fee, pair_collected, min_amount_out)?;
+    }
+    Ok(pool.balances(host)?.0.saturating_sub(asset_before))
+}
+
+/// Swaps between the pair's tokens in its own pool, accepting at most
+/// `SWAP_SLIPPAGE_BPS` below 1:1
+fn swap_at_par(
+    host: &mut dyn StrategyHost,
+    token_in: Address,
+    token_out: Address,
+    fee: u32,
+    amount_in: U256,
+) -> Result<(), Vec<u8>> {
+    if amount_in == U256::ZERO {
+        return Ok(());
+    }
+    let min_amount_out = mul_div(
+        amount_in,
+        U256::from(MAX_BPS - SWAP_SLIPPAGE_BPS),
+        U256::from(MAX_BPS),
+        Rounding::Ceil,
+    )?;
+    swap_exact_input_single(host, token_in, token_out, fee, amount_in, min_amount_out)
+}
+
+/// Least (token0, token1) adding or removing `liquidity` at `sqrt_price` should
+/// move, allowing `SWAP_SLIPPAGE_BPS` off the expected amounts
+fn min_amounts(sqrt_price: U256, sqrt_lower: U256, sqrt_upper: U256, liquidity: U256) -> Result<(U256, U256), Vec<u8>> {
+    let (amount0, amount1) = tick_math::amounts_for_liquidity(sqrt_price, sqrt_lower, sqrt_upper, liquidity)?;
+    Ok((less_slippage(amount0)?, less_slippage(amount1)?))
+}
+
+/// `amount` less `SWAP_SLIPPAGE_BPS`
+fn less_slippage(amount: U256) -> Result<U256, Vec<u8>> {
+    Ok(mul_div(amount, U256::from(MAX_BPS - SWAP_SLIPPAGE_BPS), U256::from(MAX_BPS), Rounding::Floor)?)
+}
+
+/// Refuses to add liquidity to pools trading too far from 1:1 to swap into at par
+fn check_peg(tick: i32) -> Result<(), Vec<u8>> {
+    if tick.abs() > MAX_PEG_TICKS {
+        return Err("Pool off peg".into());
+    }
+    Ok(())
+}
+
+
+/// Sells `amount_in` of `token_in` for `token_out` in a single pool of the
+/// given fee tier, sending the output to the vault. Reverts in the router if
+/// less than `min_amount_out` comes back.
+pub fn swap_exact_input_single(
+    host: &mut dyn StrategyHost,
+    token_in: Address,
+    token_out: Address,
+    fee: u32,
+    amount_in: U256,
+    min_amount_out: U256,
+) -> Result<(), Vec<u8>> {
+    let router = entry_point(host, UniswapStrategy.id(), "Uniswap")?;
+    safe_erc20::force_approve(host.host(), token_in, router, amount_in)?;
+
+    let data = ISwapRouter::exactInputSingleCall {
+        params: ISwapRouter::ExactInputSingleParams {
+            tokenIn: token_in,
+            tokenOut: token_out,
+            fee: U24::from(fee),
+            recipient: host.vault_address(),
+            amountIn: amount_in,
+            amountOutMinimum: min_amount_out,
+            sqrtPriceLimitX96: U160::ZERO,
+        },
+    }
+    .abi_encode();
+    safe_erc20::function_call(host.host(), router, &data)?;
+    Ok(())
+}
+
+/// Splits an encoded multi-hop path (`token (fee token)+`) into its first and last token
+pub fn path_ends(path: &[u8]) -> Result<(Address, Address), Vec<u8>> {
+    if path.len() < ADDRESS_LEN + HOP_LEN || (path.len() - ADDRESS_LEN) % HOP_LEN != 0 {
+        return Err("Invalid swap path".into());
+    }
+    Ok((
+        Address::from_slice(&path[..ADDRESS_LEN]),
+        Address::from_slice(&path[path.len() - ADDRESS_LEN..]),
+    ))
+}
+
+/// Sells `amount_in` of the path's first token along `path`, sending the
+/// output to the vault. Reverts in the router if less than `min_amount_out`
+/// comes back.
+pub fn swap_exact_input(
+    host: &mut dyn StrategyHost,
+    path: &[u8],
+    amount_in: U256,
+    min_amount_out: U256,
+) -> Result<(), Vec<u8>> {
+    let router = entry_point(host, UniswapStrategy.id(), "Uniswap")?;
+    let (token_in, _) = path_ends(path)?;
+    safe_erc20::force_approve(host.host(), token_in, router, amount_in)?;
+
+    let data = ISwapRouter::exactInputCall {
+        params: ISwapRouter::ExactInputParams {
+            path: Bytes::copy_from_slice(path),
+            recipient: host.vault_address(),
+            amountIn: amount_in,
+            amountOutMinimum: min_amount_out,
+        },
+    }
+    .abi_encode();
+    safe_erc20::function_call(host.host(), router, &data)?;
+    Ok(())
+}
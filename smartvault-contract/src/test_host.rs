@@ -0,0 +1,322 @@
+//! Test host that runs simulated external contracts in-process.
+//!
+//! `TestVM` answers external calls from a fixed table of mocked return data,
+//! so a contract cannot observe an external balance change within one call.
+//! `SimHost` wraps a `TestVM` and routes calls to registered addresses through
+//! real contract code (e.g. the mock tokens), each backed by its own `TestVM`.
+//! Calls mocked on the wrapped `TestVM` still take precedence, and accounts
+//! without code never run their handler. A contract that itself makes calls
+//! (e.g. a second vault) runs on a host from `at`, which shares the registry.
+
+use std::{cell::RefCell, collections::HashMap, ops::Deref, rc::Rc};
+
+use stylus_sdk::{
+    abi::Router,
+    alloy_primitives::{Address, B256, U256},
+    stylus_core::{deploy::DeploymentAccess, storage::TopLevelStorage, ValueDenier},
+    testing::*,
+};
+
+/// Handles a call from `sender` with the given calldata
+type Handler = Box<dyn FnMut(Address, &[u8]) -> Result<Vec<u8>, Vec<u8>>>;
+
+/// Handlers are shared so that one can call back into the host while running
+type SharedHandler = Rc<RefCell<Handler>>;
+
+#[derive(Clone)]
+pub struct SimHost {
+    /// Host of the contract under test; mocks on it still apply to unregistered addresses
+    pub vm: TestVM,
+    /// Holds the code of registered contracts for every host sharing the registry
+    world: TestVM,
+    contracts: Rc<RefCell<HashMap<Address, SharedHandler>>>,
+}
+
+impl SimHost {
+    pub fn new() -> Self {
+        let vm = TestVM::default();
+        Self {
+            world: vm.clone(),
+            vm,
+            contracts: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// Host for another contract deployed at `address`, seeing the same registered contracts
+    pub fn at(&self, address: Address) -> Self {
+        let vm = TestVM::default();
+        vm.set_contract_address(address);
+        Self {
+            vm,
+            world: self.world.clone(),
+            contracts: Rc::clone(&self.contracts),
+        }
+    }
+
+    /// Answers calls to `address` with `handler`
+    pub fn register(&self, address: Address, handler: Handler) {
+        self.world.set_code(address, vec![0xfe]);
+        self.contracts.borrow_mut().insert(address, Rc::new(RefCell::new(handler)));
+    }
+
+    /// Deploys `contract` at `address`: calls are dispatched through its router
+    /// with the caller as `msg.sender` on `contract_vm`
+    pub fn register_contract<C>(&self, address: Address, contract: Rc<RefCell<C>>, contract_vm: TestVM)
+    where
+        C: Router<C> + TopLevelStorage + std::borrow::BorrowMut<<C as Router<C>>::Storage> + ValueDenier + 'static,
+    {
+        self.register(
+            address,
+            Box::new(move |sender, data| {
+                if data.len() < 4 {
+                    return Err(Vec::new());
+                }
+                contract_vm.set_sender(sender);
+                let selector = u32::from_be_bytes(data[..4].try_into().unwrap());
+                let mut contract = contract.borrow_mut();
+                C::route(&mut *contract, selector, &data[4..]).unwrap_or(Err(Vec::new()))
+            }),
+        );
+    }
+
+    /// Calls a registered contract as `sender`, e.g. to set up balances from a test
+    pub fn call_as(&self, sender: Address, to: Address, data: &[u8]) -> Result<Vec<u8>, Vec<u8>> {
+        let handler = self.contracts.borrow().get(&to).cloned().expect("no contract registered");
+        let mut handler = handler.borrow_mut();
+        handler(sender, data)
+    }
+
+    fn dispatch(&self, to: Address, data: &[u8], mocked: bool) -> Option<Result<Vec<u8>, Error>> {
+        if mocked || self.world.code_size(to) == 0 {
+            return None;
+        }
+        let handler = self.contracts.borrow().get(&to).cloned()?;
+        let mut handler = handler.borrow_mut();
+        Some(handler(self.vm.contract_address(), data).map_err(Error::Revert))
+    }
+}
+
+impl Deref for SimHost {
+    type Target = TestVM;
+
+    fn deref(&self) -> &TestVM {
+        &self.vm
+    }
+}
+
+impl Host for SimHost {}
+
+impl CallAccess for SimHost {
+    fn call(&self, context: &dyn MutatingCallContext, to: Address, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mocked = self.vm.snapshot().call_returns.contains_key(&(to, data.to_vec()));
+        self.dispatch(to, data, mocked)
+            .unwrap_or_else(|| self.vm.call(context, to, data))
+    }
+
+    unsafe fn delegate_call(
+        &self,
+        context: &dyn MutatingCallContext,
+        to: Address,
+        data: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        self.vm.delegate_call(context, to, data)
+    }
+
+    fn static_call(&self, context: &dyn StaticCallContext, to: Address, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mocked = self.vm.snapshot().static_call_returns.contains_key(&(to, data.to_vec()));
+        self.dispatch(to, data, mocked)
+            .unwrap_or_else(|| self.vm.static_call(context, to, data))
+    }
+}
+
+// Everything else is answered by the wrapped TestVM
+
+impl CryptographyAccess for SimHost {
+    fn native_keccak256(&self, input: &[u8]) -> B256 {
+        self.vm.native_keccak256(input)
+    }
+}
+
+impl CalldataAccess for SimHost {
+    fn read_args(&self, len: usize) -> Vec<u8> {
+        self.vm.read_args(len)
+    }
+    fn read_return_data(&self, offset: usize, size: Option<usize>) -> Vec<u8> {
+        self.vm.read_return_data(offset, size)
+    }
+    fn return_data_size(&self) -> usize {
+        self.vm.return_data_size()
+    }
+    fn write_result(&self, data: &[u8]) {
+        self.vm.write_result(data)
+    }
+}
+
+unsafe impl UnsafeDeploymentAccess for SimHost {
+    unsafe fn create1(
+        &self,
+        code: *const u8,
+        code_len: usize,
+        endowment: *const u8,
+        contract: *mut u8,
+        revert_data_len: *mut usize,
+    ) {
+        self.vm.create1(code, code_len, endowment, contract, revert_data_len)
+    }
+    unsafe fn create2(
+        &self,
+        code: *const u8,
+        code_len: usize,
+        endowment: *const u8,
+        salt: *const u8,
+        contract: *mut u8,
+        revert_data_len: *mut usize,
+    ) {
+        self.vm.create2(code, code_len, endowment, salt, contract, revert_data_len)
+    }
+}
+
+impl StorageAccess for SimHost {
+    unsafe fn storage_cache_bytes32(&self, key: U256, value: B256) {
+        self.vm.storage_cache_bytes32(key, value)
+    }
+    fn flush_cache(&self, clear: bool) {
+        self.vm.flush_cache(clear)
+    }
+    fn storage_load_bytes32(&self, key: U256) -> B256 {
+        self.vm.storage_load_bytes32(key)
+    }
+}
+
+unsafe impl UnsafeCallAccess for SimHost {
+    unsafe fn call_contract(
+        &self,
+        to: *const u8,
+        data: *const u8,
+        data_len: usize,
+        value: *const u8,
+        gas: u64,
+        outs_len: &mut usize,
+    ) -> u8 {
+        self.vm.call_contract(to, data, data_len, value, gas, outs_len)
+    }
+    unsafe fn delegate_call_contract(
+        &self,
+        to: *const u8,
+        data: *const u8,
+        data_len: usize,
+        gas: u64,
+        outs_len: &mut usize,
+    ) -> u8 {
+        self.vm.delegate_call_contract(to, data, data_len, gas, outs_len)
+    }
+    unsafe fn static_call_contract(
+        &self,
+        to: *const u8,
+        data: *const u8,
+        data_len: usize,
+        gas: u64,
+        outs_len: &mut usize,
+    ) -> u8 {
+        self.vm.static_call_contract(to, data, data_len, gas, outs_len)
+    }
+}
+
+impl BlockAccess for SimHost {
+    fn block_basefee(&self) -> U256 {
+        self.vm.block_basefee()
+    }
+    fn block_coinbase(&self) -> Address {
+        self.vm.block_coinbase()
+    }
+    fn block_gas_limit(&self) -> u64 {
+        self.vm.block_gas_limit()
+    }
+    fn block_number(&self) -> u64 {
+        self.vm.block_number()
+    }
+    fn block_timestamp(&self) -> u64 {
+        self.vm.block_timestamp()
+    }
+}
+
+impl ChainAccess for SimHost {
+    fn chain_id(&self) -> u64 {
+        self.vm.chain_id()
+    }
+}
+
+impl AccountAccess for SimHost {
+    fn balance(&self, account: Address) -> U256 {
+        self.vm.balance(account)
+    }
+    fn contract_address(&self) -> Address {
+        self.vm.contract_address()
+    }
+    fn code(&self, account: Address) -> Vec<u8> {
+        self.world.code(account)
+    }
+    fn code_size(&self, account: Address) -> usize {
+        self.world.code_size(account)
+    }
+    fn code_hash(&self, account: Address) -> B256 {
+        self.world.code_hash(account)
+    }
+}
+
+impl MemoryAccess for SimHost {
+    fn pay_for_memory_grow(&self, pages: u16) {
+        self.vm.pay_for_memory_grow(pages)
+    }
+}
+
+impl MessageAccess for SimHost {
+    fn msg_reentrant(&self) -> bool {
+        self.vm.msg_reentrant()
+    }
+    fn msg_sender(&self) -> Address {
+        self.vm.msg_sender()
+    }
+    fn msg_value(&self) -> U256 {
+        self.vm.msg_value()
+    }
+    fn tx_origin(&self) -> Address {
+        self.vm.tx_origin()
+    }
+}
+
+impl MeteringAccess for SimHost {
+    fn evm_gas_left(&self) -> u64 {
+        self.vm.evm_gas_left()
+    }
+    fn evm_ink_left(&self) -> u64 {
+        self.vm.evm_ink_left()
+    }
+    fn tx_gas_price(&self) -> U256 {
+        self.vm.tx_gas_price()
+    }
+    fn tx_ink_price(&self) -> u32 {
+        self.vm.tx_ink_price()
+    }
+}
+
+impl ValueTransfer for SimHost {
+    fn transfer_eth(&self, to: Address, amount: U256) -> Result<(), Vec<u8>> {
+        self.vm.transfer_eth(to, amount)
+    }
+}
+
+impl DeploymentAccess for SimHost {
+    unsafe fn deploy(&self, code: &[u8], endowment: U256, salt: Option<B256>) -> Result<Address, Vec<u8>> {
+        self.vm.deploy(code, endowment, salt)
+    }
+}
+
+impl LogAccess for SimHost {
+    fn emit_log(&self, input: &[u8], num_topics: usize) {
+        self.vm.emit_log(input, num_topics)
+    }
+    fn raw_log(&self, topics: &[B256], data: &[u8]) -> Result<(), &'static str> {
+        self.vm.raw_log(topics, data)
+    }
+}
@@ -1,255 +1,3483 @@
-#[cfg(test)]
-mod tests {
-    use stylus_sdk::alloy_primitives::Address;
+use std::{cell::{Cell, RefCell}, collections::HashMap, rc::Rc};
 
-    // Mock addresses for testing
-    fn mock_usdc() -> Address {
-        Address::from([1u8; 20])
+use stylus_sdk::{
+    abi::Bytes,
+    alloy_primitives::{
+        aliases::{I24, I56, U160, U24, U96},
+        Address, I256, U256,
+    },
+    stylus_core::storage::TopLevelStorage,
+    alloy_sol_types::{sol, sol_data, SolCall, SolError, SolEvent, SolType},
+    testing::*,
+};
+
+use crate::erc20::{Approval, Erc20, Transfer};
+use crate::math::{mul_div, MathError, Rounding};
+use crate::mock_tokens::{
+    MockFeeToken, MockRebasingToken, MockStablecoin, MockStablecoinParams, MockWethParams,
+};
+use crate::ownable::{OwnableError, OwnershipTransferred};
+use crate::pausable::{Paused, PausableError};
+use crate::reentrancy_guard::ReentrancyGuardReentrantCall;
+use crate::test_host::SimHost;
+use crate::safe_erc20::{FailedCall, SafeErc20FailedOperation, IERC20};
+use crate::user_vault::{
+    DeadlineExpired, Deposit, ERC4626ExceededMaxDeposit, ERC4626ExceededMaxMint, ERC4626ExceededMaxRedeem,
+    ERC4626ExceededMaxWithdraw, ERC5143ExcessiveAssets, ERC5143ExcessiveShares,
+    ERC5143InsufficientAssets, ERC5143InsufficientShares, ExceededMaxLoss,
+    InsufficientHarvestProceeds, ProtocolAddressesRefreshed, ProtocolHarvested, ProtocolWithdrawn, Rebalanced,
+    Harvested, StrategistUpdated, Swapped,
+    UserVault, Withdraw, WORK_HARVEST, WORK_IDLE, WORK_REBALANCE,
+};
+use crate::strategies::{
+    aave::{IAavePool, IAaveRewardsController},
+    compound::{IComet, ICometRewards},
+    erc4626::{IStrategyVault, IERC4626},
+    tick_math::{amounts_for_liquidity, liquidity_for_amounts, sqrt_ratio_at_tick, MAX_TICK, MIN_TICK},
+    uniswap::{INonfungiblePositionManager, ISwapRouter, IUniswapV3Pool},
+    STRATEGIES,
+};
+use crate::vault_factory::{
+    IKeeperFactory, IUserVault, PerformData, UpkeepFailed, VaultFactory, VaultRefreshFailed, VaultRegistered,
+};
+
+// Mock addresses for testing
+fn mock_usdc() -> Address {
+    Address::from([1u8; 20])
+}
+
+fn mock_aave_pool() -> Address {
+    Address::from([2u8; 20])
+}
+
+fn mock_a_token() -> Address {
+    Address::from([3u8; 20])
+}
+
+fn mock_c_token() -> Address {
+    Address::from([4u8; 20])
+}
+
+fn mock_user() -> Address {
+    Address::from([5u8; 20])
+}
+
+fn mock_admin() -> Address {
+    Address::from([6u8; 20])
+}
+
+// ===== ERC-20 / MOCK TOKEN TESTS =====
+
+fn deploy_stablecoin(vm: &TestVM) -> MockStablecoin {
+    vm.set_sender(mock_admin());
+    let mut token = MockStablecoin::from(vm);
+    token.constructor(mock_admin()).unwrap();
+    token
+}
+
+#[test]
+fn test_mock_token_metadata() {
+    let vm = TestVM::default();
+    assert_eq!(Erc20::<MockStablecoinParams>::from(&vm).decimals().ok(), Some(6));
+    assert_eq!(Erc20::<MockWethParams>::from(&vm).decimals().ok(), Some(18));
+    assert_eq!(Erc20::<MockWethParams>::from(&vm).symbol().ok().as_deref(), Some("mWETH"));
+}
+
+#[test]
+fn test_mock_token_mint_is_owner_gated() {
+    let vm = TestVM::default();
+    let mut token = deploy_stablecoin(&vm);
+
+    token.mint(mock_user(), U256::from(1_000_000)).unwrap();
+    assert_eq!(token.erc20.balance_of(mock_user()).unwrap(), U256::from(1_000_000));
+    assert_eq!(token.erc20.total_supply(), U256::from(1_000_000));
+
+    vm.set_sender(mock_user());
+    assert!(token.mint(mock_user(), U256::from(1)).is_err());
+    assert_eq!(
+        MockStablecoin::from(&TestVM::default()).constructor(Address::ZERO),
+        Err(b"Invalid owner".to_vec())
+    );
+}
+
+#[test]
+fn test_mock_fee_token_charges_fee_on_large_transfers() {
+    let vm = TestVM::default();
+    vm.set_sender(mock_admin());
+    let mut token = MockFeeToken::from(&vm);
+    token.constructor(mock_admin(), U256::from(100)).unwrap();
+    token.mint(mock_admin(), U256::MAX).unwrap();
+
+    // value * fee_bps would overflow a plain multiplication
+    token.transfer(mock_user(), U256::MAX).unwrap();
+    let fee = U256::MAX / U256::from(100);
+    assert_eq!(token.erc20.balance_of(mock_user()).unwrap(), U256::MAX - fee);
+    assert_eq!(token.erc20.total_supply(), U256::MAX - fee);
+}
+
+#[test]
+fn test_erc20_transfer_emits_event() {
+    let vm = TestVM::default();
+    let mut token = deploy_stablecoin(&vm);
+    token.mint(mock_user(), U256::from(500)).unwrap();
+
+    vm.set_sender(mock_user());
+    assert!(token.erc20.transfer(mock_admin(), U256::from(200)).unwrap());
+    assert_eq!(token.erc20.balance_of(mock_user()).unwrap(), U256::from(300));
+    assert_eq!(token.erc20.balance_of(mock_admin()).unwrap(), U256::from(200));
+    assert!(token.erc20.transfer(mock_admin(), U256::from(301)).is_err());
+
+    let logs = vm.get_emitted_logs();
+    let (topics, data) = logs.last().unwrap();
+    assert_eq!(topics[0], Transfer::SIGNATURE_HASH);
+    assert_eq!(topics[1], mock_user().into_word());
+    assert_eq!(topics[2], mock_admin().into_word());
+    assert_eq!(U256::from_be_slice(data), U256::from(200));
+}
+
+#[test]
+fn test_erc20_mint_and_burn_emit_zero_address_transfers() {
+    let vm = TestVM::default();
+    let mut token = deploy_stablecoin(&vm);
+    token.mint(mock_user(), U256::from(50)).unwrap();
+
+    vm.set_sender(mock_user());
+    token.burn(U256::from(20)).unwrap();
+    assert_eq!(token.erc20.total_supply(), U256::from(30));
+    assert!(token.burn(U256::from(31)).is_err());
+
+    let logs = vm.get_emitted_logs();
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[0].0[1], Address::ZERO.into_word());
+    assert_eq!(logs[1].0[2], Address::ZERO.into_word());
+}
+
+#[test]
+fn test_erc20_approve_and_transfer_from() {
+    let vm = TestVM::default();
+    let mut token = deploy_stablecoin(&vm);
+    token.mint(mock_user(), U256::from(100)).unwrap();
+
+    vm.set_sender(mock_user());
+    token.erc20.approve(mock_admin(), U256::from(60)).unwrap();
+    let logs = vm.get_emitted_logs();
+    assert_eq!(logs.last().unwrap().0[0], Approval::SIGNATURE_HASH);
+
+    vm.set_sender(mock_admin());
+    token.erc20.transfer_from(mock_user(), mock_admin(), U256::from(40)).unwrap();
+    assert_eq!(token.erc20.allowance(mock_user(), mock_admin()).unwrap(), U256::from(20));
+    assert!(token.erc20.transfer_from(mock_user(), mock_admin(), U256::from(21)).is_err());
+}
+
+// ===== USER VAULT SHARE TOKEN TESTS =====
+
+// The mock tokens only become top-level contracts under their features;
+// SimHost needs them routable in tests too
+#[cfg(not(feature = "mock-stablecoin"))]
+unsafe impl TopLevelStorage for MockStablecoin {}
+#[cfg(not(feature = "mock-fee-token"))]
+unsafe impl TopLevelStorage for MockFeeToken {}
+#[cfg(not(feature = "mock-rebasing-token"))]
+unsafe impl TopLevelStorage for MockRebasingToken {}
+unsafe impl TopLevelStorage for UserVault {}
+
+sol! {
+    interface IMockToken {
+        function mint(address to, uint256 value) external;
+        function burn(uint256 value) external;
+    }
+
+    interface IFactoryAdmin {
+        function setAaveAddress(address aave_address) external;
+        function setCompoundAddress(address compound_address) external;
+    }
+}
+
+fn mock_factory() -> Address {
+    Address::from([7u8; 20])
+}
+
+/// Balance every test account starts with
+fn initial_balance() -> U256 {
+    U256::from(10).pow(U256::from(60))
+}
+
+/// Deploys a mock stablecoin owned by the admin at `address`
+fn register_stablecoin(vm: &SimHost, address: Address) {
+    let token_vm = TestVM::default();
+    let token = Rc::new(RefCell::new(deploy_stablecoin(&token_vm)));
+    vm.register_contract(address, token, token_vm);
+}
+
+fn mint_token(vm: &SimHost, token: Address, to: Address, value: U256) {
+    let data = IMockToken::mintCall { to, value }.abi_encode();
+    vm.call_as(mock_admin(), token, &data).unwrap();
+}
+
+fn token_balance(vm: &SimHost, token: Address, account: Address) -> U256 {
+    let returned = vm
+        .call_as(account, token, &IERC20::balanceOfCall { account }.abi_encode())
+        .unwrap();
+    U256::from_be_slice(&returned)
+}
+
+/// Sends `amount` of the asset straight to the vault, like yield or a donation
+fn donate(vm: &SimHost, amount: U256) {
+    mint_token(vm, mock_usdc(), vm.contract_address(), amount);
+}
+
+/// Deploys the vault asset at `mock_usdc()`, funds the test accounts and
+/// approves the vault for all of them
+fn deploy_usdc(vm: &SimHost) {
+    register_stablecoin(vm, mock_usdc());
+    fund_test_accounts(vm);
+}
+
+/// Funds the test accounts with the asset at `mock_usdc()` and approves the
+/// vault for all of them
+fn fund_test_accounts(vm: &SimHost) {
+    for account in [mock_user(), mock_admin(), mock_attacker()] {
+        mint_token(vm, mock_usdc(), account, initial_balance());
+        let approve = IERC20::approveCall {
+            spender: vm.contract_address(),
+            value: U256::MAX,
+        }
+        .abi_encode();
+        vm.call_as(account, mock_usdc(), &approve).unwrap();
+    }
+}
+
+/// Deploys a factory, initialized by the admin, at `mock_factory()`
+fn deploy_factory(vm: &SimHost) {
+    let factory_vm = TestVM::default();
+    factory_vm.set_sender(mock_admin());
+    let factory = Rc::new(RefCell::new(VaultFactory::from(&factory_vm)));
+    factory.borrow_mut().init().unwrap();
+    vm.register_contract(mock_factory(), factory, factory_vm);
+}
+
+fn init_vault(vm: &SimHost) -> UserVault {
+    deploy_usdc(vm);
+    deploy_factory(vm);
+    vm.set_sender(mock_admin());
+    let mut vault = UserVault::from(vm);
+    initialize_vault(&mut vault, mock_usdc(), 0);
+    vault
+}
+
+fn initialize_vault(vault: &mut UserVault, asset: Address, asset_mode: u8) {
+    vault
+        .initialize(
+            mock_admin(),
+            asset,
+            mock_factory(),
+            "alice".into(),
+            "saver".into(),
+            "Alice Vault".into(),
+            "aVS".into(),
+            6,
+            U256::from(20),
+            U256::from(30),
+            asset_mode,
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_vault_shares_use_erc20_component() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+
+    vault.mint(U256::from(1_000), mock_user()).unwrap();
+    assert_eq!(vault.erc20.balance_of(mock_user()).unwrap(), U256::from(1_000));
+    assert_eq!(vault.erc20.total_supply(), U256::from(1_000));
+
+    vm.set_sender(mock_user());
+    vault.erc20.transfer(mock_admin(), U256::from(400)).unwrap();
+    assert_eq!(vault.erc20.balance_of(mock_admin()).unwrap(), U256::from(400));
+
+    let logs = vm.get_emitted_logs();
+    assert!(logs.iter().any(|(topics, _)| topics[0] == Transfer::SIGNATURE_HASH
+        && topics[1] == Address::ZERO.into_word()));
+}
+
+#[test]
+fn test_vault_redeem_burns_shares() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vault.mint(U256::from(1_000), mock_user()).unwrap();
+
+    vm.set_sender(mock_user());
+    vault.redeem(U256::from(250), mock_user(), mock_user()).unwrap();
+    assert_eq!(vault.erc20.balance_of(mock_user()).unwrap(), U256::from(750));
+    assert_eq!(vault.erc20.total_supply(), U256::from(750));
+    assert!(vault.redeem(U256::from(751), mock_user(), mock_user()).is_err());
+}
+
+#[test]
+fn test_vault_share_metadata() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    assert_eq!(vault.erc20.name().ok().as_deref(), Some("Vault Shares"));
+    assert_eq!(vault.decimals().unwrap(), 6);
+
+    vault.set_vault_decimals(18).unwrap();
+    assert_eq!(vault.decimals().unwrap(), 18);
+}
+
+// ===== ERC-4626 ENTRY POINT TESTS =====
+
+#[test]
+fn test_deposit_mints_to_receiver_and_emits_standard_event() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_sender(mock_user());
+
+    assert_eq!(vault.deposit(U256::from(1_000), mock_admin()).unwrap(), U256::from(1_000));
+    assert_eq!(vault.erc20.balance_of(mock_admin()).unwrap(), U256::from(1_000));
+    assert_eq!(vault.erc20.balance_of(mock_user()).unwrap(), U256::ZERO);
+
+    let logs = vm.get_emitted_logs();
+    let (topics, data) = logs.last().unwrap();
+    assert_eq!(topics[0], Deposit::SIGNATURE_HASH);
+    assert_eq!(topics[1], mock_user().into_word());
+    assert_eq!(topics[2], mock_admin().into_word());
+    assert_eq!(data.len(), 64);
+
+    assert!(vault.deposit(U256::from(1), Address::ZERO).is_err());
+}
+
+#[test]
+fn test_mint_pulls_assets_from_caller() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_sender(mock_user());
+
+    let reason = b"no funds".to_vec();
+    vm.mock_call(mock_usdc(), transfer_from_call(&vm, mock_user(), 1_000), Err(reason.clone()));
+    assert_eq!(vault.mint(U256::from(1_000), mock_user()), Err(reason));
+    assert_eq!(vault.erc20.total_supply(), U256::ZERO);
+
+    assert_eq!(vault.mint(U256::from(500), mock_user()).unwrap(), U256::from(500));
+    assert_eq!(vault.total_assets().unwrap(), U256::from(500));
+}
+
+#[test]
+fn test_redeem_by_third_party_spends_allowance() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+
+    // Without an allowance the admin cannot touch the user's shares
+    vm.set_sender(mock_admin());
+    assert!(vault.redeem(U256::from(100), mock_admin(), mock_user()).is_err());
+    assert!(vault.withdraw(U256::from(100), mock_admin(), mock_user()).is_err());
+
+    vm.set_sender(mock_user());
+    vault.erc20.approve(mock_admin(), U256::from(300)).unwrap();
+
+    vm.set_sender(mock_admin());
+    vault.redeem(U256::from(200), mock_admin(), mock_user()).unwrap();
+    assert_eq!(vault.erc20.allowance(mock_user(), mock_admin()).unwrap(), U256::from(100));
+    assert_eq!(vault.erc20.balance_of(mock_user()).unwrap(), U256::from(800));
+
+    let logs = vm.get_emitted_logs();
+    let (topics, _) = logs.last().unwrap();
+    assert_eq!(topics[0], Withdraw::SIGNATURE_HASH);
+    assert_eq!(topics[1], mock_admin().into_word());
+    assert_eq!(topics[2], mock_admin().into_word());
+    assert_eq!(topics[3], mock_user().into_word());
+
+    assert!(vault.redeem(U256::from(101), mock_admin(), mock_user()).is_err());
+}
+
+#[test]
+fn test_withdraw_pays_receiver() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+
+    assert_eq!(
+        vault.withdraw(U256::from(300), mock_admin(), mock_user()).unwrap(),
+        U256::from(300)
+    );
+    assert_eq!(token_balance(&vm, mock_usdc(), mock_admin()), initial_balance() + U256::from(300));
+
+    // A failing payout to the receiver fails the withdrawal
+    let transfer = IERC20::transferCall {
+        to: mock_admin(),
+        value: U256::from(400),
+    }
+    .abi_encode();
+    let reason = b"blocked".to_vec();
+    vm.mock_call(mock_usdc(), transfer, Err(reason.clone()));
+    assert_eq!(vault.withdraw(U256::from(400), mock_admin(), mock_user()), Err(reason));
+}
+
+/// Deposits 1,000 assets for the user, then simulates yield so that
+/// 1 share is worth 1.5 assets
+fn vault_with_yield(vm: &SimHost) -> UserVault {
+    let mut vault = init_vault(vm);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+    donate(vm, U256::from(500));
+    vault
+}
+
+#[test]
+fn test_previews_round_in_vaults_favour() {
+    let vm = SimHost::new();
+    let vault = vault_with_yield(&vm);
+
+    // 100 assets = 66.67 shares
+    assert_eq!(vault.preview_deposit(U256::from(100)).unwrap(), U256::from(66));
+    assert_eq!(vault.preview_withdraw(U256::from(100)).unwrap(), U256::from(67));
+    // 1 share = 1.5 assets
+    assert_eq!(vault.preview_mint(U256::from(1)).unwrap(), U256::from(2));
+    assert_eq!(vault.preview_redeem(U256::from(1)).unwrap(), U256::from(1));
+    // Exact conversions are not rounded
+    assert_eq!(vault.preview_mint(U256::from(66)).unwrap(), U256::from(99));
+    assert_eq!(vault.convert_to_shares(U256::from(150)).unwrap(), U256::from(100));
+}
+
+#[test]
+fn test_entry_points_match_previews() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_yield(&vm);
+
+    let shares = vault.preview_withdraw(U256::from(100)).unwrap();
+    assert_eq!(vault.withdraw(U256::from(100), mock_user(), mock_user()).unwrap(), shares);
+
+    let assets = vault.preview_mint(U256::from(1)).unwrap();
+    assert_eq!(vault.mint(U256::from(1), mock_user()).unwrap(), assets);
+
+    let assets = vault.preview_redeem(U256::from(1)).unwrap();
+    assert_eq!(vault.redeem(U256::from(1), mock_user(), mock_user()).unwrap(), assets);
+
+    let shares = vault.preview_deposit(U256::from(100)).unwrap();
+    assert_eq!(vault.deposit(U256::from(100), mock_user()).unwrap(), shares);
+}
+
+#[test]
+fn test_round_trips_never_profit() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_yield(&vm);
+
+    for amount in [1u64, 2, 3, 7, 99] {
+        let paid = vault.mint(U256::from(amount), mock_user()).unwrap();
+        let received = vault.redeem(U256::from(amount), mock_user(), mock_user()).unwrap();
+        assert!(received <= paid);
+
+        let burned = vault.withdraw(U256::from(amount), mock_user(), mock_user()).unwrap();
+        if vault.preview_deposit(U256::from(amount)).unwrap() == U256::ZERO {
+            // Deposits worth less than one share are rejected
+            assert!(vault.deposit(U256::from(amount), mock_user()).is_err());
+            continue;
+        }
+        let minted = vault.deposit(U256::from(amount), mock_user()).unwrap();
+        assert!(minted <= burned);
+    }
+}
+
+#[test]
+fn test_conversions_survive_large_18_decimal_supplies() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_sender(mock_user());
+
+    // 10^30 tokens at 18 decimals: the product of two such amounts overflows U256
+    let whale = U256::from(10).pow(U256::from(48));
+    vault.deposit(whale, mock_user()).unwrap();
+    donate(&vm, whale * U256::from(2));
+
+    assert_eq!(vault.convert_to_shares(whale).unwrap(), whale / U256::from(3));
+    // The virtual asset and share shave a few wei off the exact 3x
+    let assets = vault.convert_to_assets(whale).unwrap();
+    assert!(assets <= whale * U256::from(3) && whale * U256::from(3) - assets < U256::from(3));
+    assert_eq!(
+        vault.preview_withdraw(U256::from(1)).unwrap(),
+        U256::from(1),
+        "one wei rounds up to one share"
+    );
+    vault.withdraw(whale, mock_user(), mock_user()).unwrap();
+}
+
+// ===== INFLATION ATTACK TESTS =====
+
+fn mock_attacker() -> Address {
+    Address::from([8u8; 20])
+}
+
+/// Front-runs the victim: deposits 1 wei, then donates `donation`
+fn attacker_inflates(vm: &SimHost, vault: &mut UserVault, donation: u64) {
+    vm.set_sender(mock_attacker());
+    vault.deposit(U256::from(1), mock_attacker()).unwrap();
+    let transfer = IERC20::transferCall {
+        to: vm.contract_address(),
+        value: U256::from(donation),
+    }
+    .abi_encode();
+    vm.call_as(mock_attacker(), mock_usdc(), &transfer).unwrap();
+}
+
+#[test]
+fn test_virtual_offset_protects_victim_deposit() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vault.set_inflation_protection(6, U256::ZERO).unwrap();
+    assert_eq!(vault.decimals().unwrap(), 12);
+
+    attacker_inflates(&vm, &mut vault, 10_000);
+
+    vm.set_sender(mock_user());
+    let shares = vault.deposit(U256::from(10_000), mock_user()).unwrap();
+    let recovered = vault.preview_redeem(shares).unwrap();
+    assert!(recovered >= U256::from(9_990), "victim recovered {recovered}");
+
+    // The attacker gets back about half of what it put in
+    let attacker_shares = vault.erc20.balance_of(mock_attacker()).unwrap();
+    assert!(vault.preview_redeem(attacker_shares).unwrap() < U256::from(10_001));
+}
+
+#[test]
+fn test_attack_is_unprofitable_without_offset() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    attacker_inflates(&vm, &mut vault, 10_000);
+
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(10_000), mock_user()).unwrap();
+
+    // The single virtual share keeps part of the donation, so the attacker loses
+    let attacker_shares = vault.erc20.balance_of(mock_attacker()).unwrap();
+    assert!(vault.preview_redeem(attacker_shares).unwrap() < U256::from(10_001));
+
+    // A deposit that would round to zero shares reverts instead of being swallowed
+    donate(&vm, U256::from(100_000));
+    assert_eq!(vault.preview_deposit(U256::from(10)).unwrap(), U256::ZERO);
+    assert!(vault.deposit(U256::from(10), mock_user()).is_err());
+}
+
+#[test]
+fn test_min_initial_deposit_burns_dead_shares() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vault.set_inflation_protection(0, U256::from(1_000)).unwrap();
+
+    vm.set_sender(mock_user());
+    assert!(vault.deposit(U256::from(999), mock_user()).is_err());
+    assert_eq!(vault.preview_deposit(U256::from(5_000)).unwrap(), U256::from(4_000));
+    assert_eq!(vault.deposit(U256::from(5_000), mock_user()).unwrap(), U256::from(4_000));
+
+    let dead = Address::from_word(U256::from(0xdead).into());
+    assert_eq!(vault.erc20.balance_of(dead).unwrap(), U256::from(1_000));
+    assert_eq!(vault.erc20.total_supply(), U256::from(5_000));
+
+    // Only the first deposit pays
+    assert_eq!(vault.deposit(U256::from(100), mock_user()).unwrap(), U256::from(100));
+}
+
+#[test]
+fn test_first_mint_pays_for_dead_shares() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vault.set_inflation_protection(0, U256::from(1_000)).unwrap();
+
+    vm.set_sender(mock_user());
+    assert_eq!(vault.preview_mint(U256::from(500)).unwrap(), U256::from(1_500));
+    assert_eq!(vault.mint(U256::from(500), mock_user()).unwrap(), U256::from(1_500));
+    assert_eq!(vault.erc20.balance_of(mock_user()).unwrap(), U256::from(500));
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_500));
+}
+
+#[test]
+fn test_inflation_protection_is_owner_only_and_fixed_after_first_deposit() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    assert!(vault.set_inflation_protection(19, U256::ZERO).is_err());
+
+    vm.set_sender(mock_user());
+    assert!(vault.set_inflation_protection(3, U256::ZERO).is_err());
+    vault.deposit(U256::from(10), mock_user()).unwrap();
+
+    vm.set_sender(mock_admin());
+    assert!(vault.set_inflation_protection(3, U256::ZERO).is_err());
+    assert_eq!(vault.decimals_offset().unwrap(), 0);
+}
+
+// ===== DEPOSIT CAP AND MAX_* TESTS =====
+
+#[test]
+fn test_max_views_without_caps() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    assert_eq!(vault.max_deposit(mock_user()).unwrap(), U256::MAX);
+    assert_eq!(vault.max_mint(mock_user()).unwrap(), U256::MAX);
+    assert_eq!(vault.max_withdraw(mock_user()).unwrap(), U256::ZERO);
+
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+    assert_eq!(vault.max_withdraw(mock_user()).unwrap(), U256::from(1_000));
+    assert_eq!(vault.max_redeem(mock_user()).unwrap(), U256::from(1_000));
+}
+
+#[test]
+fn test_max_views_are_zero_when_paused() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+
+    vm.set_sender(mock_admin());
+    vault.pause().unwrap();
+    assert_eq!(vault.max_deposit(mock_user()).unwrap(), U256::ZERO);
+    assert_eq!(vault.max_mint(mock_user()).unwrap(), U256::ZERO);
+    assert_eq!(vault.max_withdraw(mock_user()).unwrap(), U256::ZERO);
+    assert_eq!(vault.max_redeem(mock_user()).unwrap(), U256::ZERO);
+}
+
+#[test]
+fn test_total_and_per_depositor_caps_are_enforced() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+
+    vm.set_sender(mock_user());
+    assert!(vault.set_deposit_caps(U256::from(1_000), U256::ZERO).is_err());
+
+    vm.set_sender(mock_admin());
+    vault.set_deposit_caps(U256::from(1_000), U256::from(600)).unwrap();
+    assert_eq!(vault.deposit_cap().unwrap(), U256::from(1_000));
+
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(400), mock_user()).unwrap();
+    assert_eq!(vault.max_deposit(mock_user()).unwrap(), U256::from(200));
+    assert_eq!(vault.max_mint(mock_user()).unwrap(), U256::from(200));
+    assert_eq!(
+        vault.deposit(U256::from(201), mock_user()),
+        Err(ERC4626ExceededMaxDeposit {
+            receiver: mock_user(),
+            assets: U256::from(201),
+            max: U256::from(200),
+        }
+        .abi_encode())
+    );
+    assert!(vault.mint(U256::from(201), mock_user()).is_err());
+
+    // Another receiver is limited by the remaining total cap
+    vault.deposit(U256::from(500), mock_admin()).unwrap();
+    assert_eq!(vault.max_deposit(mock_admin()).unwrap(), U256::from(100));
+    assert_eq!(vault.max_deposit(mock_user()).unwrap(), U256::from(100));
+    assert!(vault.mint(U256::from(101), mock_admin()).is_err());
+    vault.mint(U256::from(100), mock_admin()).unwrap();
+    assert_eq!(vault.max_deposit(mock_user()).unwrap(), U256::ZERO);
+}
+
+#[test]
+fn test_max_withdraw_is_limited_by_idle_liquidity() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+
+    deploy_aave(&vm, &mut vault);
+    vm.set_sender(mock_admin());
+    vault.deploy_to_aave(U256::from(700)).unwrap();
+
+    assert_eq!(vault.max_withdraw(mock_user()).unwrap(), U256::from(300));
+    assert_eq!(vault.max_redeem(mock_user()).unwrap(), U256::from(300));
+
+    vm.set_sender(mock_user());
+    assert_eq!(
+        vault.withdraw(U256::from(301), mock_user(), mock_user()),
+        Err(ERC4626ExceededMaxWithdraw {
+            owner: mock_user(),
+            assets: U256::from(301),
+            max: U256::from(300),
+        }
+        .abi_encode())
+    );
+    assert_eq!(
+        vault.redeem(U256::from(301), mock_user(), mock_user()),
+        Err(ERC4626ExceededMaxRedeem {
+            owner: mock_user(),
+            shares: U256::from(301),
+            max: U256::from(300),
+        }
+        .abi_encode())
+    );
+    vault.withdraw(U256::from(300), mock_user(), mock_user()).unwrap();
+}
+
+// ===== SLIPPAGE AND DEADLINE TESTS =====
+
+const DEADLINE: u64 = 1_700_000_000;
+
+#[test]
+fn test_bounded_entry_points_enforce_deadline() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_yield(&vm);
+    let deadline = U256::from(DEADLINE);
+
+    vm.set_block_timestamp(DEADLINE);
+    vault.deposit_bounded(U256::from(100), mock_user(), U256::ZERO, deadline).unwrap();
+
+    vm.set_block_timestamp(DEADLINE + 1);
+    let expired = DeadlineExpired {
+        deadline,
+        timestamp: U256::from(DEADLINE + 1),
+    }
+    .abi_encode();
+    assert_eq!(
+        vault.deposit_bounded(U256::from(100), mock_user(), U256::ZERO, deadline),
+        Err(expired.clone())
+    );
+    assert_eq!(
+        vault.mint_bounded(U256::from(1), mock_user(), U256::MAX, deadline),
+        Err(expired.clone())
+    );
+    assert_eq!(
+        vault.withdraw_bounded(U256::from(1), mock_user(), mock_user(), U256::MAX, deadline),
+        Err(expired.clone())
+    );
+    assert_eq!(
+        vault.redeem_bounded(U256::from(1), mock_user(), mock_user(), U256::ZERO, deadline),
+        Err(expired)
+    );
+    assert!(!vault.reentrancy_guard.entered.get());
+}
+
+#[test]
+fn test_bounded_entry_points_enforce_slippage() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_yield(&vm);
+    let deadline = U256::from(u64::MAX);
+    let supply = vault.erc20.total_supply();
+
+    // 100 assets buy 66 shares, 1 share costs 2 assets, 100 assets burn 67 shares
+    // and 1 share pays 1 asset
+    assert_eq!(
+        vault.deposit_bounded(U256::from(100), mock_user(), U256::from(67), deadline),
+        Err(ERC5143InsufficientShares {
+            shares: U256::from(66),
+            min_shares: U256::from(67),
+        }
+        .abi_encode())
+    );
+    assert_eq!(
+        vault.mint_bounded(U256::from(1), mock_user(), U256::from(1), deadline),
+        Err(ERC5143ExcessiveAssets {
+            assets: U256::from(2),
+            max_assets: U256::from(1),
+        }
+        .abi_encode())
+    );
+    assert_eq!(
+        vault.withdraw_bounded(U256::from(100), mock_user(), mock_user(), U256::from(66), deadline),
+        Err(ERC5143ExcessiveShares {
+            shares: U256::from(67),
+            max_shares: U256::from(66),
+        }
+        .abi_encode())
+    );
+    assert_eq!(
+        vault.redeem_bounded(U256::from(1), mock_user(), mock_user(), U256::from(2), deadline),
+        Err(ERC5143InsufficientAssets {
+            assets: U256::from(1),
+            min_assets: U256::from(2),
+        }
+        .abi_encode())
+    );
+    // Violations are caught before any shares move
+    assert_eq!(vault.erc20.total_supply(), supply);
+
+    // Bounds that are met behave like the plain entry points
+    assert_eq!(
+        vault.deposit_bounded(U256::from(100), mock_user(), U256::from(66), deadline).unwrap(),
+        U256::from(66)
+    );
+    assert_eq!(
+        vault.redeem_bounded(U256::from(66), mock_user(), mock_user(), U256::from(99), deadline).unwrap(),
+        U256::from(99)
+    );
+}
+
+// ===== FEE-ON-TRANSFER AND REBASING ASSET TESTS =====
+
+fn fee_token_address() -> Address {
+    Address::from([9u8; 20])
+}
+
+fn rebasing_token_address() -> Address {
+    Address::from([10u8; 20])
+}
+
+/// Deploys a fee token charging `fee_bps`, funds the user with 10,000 and
+/// approves the vault
+fn deploy_fee_token(host: &SimHost, fee_bps: u64) -> Rc<RefCell<MockFeeToken>> {
+    let token_vm = TestVM::default();
+    let token = Rc::new(RefCell::new(MockFeeToken::from(&token_vm)));
+    {
+        let mut token = token.borrow_mut();
+        token_vm.set_sender(mock_admin());
+        token.constructor(mock_admin(), U256::from(fee_bps)).unwrap();
+        token.mint(mock_user(), U256::from(10_000)).unwrap();
+        token_vm.set_sender(mock_user());
+        token.erc20.approve(host.vm.contract_address(), U256::MAX).unwrap();
+    }
+    host.register_contract(fee_token_address(), token.clone(), token_vm);
+    token
+}
+
+/// Deploys a rebasing token at index 1.0, funds the user with 10,000 and
+/// approves the vault
+fn deploy_rebasing_token(host: &SimHost) -> (Rc<RefCell<MockRebasingToken>>, TestVM) {
+    let token_vm = TestVM::default();
+    let token = Rc::new(RefCell::new(MockRebasingToken::from(&token_vm)));
+    {
+        let mut token = token.borrow_mut();
+        token_vm.set_sender(mock_admin());
+        token.constructor(mock_admin()).unwrap();
+        token.mint(mock_user(), U256::from(10_000)).unwrap();
+        token_vm.set_sender(mock_user());
+        token.erc20.approve(host.vm.contract_address(), U256::MAX).unwrap();
+    }
+    host.register_contract(rebasing_token_address(), token.clone(), token_vm.clone());
+    (token, token_vm)
+}
+
+fn init_vault_on(host: &SimHost, asset: Address, asset_mode: u8) -> UserVault {
+    deploy_factory(host);
+    host.vm.set_sender(mock_admin());
+    let mut vault = UserVault::from(host);
+    initialize_vault(&mut vault, asset, asset_mode);
+    vault
+}
+
+#[test]
+fn test_fee_on_transfer_deposit_credits_received_amount() {
+    let host = SimHost::new();
+    let token = deploy_fee_token(&host, 100); // 1%
+    let mut vault = init_vault_on(&host, fee_token_address(), 1);
+
+    host.vm.set_sender(mock_user());
+    assert_eq!(vault.deposit(U256::from(1_000), mock_user()).unwrap(), U256::from(990));
+    assert_eq!(vault.total_assets().unwrap(), U256::from(990));
+    let vault_balance = token.borrow().erc20.balance_of(host.vm.contract_address()).unwrap();
+    assert_eq!(vault_balance, U256::from(990));
+
+    let logs = host.vm.get_emitted_logs();
+    let (topics, data) = logs.last().unwrap();
+    assert_eq!(topics[0], Deposit::SIGNATURE_HASH);
+    assert_eq!(U256::from_be_slice(&data[..32]), U256::from(990));
+}
+
+#[test]
+fn test_standard_mode_over_credits_fee_tokens() {
+    let host = SimHost::new();
+    let token = deploy_fee_token(&host, 100);
+    let mut vault = init_vault_on(&host, fee_token_address(), 0);
+
+    // Shares are minted for the requested amount, diluting other holders
+    host.vm.set_sender(mock_user());
+    assert_eq!(vault.deposit(U256::from(1_000), mock_user()).unwrap(), U256::from(1_000));
+    let vault_balance = token.borrow().erc20.balance_of(host.vm.contract_address()).unwrap();
+    assert_eq!(vault.total_assets().unwrap(), U256::from(990));
+    assert_eq!(vault_balance, U256::from(990));
+}
+
+#[test]
+fn test_fee_on_transfer_mode_disables_mint() {
+    let host = SimHost::new();
+    deploy_fee_token(&host, 100);
+    let mut vault = init_vault_on(&host, fee_token_address(), 1);
+
+    host.vm.set_sender(mock_user());
+    assert_eq!(vault.max_mint(mock_user()).unwrap(), U256::ZERO);
+    assert_eq!(
+        vault.mint(U256::from(1_000), mock_user()),
+        Err(ERC4626ExceededMaxMint {
+            receiver: mock_user(),
+            shares: U256::from(1_000),
+            max: U256::ZERO,
+        }
+        .abi_encode())
+    );
+    // Deposits still work, credited by what arrives
+    assert_eq!(vault.deposit(U256::from(1_000), mock_user()).unwrap(), U256::from(990));
+}
+
+#[test]
+fn test_invalid_asset_mode_is_rejected() {
+    let vm = TestVM::default();
+    vm.set_sender(mock_admin());
+    let mut vault = UserVault::from(&vm);
+    let result = vault.initialize(
+        mock_admin(),
+        mock_usdc(),
+        mock_factory(),
+        "alice".into(),
+        "saver".into(),
+        "Alice Vault".into(),
+        "aVS".into(),
+        6,
+        U256::from(20),
+        U256::from(30),
+        2,
+    );
+    assert_eq!(result, Err(b"Invalid asset mode".to_vec()));
+}
+
+#[test]
+fn test_rebasing_asset_reads_live_balance() {
+    let host = SimHost::new();
+    let (token, token_vm) = deploy_rebasing_token(&host);
+    let mut vault = init_vault_on(&host, rebasing_token_address(), 1);
+
+    host.vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+
+    // +10% rebase reaches the share price without any transfer
+    token_vm.set_sender(mock_admin());
+    token.borrow_mut().rebase(U256::from(1_100_000_000_000_000_000u64)).unwrap();
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_100));
+
+    let paid = vault.redeem(U256::from(1_000), mock_user(), mock_user()).unwrap();
+    assert_eq!(paid, U256::from(1_099));
+    // The token stores units, so the user's balance rounds down by one wei
+    assert_eq!(token.borrow().balance_of(mock_user()).unwrap(), U256::from(9_900 + 1_098));
+}
+
+// ===== ACCESS CONTROL COMPONENT TESTS =====
+
+#[test]
+fn test_ownable_two_step_transfer() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    assert_eq!(vault.ownable.owner(), mock_admin());
+
+    vault.ownable.transfer_ownership(mock_user()).unwrap();
+    assert_eq!(vault.ownable.owner(), mock_admin());
+    assert_eq!(vault.ownable.pending_owner(), mock_user());
+
+    // Only the nominee can accept
+    assert!(matches!(
+        vault.ownable.accept_ownership(),
+        Err(OwnableError::UnauthorizedAccount(_))
+    ));
+
+    vm.set_sender(mock_user());
+    vault.ownable.accept_ownership().unwrap();
+    assert_eq!(vault.ownable.owner(), mock_user());
+    assert_eq!(vault.ownable.pending_owner(), Address::ZERO);
+    let logs = vm.get_emitted_logs();
+    assert_eq!(logs.last().unwrap().0[0], OwnershipTransferred::SIGNATURE_HASH);
+
+    // The previous owner lost access
+    vm.set_sender(mock_admin());
+    assert!(vault.set_vault_decimals(8).is_err());
+    assert!(matches!(
+        vault.ownable.transfer_ownership(mock_admin()),
+        Err(OwnableError::UnauthorizedAccount(_))
+    ));
+}
+
+#[test]
+fn test_ownable_rejects_zero_owner_and_renounces() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    assert!(matches!(
+        vault.ownable.transfer_ownership(Address::ZERO),
+        Err(OwnableError::InvalidOwner(_))
+    ));
+
+    vault.ownable.renounce_ownership().unwrap();
+    assert_eq!(vault.ownable.owner(), Address::ZERO);
+    assert!(vault.pause().is_err());
+}
+
+#[test]
+fn test_vault_pause_blocks_entry_points() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+
+    vm.set_sender(mock_user());
+    assert!(vault.pause().is_err());
+
+    vm.set_sender(mock_admin());
+    vault.pause().unwrap();
+    assert!(vault.pausable.paused());
+    let logs = vm.get_emitted_logs();
+    assert_eq!(logs.last().unwrap().0[0], Paused::SIGNATURE_HASH);
+    assert!(matches!(
+        vault.pausable.pause_impl(),
+        Err(PausableError::EnforcedPause(_))
+    ));
+
+    assert!(vault.mint(U256::from(1), mock_user()).is_err());
+    assert!(vault.redeem(U256::from(1), mock_user(), mock_user()).is_err());
+
+    vault.unpause().unwrap();
+    assert!(!vault.pausable.paused());
+    vault.mint(U256::from(1), mock_user()).unwrap();
+}
+
+#[test]
+fn test_factory_ownership_and_pause() {
+    let vm = TestVM::default();
+    vm.set_sender(mock_admin());
+    let mut factory = VaultFactory::from(&vm);
+    factory.init().unwrap();
+    assert_eq!(factory.ownable.owner(), mock_admin());
+    assert_eq!(factory.get_deployer_admin().unwrap(), mock_admin());
+    assert!(factory.check_is_admin(mock_admin()).unwrap());
+
+    // init can only run once
+    vm.set_sender(mock_user());
+    assert!(factory.init().is_err());
+    assert!(factory.pause().is_err());
+
+    vm.set_sender(mock_admin());
+    factory.pause().unwrap();
+    vm.set_sender(mock_user());
+    assert!(factory.register_user("bob".into(), "hodler".into()).is_err());
+
+    vm.set_sender(mock_admin());
+    factory.unpause().unwrap();
+    vm.set_sender(mock_user());
+    factory.register_user("bob".into(), "hodler".into()).unwrap();
+    factory.create_vault().unwrap();
+}
+
+#[test]
+fn test_factory_admin_rights_follow_ownership() {
+    let vm = TestVM::default();
+    vm.set_sender(mock_admin());
+    let mut factory = VaultFactory::from(&vm);
+    factory.init().unwrap();
+    factory.add_admin(mock_attacker()).unwrap();
+    assert_eq!(factory.get_admin_count().unwrap(), U256::from(2));
+
+    factory.ownable.transfer_ownership(mock_user()).unwrap();
+    vm.set_sender(mock_user());
+    factory.ownable.accept_ownership().unwrap();
+
+    // The previous owner was only an admin through ownership
+    assert!(!factory.check_is_admin(mock_admin()).unwrap());
+    assert!(factory.check_is_admin(mock_user()).unwrap());
+    assert_eq!(factory.get_admin_count().unwrap(), U256::from(2));
+    vm.set_sender(mock_admin());
+    assert_eq!(factory.set_aave_address(mock_aave_pool()), Err(b"Not authorized".to_vec()));
+
+    vm.set_sender(mock_user());
+    assert_eq!(factory.remove_admin(mock_user()), Err(b"Cannot remove owner".to_vec()));
+    factory.remove_admin(mock_attacker()).unwrap();
+    assert_eq!(factory.get_admin_count().unwrap(), U256::ONE);
+}
+
+// ===== REENTRANCY TESTS =====
+
+/// Result of a call made back into the vault from the asset token
+type Reentry = Rc<RefCell<Option<Result<U256, Vec<u8>>>>>;
+
+/// Deploys a vault whose asset at `mock_usdc()` calls `reenter` on the vault
+/// from the next `transfer`/`transferFrom` once armed, as the depositor (like
+/// a token hook the depositor controls), then behaves like a normal token
+fn reentrant_token_vault(
+    vm: &SimHost,
+    reenter: fn(&mut UserVault) -> Result<U256, Vec<u8>>,
+) -> (UserVault, Rc<Cell<bool>>, Reentry) {
+    let inner = Address::from([40u8; 20]);
+    register_stablecoin(vm, inner);
+    let armed = Rc::new(Cell::new(false));
+    let reentry: Reentry = Rc::default();
+    let (host, trigger, record) = (vm.clone(), Rc::clone(&armed), Rc::clone(&reentry));
+    vm.register(
+        mock_usdc(),
+        Box::new(move |sender, data| {
+            let selector = &data[..4];
+            let moves_tokens =
+                selector == IERC20::transferCall::SELECTOR || selector == IERC20::transferFromCall::SELECTOR;
+            if moves_tokens && trigger.replace(false) {
+                let outer_sender = host.vm.msg_sender();
+                host.vm.set_sender(mock_user());
+                *record.borrow_mut() = Some(reenter(&mut UserVault::from(&host)));
+                host.vm.set_sender(outer_sender);
+            }
+            host.call_as(sender, inner, data)
+        }),
+    );
+    fund_test_accounts(vm);
+    deploy_factory(vm);
+
+    vm.set_sender(mock_admin());
+    let mut vault = UserVault::from(vm);
+    initialize_vault(&mut vault, mock_usdc(), 0);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+    (vault, armed, reentry)
+}
+
+fn reentrant_call_error() -> Vec<u8> {
+    ReentrancyGuardReentrantCall {}.abi_encode()
+}
+
+#[test]
+fn test_malicious_token_cannot_reenter_withdraw() {
+    let vm = SimHost::new();
+    let (mut vault, armed, reentry) =
+        reentrant_token_vault(&vm, |vault| vault.withdraw(U256::from(500), mock_user(), mock_user()));
+
+    armed.set(true);
+    vault.withdraw(U256::from(400), mock_user(), mock_user()).unwrap();
+    assert_eq!(reentry.borrow_mut().take(), Some(Err(reentrant_call_error())));
+
+    // Only the outer withdrawal took effect
+    assert_eq!(vault.erc20.balance_of(mock_user()).unwrap(), U256::from(600));
+    assert_eq!(vault.erc20.total_supply(), U256::from(600));
+    assert_eq!(vault.total_assets().unwrap(), U256::from(600));
+    assert_eq!(token_balance(&vm, mock_usdc(), mock_user()), initial_balance() - U256::from(600));
+}
+
+#[test]
+fn test_malicious_token_cannot_reenter_redeem() {
+    let vm = SimHost::new();
+    let (mut vault, armed, reentry) =
+        reentrant_token_vault(&vm, |vault| vault.redeem(U256::from(500), mock_user(), mock_user()));
+
+    armed.set(true);
+    vault.redeem(U256::from(400), mock_user(), mock_user()).unwrap();
+    assert_eq!(reentry.borrow_mut().take(), Some(Err(reentrant_call_error())));
+
+    assert_eq!(vault.erc20.balance_of(mock_user()).unwrap(), U256::from(600));
+    assert_eq!(vault.erc20.total_supply(), U256::from(600));
+    assert_eq!(vault.total_assets().unwrap(), U256::from(600));
+    assert_eq!(token_balance(&vm, mock_usdc(), mock_user()), initial_balance() - U256::from(600));
+}
+
+#[test]
+fn test_malicious_token_cannot_reenter_deposit() {
+    let vm = SimHost::new();
+    let (mut vault, armed, reentry) =
+        reentrant_token_vault(&vm, |vault| vault.deposit(U256::from(500), mock_user()));
+
+    armed.set(true);
+    vault.deposit(U256::from(200), mock_user()).unwrap();
+    assert_eq!(reentry.borrow_mut().take(), Some(Err(reentrant_call_error())));
+
+    assert_eq!(vault.erc20.balance_of(mock_user()).unwrap(), U256::from(1_200));
+    assert_eq!(vault.erc20.total_supply(), U256::from(1_200));
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_200));
+    assert_eq!(token_balance(&vm, mock_usdc(), mock_user()), initial_balance() - U256::from(1_200));
+}
+
+#[test]
+fn test_lock_released_after_withdraw() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+
+    // Shares are burned and totals updated before assets are sent
+    vault.withdraw(U256::from(400), mock_user(), mock_user()).unwrap();
+    assert!(!vault.reentrancy_guard.entered.get());
+    assert_eq!(vault.erc20.balance_of(mock_user()).unwrap(), U256::from(600));
+    assert_eq!(vault.total_assets().unwrap(), U256::from(600));
+
+    vault.redeem(U256::from(100), mock_user(), mock_user()).unwrap();
+    assert!(!vault.reentrancy_guard.entered.get());
+}
+
+// ===== SAFE ERC-20 TESTS =====
+
+fn transfer_from_call(vm: &TestVM, from: Address, value: u64) -> Vec<u8> {
+    IERC20::transferFromCall {
+        from,
+        to: vm.contract_address(),
+        value: U256::from(value),
+    }
+    .abi_encode()
+}
+
+fn abi_bool(value: bool) -> Vec<u8> {
+    U256::from(value as u8).to_be_bytes::<32>().to_vec()
+}
+
+#[test]
+fn test_deposit_bubbles_token_revert_reason() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_sender(mock_user());
+
+    let reason = b"ERC20: insufficient allowance".to_vec();
+    vm.mock_call(
+        mock_usdc(),
+        transfer_from_call(&vm, mock_user(), 1_000),
+        Err(reason.clone()),
+    );
+
+    assert_eq!(vault.deposit(U256::from(1_000), mock_user()), Err(reason));
+    assert_eq!(vault.erc20.total_supply(), U256::ZERO);
+    assert_eq!(vault.total_assets().unwrap(), U256::ZERO);
+    assert!(!vault.reentrancy_guard.entered.get());
+}
+
+#[test]
+fn test_deposit_reverts_when_token_returns_false() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_sender(mock_user());
+
+    vm.mock_call(mock_usdc(), transfer_from_call(&vm, mock_user(), 1_000), Ok(abi_bool(false)));
+    assert_eq!(
+        vault.deposit(U256::from(1_000), mock_user()),
+        Err(SafeErc20FailedOperation { token: mock_usdc() }.abi_encode())
+    );
+    assert_eq!(vault.erc20.total_supply(), U256::ZERO);
+}
+
+#[test]
+fn test_deposit_accepts_true_and_empty_returns() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_sender(mock_user());
+
+    // Standard token returning `true`
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+
+    // USDT-style token returning nothing
+    vm.mock_call(mock_usdc(), transfer_from_call(&vm, mock_user(), 500), Ok(Vec::new()));
+    vault.deposit(U256::from(500), mock_user()).unwrap();
+    assert_eq!(vault.erc20.balance_of(mock_user()).unwrap(), U256::from(1_500));
+}
+
+#[test]
+fn test_withdraw_reverts_when_transfer_fails() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+
+    let transfer = IERC20::transferCall {
+        to: mock_user(),
+        value: U256::from(400),
+    }
+    .abi_encode();
+    vm.mock_call(mock_usdc(), transfer, Err(Vec::new()));
+    assert_eq!(
+        vault.withdraw(U256::from(400), mock_user(), mock_user()),
+        Err(FailedCall { target: mock_usdc() }.abi_encode())
+    );
+}
+
+#[test]
+fn test_asset_without_code_is_rejected() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_code(mock_usdc(), Vec::new());
+    vm.set_sender(mock_user());
+
+    // Pricing the deposit reads the vault balance, which fails first
+    assert_eq!(
+        vault.deposit(U256::from(1_000), mock_user()),
+        Err(FailedCall { target: mock_usdc() }.abi_encode())
+    );
+
+    // A transfer to an account without code succeeds silently and is refused
+    vm.mock_static_call(
+        mock_usdc(),
+        IERC20::balanceOfCall { account: vm.contract_address() }.abi_encode(),
+        Ok(U256::ZERO.to_be_bytes::<32>().to_vec()),
+    );
+    assert_eq!(
+        vault.deposit(U256::from(1_000), mock_user()),
+        Err(SafeErc20FailedOperation { token: mock_usdc() }.abi_encode())
+    );
+}
+
+#[test]
+fn test_deploy_to_aave_bubbles_supply_revert() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+
+    vault
+        .protocol_addresses
+        .setter("aave".into())
+        .set(mock_aave_pool());
+    let supply = IAavePool::supplyCall {
+        asset: mock_usdc(),
+        amount: U256::from(600),
+        onBehalfOf: vm.contract_address(),
+        referralCode: 0,
+    }
+    .abi_encode();
+    let reason = b"51".to_vec(); // Aave SUPPLY_CAP_EXCEEDED
+    vm.mock_call(mock_aave_pool(), supply, Err(reason.clone()));
+
+    vm.set_sender(mock_admin());
+    assert_eq!(vault.deploy_to_aave(U256::from(600)), Err(reason));
+    assert!(!vault.reentrancy_guard.entered.get());
+}
+
+// ===== PROTOCOL POSITION TESTS =====
+
+/// Expected `get_position_breakdown` result, values in `STRATEGIES` order
+/// (aave, compound, uniswap, erc4626)
+fn breakdown(idle: u64, values: [u64; 4]) -> (U256, Vec<String>, Vec<U256>) {
+    let protocols = STRATEGIES.iter().map(|strategy| strategy.id().to_string()).collect();
+    (U256::from(idle), protocols, values.into_iter().map(U256::from).collect())
+}
+
+/// Registers a minimal Aave pool for the vault: supplied assets move to the
+/// pool and are represented 1:1 by a mock aToken at `mock_a_token()`
+fn deploy_aave(vm: &SimHost, vault: &mut UserVault) {
+    register_stablecoin(vm, mock_a_token());
+    let host = vm.clone();
+    vm.register(
+        mock_aave_pool(),
+        Box::new(move |sender, data| {
+            if let Ok(call) = IAavePool::supplyCall::abi_decode(data, true) {
+                let pull = IERC20::transferFromCall {
+                    from: sender,
+                    to: mock_aave_pool(),
+                    value: call.amount,
+                }
+                .abi_encode();
+                host.call_as(mock_aave_pool(), call.asset, &pull)?;
+                mint_token(&host, mock_a_token(), call.onBehalfOf, call.amount);
+                return Ok(Vec::new());
+            }
+            if let Ok(call) = IAavePool::withdrawCall::abi_decode(data, true) {
+                let mut amount = call.amount;
+                if amount == U256::MAX {
+                    amount = token_balance(&host, mock_a_token(), sender);
+                }
+                host.call_as(sender, mock_a_token(), &IMockToken::burnCall { value: amount }.abi_encode())?;
+                let pay = IERC20::transferCall { to: call.to, value: amount }.abi_encode();
+                host.call_as(mock_aave_pool(), call.asset, &pay)?;
+                return Ok(amount.to_be_bytes::<32>().to_vec());
+            }
+            if IAavePool::getReserveDataCall::abi_decode(data, true).is_ok() {
+                // ReserveData is 15 static words; aTokenAddress is the ninth
+                let mut reserve = vec![0u8; 15 * 32];
+                reserve[8 * 32 + 12..9 * 32].copy_from_slice(mock_a_token().as_slice());
+                return Ok(reserve);
+            }
+            Err(Vec::new())
+        }),
+    );
+    vault.protocol_addresses.setter("aave".into()).set(mock_aave_pool());
+}
+
+/// Registers a minimal Comet market for the vault at `mock_c_token()` and
+/// returns its supplied balances, which tests raise to accrue interest
+fn deploy_comet(vm: &SimHost, vault: &mut UserVault) -> Rc<RefCell<HashMap<Address, U256>>> {
+    let balances: Rc<RefCell<HashMap<Address, U256>>> = Rc::default();
+    let (host, book) = (vm.clone(), Rc::clone(&balances));
+    vm.register(
+        mock_c_token(),
+        Box::new(move |sender, data| {
+            if let Ok(call) = IComet::supplyCall::abi_decode(data, true) {
+                let pull = IERC20::transferFromCall {
+                    from: sender,
+                    to: mock_c_token(),
+                    value: call.amount,
+                }
+                .abi_encode();
+                host.call_as(mock_c_token(), call.asset, &pull)?;
+                *book.borrow_mut().entry(sender).or_default() += call.amount;
+                return Ok(Vec::new());
+            }
+            if let Ok(call) = IComet::withdrawCall::abi_decode(data, true) {
+                let balance = book.borrow().get(&sender).copied().unwrap_or_default();
+                let amount = if call.amount == U256::MAX { balance } else { call.amount };
+                if amount > balance {
+                    return Err(Vec::new());
+                }
+                book.borrow_mut().insert(sender, balance - amount);
+                let pay = IERC20::transferCall { to: sender, value: amount }.abi_encode();
+                host.call_as(mock_c_token(), call.asset, &pay)?;
+                return Ok(Vec::new());
+            }
+            if let Ok(call) = IERC20::balanceOfCall::abi_decode(data, true) {
+                let balance = book.borrow().get(&call.account).copied().unwrap_or_default();
+                return Ok(balance.to_be_bytes::<32>().to_vec());
+            }
+            Err(Vec::new())
+        }),
+    );
+    vault.protocol_addresses.setter("compound".into()).set(mock_c_token());
+    balances
+}
+
+/// Accrues `amount` of interest on the vault's Aave position
+fn accrue_aave_interest(vm: &SimHost, amount: U256) {
+    mint_token(vm, mock_a_token(), vm.contract_address(), amount);
+    mint_token(vm, mock_usdc(), mock_aave_pool(), amount);
+}
+
+/// Accrues `amount` of interest on the vault's Compound position
+fn accrue_comet_interest(vm: &SimHost, comet: &RefCell<HashMap<Address, U256>>, amount: U256) {
+    *comet.borrow_mut().entry(vm.contract_address()).or_default() += amount;
+    mint_token(vm, mock_usdc(), mock_c_token(), amount);
+}
+
+#[test]
+fn test_total_assets_includes_protocol_positions() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    deploy_aave(&vm, &mut vault);
+    deploy_comet(&vm, &mut vault);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+
+    vm.set_sender(mock_admin());
+    vault.deploy_to_aave(U256::from(400)).unwrap();
+    vault.deploy_to_compound(U256::from(300)).unwrap();
+    assert_eq!(vault.get_protocol_address("aave_atoken".into()).unwrap(), mock_a_token());
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(300, [400, 300, 0, 0])
+    );
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_000));
+
+    // Deployed assets are no longer available to deploy again
+    assert_eq!(vault.deploy_to_aave(U256::from(301)), Err(b"Insufficient assets".to_vec()));
+}
+
+#[test]
+fn test_protocol_yield_reaches_share_price() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    deploy_aave(&vm, &mut vault);
+    let comet = deploy_comet(&vm, &mut vault);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+    vm.set_sender(mock_admin());
+    vault.deploy_to_aave(U256::from(500)).unwrap();
+    vault.deploy_to_compound(U256::from(500)).unwrap();
+
+    // aTokens rebase upwards; Comet balances grow with the supply index
+    accrue_aave_interest(&vm, U256::from(50));
+    accrue_comet_interest(&vm, &comet, U256::from(150));
+
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(0, [550, 650, 0, 0])
+    );
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_200));
+    // 1,000 shares are now worth 1,200 assets, less the virtual share's cut
+    assert_eq!(vault.convert_to_assets(U256::from(1_000)).unwrap(), U256::from(1_199));
+}
+
+#[test]
+fn test_withdraw_from_protocols_returns_assets() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    deploy_aave(&vm, &mut vault);
+    deploy_comet(&vm, &mut vault);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+    assert!(vault.withdraw_from_aave(U256::from(1)).is_err());
+
+    vm.set_sender(mock_admin());
+    vault.deploy_to_aave(U256::from(400)).unwrap();
+    vault.deploy_to_compound(U256::from(300)).unwrap();
+
+    assert_eq!(vault.withdraw_from_aave(U256::from(150)).unwrap(), U256::from(150));
+    let logs = vm.get_emitted_logs();
+    let (topics, data) = logs.last().unwrap();
+    assert_eq!(topics[0], ProtocolWithdrawn::SIGNATURE_HASH);
+    let event = ProtocolWithdrawn::abi_decode_data(data, true).unwrap();
+    assert_eq!((event.0.as_str(), event.1), ("aave", U256::from(150)));
+
+    assert_eq!(vault.withdraw_from_compound(U256::from(100)).unwrap(), U256::from(100));
+    assert_eq!(vault.get_protocol_allocation("aave".into()).unwrap(), U256::from(250));
+    assert_eq!(vault.get_protocol_allocation("compound".into()).unwrap(), U256::from(200));
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(550, [250, 200, 0, 0])
+    );
+    assert!(!vault.reentrancy_guard.entered.get());
+
+    // Only the owner can pull capital
+    vm.set_sender(mock_user());
+    assert!(vault.withdraw_from_compound(U256::from(1)).is_err());
+    assert!(vault.withdraw_all_from_protocol("aave".into()).is_err());
+}
+
+#[test]
+fn test_withdraw_all_from_protocol_includes_yield() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    deploy_aave(&vm, &mut vault);
+    let comet = deploy_comet(&vm, &mut vault);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+    vm.set_sender(mock_admin());
+    vault.deploy_to_aave(U256::from(500)).unwrap();
+    vault.deploy_to_compound(U256::from(500)).unwrap();
+    accrue_aave_interest(&vm, U256::from(50));
+    accrue_comet_interest(&vm, &comet, U256::from(20));
+
+    assert_eq!(vault.withdraw_all_from_protocol("aave".into()).unwrap(), U256::from(550));
+    assert_eq!(vault.withdraw_all_from_protocol("compound".into()).unwrap(), U256::from(520));
+    assert_eq!(vault.get_protocol_allocation("aave".into()).unwrap(), U256::ZERO);
+    assert_eq!(vault.get_protocol_allocation("compound".into()).unwrap(), U256::ZERO);
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(1_070, [0, 0, 0, 0])
+    );
+
+    assert_eq!(
+        vault.withdraw_all_from_protocol("morpho".into()),
+        Err(b"Unsupported protocol".to_vec())
+    );
+}
+
+#[test]
+fn test_protocol_functions_dispatch_by_id() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    deploy_aave(&vm, &mut vault);
+    deploy_comet(&vm, &mut vault);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+    assert!(vault.deploy_to_protocol("aave".into(), U256::from(1)).is_err());
+
+    vm.set_sender(mock_admin());
+    vault.deploy_to_protocol("aave".into(), U256::from(300)).unwrap();
+    vault.deploy_to_protocol("compound".into(), U256::from(200)).unwrap();
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(500, [300, 200, 0, 0])
+    );
+
+    assert_eq!(
+        vault.withdraw_from_protocol("compound".into(), U256::from(50)).unwrap(),
+        U256::from(50)
+    );
+    assert_eq!(vault.get_protocol_allocation("compound".into()).unwrap(), U256::from(150));
+    assert_eq!(
+        vault.withdraw_from_protocol("aave".into(), U256::ZERO),
+        Err(b"Amount must be greater than 0".to_vec())
+    );
+    assert_eq!(
+        vault.deploy_to_protocol("aave".into(), U256::from(551)),
+        Err(b"Insufficient assets".to_vec())
+    );
+    assert_eq!(
+        vault.deploy_to_protocol("morpho".into(), U256::from(1)),
+        Err(b"Unsupported protocol".to_vec())
+    );
+    assert_eq!(
+        vault.withdraw_from_protocol("morpho".into(), U256::from(1)),
+        Err(b"Unsupported protocol".to_vec())
+    );
+    assert_eq!(
+        vault.deploy_to_protocol("uniswap".into(), U256::from(1)),
+        Err(b"Uniswap pool not set".to_vec())
+    );
+}
+
+#[test]
+fn test_emergency_exit_works_while_paused() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    deploy_aave(&vm, &mut vault);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+    vm.set_sender(mock_admin());
+    vault.deploy_to_aave(U256::from(600)).unwrap();
+    accrue_aave_interest(&vm, U256::from(30));
+    vault.pause().unwrap();
+
+    assert!(vault.deploy_to_aave(U256::from(100)).is_err());
+    vm.set_sender(mock_user());
+    assert!(vault.emergency_exit("aave".into()).is_err());
+
+    vm.set_sender(mock_admin());
+    assert_eq!(vault.emergency_exit("aave".into()).unwrap(), U256::from(630));
+    assert_eq!(vault.get_protocol_allocation("aave".into()).unwrap(), U256::ZERO);
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(1_030, [0, 0, 0, 0])
+    );
+    assert_eq!(
+        vault.emergency_exit("morpho".into()),
+        Err(b"Unsupported protocol".to_vec())
+    );
+    assert!(!vault.reentrancy_guard.entered.get());
+}
+
+// ===== FACTORY PROTOCOL ADDRESS TESTS =====
+
+fn set_factory_address(vm: &SimHost, data: Vec<u8>) {
+    vm.call_as(mock_admin(), mock_factory(), &data).unwrap();
+}
+
+#[test]
+fn test_protocol_addresses_are_read_from_factory() {
+    let vm = SimHost::new();
+    deploy_usdc(&vm);
+    deploy_factory(&vm);
+    set_factory_address(&vm, IFactoryAdmin::setAaveAddressCall { aave_address: mock_aave_pool() }.abi_encode());
+    vm.set_sender(mock_admin());
+    let mut vault = UserVault::from(&vm);
+    initialize_vault(&mut vault, mock_usdc(), 0);
+    assert_eq!(vault.get_protocol_address("aave".into()).unwrap(), mock_aave_pool());
+    assert_eq!(vault.get_protocol_address("compound".into()).unwrap(), Address::ZERO);
+
+    // An admin change reaches the vault on the next refresh
+    set_factory_address(
+        &vm,
+        IFactoryAdmin::setCompoundAddressCall { compound_address: mock_c_token() }.abi_encode(),
+    );
+    vm.set_sender(mock_user());
+    assert!(vault.refresh_protocol_addresses().is_err());
+    vm.set_sender(mock_admin());
+    assert_eq!(vault.refresh_protocol_addresses().unwrap(), vec!["compound".to_string()]);
+    assert_eq!(vault.get_protocol_address("compound".into()).unwrap(), mock_c_token());
+    let logs = vm.get_emitted_logs();
+    let (topics, data) = logs.last().unwrap();
+    assert_eq!(topics[0], ProtocolAddressesRefreshed::SIGNATURE_HASH);
+    assert_eq!(
+        ProtocolAddressesRefreshed::abi_decode_data(data, true).unwrap(),
+        (vec!["compound".to_string()], vec![mock_c_token()])
+    );
+
+    // Only the factory may push addresses; nothing changed, so nothing is logged
+    let push = |vault: &mut UserVault| {
+        vault.update_protocol_addresses(mock_aave_pool(), mock_c_token(), Address::ZERO, Address::ZERO)
+    };
+    assert_eq!(push(&mut vault), Err(b"Not factory".to_vec()));
+    vm.set_sender(mock_factory());
+    assert!(push(&mut vault).unwrap().is_empty());
+    assert!(vault.refresh_protocol_addresses().is_err());
+    assert_eq!(vm.get_emitted_logs().len(), logs.len());
+}
+
+#[test]
+fn test_refresh_keeps_entry_points_of_open_positions() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+
+    // The factory knows neither of the entry points the positions were opened on
+    assert_eq!(
+        vault.refresh_protocol_addresses(),
+        Err(b"Position not empty".to_vec())
+    );
+    assert_eq!(vault.get_protocol_address("aave".into()).unwrap(), mock_aave_pool());
+
+    vault.withdraw_all_from_protocol("aave".into()).unwrap();
+    vault.withdraw_all_from_protocol("compound".into()).unwrap();
+    assert_eq!(vault.protocol_addresses.get("aave_atoken".into()), mock_a_token());
+    assert_eq!(
+        vault.refresh_protocol_addresses().unwrap(),
+        vec!["aave".to_string(), "compound".to_string()]
+    );
+    assert_eq!(vault.get_protocol_address("aave".into()).unwrap(), Address::ZERO);
+    // The aToken belonged to the old pool
+    assert_eq!(vault.protocol_addresses.get("aave_atoken".into()), Address::ZERO);
+}
+
+#[test]
+fn test_factory_pushes_protocol_addresses_to_its_vaults() {
+    let vm = SimHost::new();
+    deploy_usdc(&vm);
+    let (factory_host, factory) = deploy_shared_factory(&vm);
+    let plain = register_deployed_vault(&vm, &factory_host, &factory, 0, |_, _| {});
+    let invested = register_deployed_vault(&vm, &factory_host, &factory, 1, |_, vault| {
+        deploy_aave(&vm, vault);
+        vault.deploy_to_protocol("aave".into(), U256::from(100)).unwrap();
+    });
+
+    let new_pool = Address::from([26u8; 20]);
+    factory_host.vm.set_sender(mock_admin());
+    factory.borrow_mut().set_aave_address(new_pool).unwrap();
+    factory_host.vm.set_sender(mock_user());
+    assert_eq!(
+        factory.borrow_mut().refresh_vault_protocol_addresses(U256::ZERO, U256::from(10)),
+        Err(b"Not authorized".to_vec())
+    );
+
+    // The vault with an open Aave position keeps its pool and is reported
+    factory_host.vm.set_sender(mock_admin());
+    assert_eq!(
+        factory.borrow_mut().refresh_vault_protocol_addresses(U256::ZERO, U256::from(10)).unwrap(),
+        vec![invested]
+    );
+    assert!(!factory.borrow().reentrancy_guard.entered.get());
+    let logs = factory_host.vm.get_emitted_logs();
+    assert_eq!(logs.last().unwrap().0[0], VaultRefreshFailed::SIGNATURE_HASH);
+    let aave_address = |vault: Address| {
+        let call = IStrategyVault::getProtocolAddressCall { protocol: "aave".into() };
+        let returned = vm.call_as(mock_user(), vault, &call.abi_encode()).unwrap();
+        IStrategyVault::getProtocolAddressCall::abi_decode_returns(&returned, true).unwrap()._0
+    };
+    assert_eq!(aave_address(plain), new_pool);
+    assert_eq!(aave_address(invested), mock_aave_pool());
+}
+
+// ===== WITHDRAWAL QUEUE TESTS =====
+
+/// Deposits 1,000 for the user and deploys 400 each to Aave and Compound
+fn vault_with_positions(vm: &SimHost) -> UserVault {
+    let mut vault = init_vault(vm);
+    deploy_aave(vm, &mut vault);
+    deploy_comet(vm, &mut vault);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+    vm.set_sender(mock_admin());
+    vault.deploy_to_aave(U256::from(400)).unwrap();
+    vault.deploy_to_compound(U256::from(400)).unwrap();
+    vault
+}
+
+#[test]
+fn test_withdrawal_queue_configuration() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    assert_eq!(
+        vault.set_withdrawal_queue(vec!["morpho".into()]),
+        Err(b"Unsupported protocol".to_vec())
+    );
+    assert_eq!(
+        vault.set_withdrawal_queue(vec!["aave".into(), "aave".into()]),
+        Err(b"Duplicate protocol".to_vec())
+    );
+    assert_eq!(vault.set_max_loss(U256::from(10_001)), Err(b"Invalid max loss".to_vec()));
+
+    vault.set_withdrawal_queue(vec!["compound".into(), "aave".into()]).unwrap();
+    vault.set_max_loss(U256::from(50)).unwrap();
+    assert_eq!(
+        vault.get_withdrawal_queue().unwrap(),
+        vec!["compound".to_string(), "aave".to_string()]
+    );
+    assert_eq!(vault.max_loss().unwrap(), U256::from(50));
+
+    // Replacing the queue drops the old entries
+    vault.set_withdrawal_queue(vec!["aave".into()]).unwrap();
+    assert_eq!(vault.get_withdrawal_queue().unwrap(), vec!["aave".to_string()]);
+
+    vm.set_sender(mock_user());
+    assert!(vault.set_withdrawal_queue(Vec::new()).is_err());
+    assert!(vault.set_max_loss(U256::ZERO).is_err());
+}
+
+#[test]
+fn test_withdraw_unwinds_queue_in_order() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    assert_eq!(vault.max_withdraw(mock_user()).unwrap(), U256::from(200));
+
+    vault.set_withdrawal_queue(vec!["compound".into(), "aave".into()]).unwrap();
+    assert_eq!(vault.max_withdraw(mock_user()).unwrap(), U256::from(1_000));
+
+    vm.set_sender(mock_user());
+    let before = token_balance(&vm, mock_usdc(), mock_user());
+    vault.withdraw(U256::from(700), mock_user(), mock_user()).unwrap();
+    assert_eq!(token_balance(&vm, mock_usdc(), mock_user()), before + U256::from(700));
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(0, [300, 0, 0, 0])
+    );
+
+    // One event per unwind, in queue order, then the withdrawal
+    let logs = vm.get_emitted_logs();
+    let unwinds: Vec<_> = logs
+        .iter()
+        .filter(|(topics, _)| topics[0] == ProtocolWithdrawn::SIGNATURE_HASH)
+        .map(|(_, data)| ProtocolWithdrawn::abi_decode_data(data, true).unwrap())
+        .collect();
+    assert_eq!(
+        unwinds,
+        vec![("compound".to_string(), U256::from(400)), ("aave".to_string(), U256::from(100))]
+    );
+    assert_eq!(logs.last().unwrap().0[0], Withdraw::SIGNATURE_HASH);
+
+    // The rest of the position is reachable by redeem too
+    assert_eq!(vault.redeem(U256::from(300), mock_user(), mock_user()).unwrap(), U256::from(300));
+}
+
+/// Makes Compound hand back nothing when asked for `amount`
+fn comet_withholds(vm: &SimHost, amount: u64) {
+    let withdraw = IComet::withdrawCall {
+        asset: mock_usdc(),
+        amount: U256::from(amount),
     }
-    
-    fn mock_aave_pool() -> Address {
-        Address::from([2u8; 20])
+    .abi_encode();
+    vm.mock_call(mock_c_token(), withdraw, Ok(Vec::new()));
+}
+
+#[test]
+fn test_unwind_loss_beyond_max_loss_reverts() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    vault.set_withdrawal_queue(vec!["compound".into()]).unwrap();
+    vault.set_max_loss(U256::from(1_000)).unwrap();
+    comet_withholds(&vm, 100);
+
+    vm.set_sender(mock_user());
+    assert_eq!(
+        vault.redeem(U256::from(300), mock_user(), mock_user()),
+        Err(ExceededMaxLoss {
+            loss: U256::from(100),
+            max_loss: U256::from(30),
+        }
+        .abi_encode())
+    );
+}
+
+#[test]
+fn test_withdraw_reverts_on_any_unwinding_loss() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    vault.set_withdrawal_queue(vec!["compound".into()]).unwrap();
+    vault.set_max_loss(U256::from(5_000)).unwrap();
+    comet_withholds(&vm, 100);
+
+    // withdraw promises exact assets, so the loss bound only applies to redeem
+    vm.set_sender(mock_user());
+    assert_eq!(
+        vault.withdraw(U256::from(300), mock_user(), mock_user()),
+        Err(ExceededMaxLoss {
+            loss: U256::from(100),
+            max_loss: U256::ZERO,
+        }
+        .abi_encode())
+    );
+}
+
+#[test]
+fn test_redeem_bounded_rejects_unwinding_loss() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    vault.set_withdrawal_queue(vec!["compound".into()]).unwrap();
+    vault.set_max_loss(U256::from(5_000)).unwrap();
+    comet_withholds(&vm, 100);
+
+    // The preview promises 300, but the unwind only delivers 200
+    vm.set_sender(mock_user());
+    assert_eq!(
+        vault.redeem_bounded(U256::from(300), mock_user(), mock_user(), U256::from(201), U256::MAX),
+        Err(ERC5143InsufficientAssets {
+            assets: U256::from(200),
+            min_assets: U256::from(201),
+        }
+        .abi_encode())
+    );
+}
+
+#[test]
+fn test_redeem_passes_on_loss_within_bound() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    vault.set_withdrawal_queue(vec!["compound".into()]).unwrap();
+    vault.set_max_loss(U256::from(5_000)).unwrap();
+    comet_withholds(&vm, 100);
+
+    vm.set_sender(mock_user());
+    let before = token_balance(&vm, mock_usdc(), mock_user());
+    assert_eq!(vault.redeem(U256::from(300), mock_user(), mock_user()).unwrap(), U256::from(200));
+    assert_eq!(token_balance(&vm, mock_usdc(), mock_user()), before + U256::from(200));
+
+    let logs = vm.get_emitted_logs();
+    let (topics, data) = logs.last().unwrap();
+    assert_eq!(topics[0], Withdraw::SIGNATURE_HASH);
+    assert_eq!(U256::from_be_slice(&data[..32]), U256::from(200));
+}
+
+// ===== HARVEST TESTS =====
+
+fn mock_reward_token() -> Address {
+    Address::from([11u8; 20])
+}
+
+fn mock_aave_rewards() -> Address {
+    Address::from([12u8; 20])
+}
+
+fn mock_comet_rewards() -> Address {
+    Address::from([13u8; 20])
+}
+
+fn mock_router() -> Address {
+    Address::from([14u8; 20])
+}
+
+/// Registers reward distributors for both protocols that pay `reward` of
+/// `token` per claim, and points the vault at them
+fn deploy_rewards(vm: &SimHost, vault: &mut UserVault, token: Address, reward: u64) {
+    let host = vm.clone();
+    vm.register(
+        mock_aave_rewards(),
+        Box::new(move |_, data| {
+            let call = IAaveRewardsController::claimRewardsCall::abi_decode(data, true)
+                .map_err(|_| Vec::new())?;
+            assert_eq!(call.assets, vec![mock_a_token()]);
+            mint_token(&host, call.reward, call.to, U256::from(reward));
+            Ok(U256::from(reward).to_be_bytes::<32>().to_vec())
+        }),
+    );
+    let host = vm.clone();
+    vm.register(
+        mock_comet_rewards(),
+        Box::new(move |_, data| {
+            let call = ICometRewards::claimCall::abi_decode(data, true).map_err(|_| Vec::new())?;
+            mint_token(&host, token, call.src, U256::from(reward));
+            Ok(Vec::new())
+        }),
+    );
+
+    vm.set_sender(mock_admin());
+    vault.set_rewards_config("aave".into(), mock_aave_rewards(), token).unwrap();
+    vault.set_rewards_config("compound".into(), mock_comet_rewards(), token).unwrap();
+}
+
+/// Registers a Uniswap router that buys any token at two assets apiece
+fn deploy_router(vm: &SimHost, vault: &mut UserVault) {
+    register_router(vm, 2);
+    vault.protocol_addresses.setter("uniswap".into()).set(mock_router());
+}
+
+/// Registers a Uniswap router at `mock_router()` paying `rate` output
+/// tokens per input token on every route
+fn register_router(vm: &SimHost, rate: u64) {
+    let host = vm.clone();
+    vm.register(
+        mock_router(),
+        Box::new(move |sender, data| {
+            if ISwapRouter::positionManagerCall::abi_decode(data, true).is_ok() {
+                return Ok(mock_position_manager().into_word().to_vec());
+            }
+            let (token_in, token_out, recipient, amount_in, min_amount_out) =
+                if let Ok(call) = ISwapRouter::exactInputSingleCall::abi_decode(data, true) {
+                    let params = call.params;
+                    (params.tokenIn, params.tokenOut, params.recipient, params.amountIn, params.amountOutMinimum)
+                } else {
+                    let params = ISwapRouter::exactInputCall::abi_decode(data, true)
+                        .map_err(|_| Vec::new())?
+                        .params;
+                    let path = params.path;
+                    let token_in = Address::from_slice(&path[..20]);
+                    let token_out = Address::from_slice(&path[path.len() - 20..]);
+                    (token_in, token_out, params.recipient, params.amountIn, params.amountOutMinimum)
+                };
+            let amount_out = amount_in * U256::from(rate);
+            if amount_out < min_amount_out {
+                return Err(b"Too little received".to_vec());
+            }
+            let pull = IERC20::transferFromCall {
+                from: sender,
+                to: mock_router(),
+                value: amount_in,
+            }
+            .abi_encode();
+            host.call_as(mock_router(), token_in, &pull)?;
+            mint_token(&host, token_out, recipient, amount_out);
+            Ok(amount_out.to_be_bytes::<32>().to_vec())
+        }),
+    );
+}
+
+#[test]
+fn test_harvest_swaps_rewards_and_compounds() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    register_stablecoin(&vm, mock_reward_token());
+    deploy_rewards(&vm, &mut vault, mock_reward_token(), 100);
+    deploy_router(&vm, &mut vault);
+
+    assert_eq!(vault.harvest_from_protocol("aave".into(), U256::from(200)).unwrap(), U256::from(200));
+    assert!(!vault.reentrancy_guard.entered.get());
+
+    // Proceeds follow the 400/400 allocations
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(200, [500, 500, 0, 0])
+    );
+    assert_eq!(vault.get_protocol_allocation("aave".into()).unwrap(), U256::from(500));
+    assert_eq!(token_balance(&vm, mock_reward_token(), vm.contract_address()), U256::ZERO);
+
+    let logs = vm.get_emitted_logs();
+    let (topics, data) = logs.last().unwrap();
+    assert_eq!(topics[0], ProtocolHarvested::SIGNATURE_HASH);
+    let (protocol, rewards, proceeds, share_price) =
+        ProtocolHarvested::abi_decode_data(data, true).unwrap();
+    assert_eq!(protocol, "aave");
+    assert_eq!((rewards, proceeds), (U256::from(100), U256::from(200)));
+    assert_eq!(share_price, vault.convert_to_assets(U256::from(1_000_000)).unwrap());
+    assert!(share_price > U256::from(1_000_000));
+}
+
+#[test]
+fn test_harvest_enforces_min_output() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    register_stablecoin(&vm, mock_reward_token());
+    deploy_rewards(&vm, &mut vault, mock_reward_token(), 100);
+    deploy_router(&vm, &mut vault);
+
+    assert_eq!(
+        vault.harvest_from_protocol("compound".into(), U256::from(201)),
+        Err(b"Too little received".to_vec())
+    );
+    assert!(!vault.reentrancy_guard.entered.get());
+}
+
+#[test]
+fn test_harvest_of_asset_rewards_skips_swap() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    deploy_rewards(&vm, &mut vault, mock_usdc(), 100);
+
+    assert_eq!(
+        vault.harvest_from_protocol("compound".into(), U256::from(101)),
+        Err(InsufficientHarvestProceeds {
+            proceeds: U256::from(100),
+            min_proceeds: U256::from(101),
+        }
+        .abi_encode())
+    );
+    assert_eq!(vault.harvest_from_protocol("compound".into(), U256::ZERO).unwrap(), U256::from(100));
+    assert_eq!(vault.get_protocol_allocation("compound".into()).unwrap(), U256::from(450));
+}
+
+#[test]
+fn test_harvest_requires_owner_and_configuration() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    assert_eq!(
+        vault.harvest_from_protocol("aave".into(), U256::ZERO),
+        Err(b"Rewards not configured".to_vec())
+    );
+    assert_eq!(
+        vault.harvest_from_protocol("morpho".into(), U256::ZERO),
+        Err(b"Unsupported protocol".to_vec())
+    );
+    assert!(vault
+        .set_rewards_config("morpho".into(), mock_aave_rewards(), mock_reward_token())
+        .is_err());
+
+    register_stablecoin(&vm, mock_reward_token());
+    deploy_rewards(&vm, &mut vault, mock_reward_token(), 100);
+    assert_eq!(
+        vault.get_rewards_config("aave".into()).unwrap(),
+        (mock_aave_rewards(), mock_reward_token())
+    );
+    assert_eq!(
+        vault.harvest_from_protocol("aave".into(), U256::ZERO),
+        Err(b"Uniswap address not set".to_vec())
+    );
+
+    vm.set_sender(mock_user());
+    assert!(vault.harvest_from_protocol("aave".into(), U256::ZERO).is_err());
+    assert!(vault
+        .set_rewards_config("aave".into(), Address::ZERO, Address::ZERO)
+        .is_err());
+}
+
+// ===== ERC-4626 STRATEGY TESTS =====
+
+fn mock_target_vault() -> Address {
+    Address::from([15u8; 20])
+}
+
+/// Deploys a second `UserVault` on the same asset at `mock_target_vault()`,
+/// seeded with a 1,000 deposit from the user
+fn deploy_target_vault(vm: &SimHost) -> Rc<RefCell<UserVault>> {
+    let host = vm.at(mock_target_vault());
+    host.vm.set_sender(mock_admin());
+    let mut target = UserVault::from(&host);
+    initialize_vault(&mut target, mock_usdc(), 0);
+
+    let approve = IERC20::approveCall {
+        spender: mock_target_vault(),
+        value: U256::MAX,
     }
-    
-    fn mock_a_token() -> Address {
-        Address::from([3u8; 20])
+    .abi_encode();
+    vm.call_as(mock_user(), mock_usdc(), &approve).unwrap();
+    host.vm.set_sender(mock_user());
+    target.deposit(U256::from(1_000), mock_user()).unwrap();
+
+    let target = Rc::new(RefCell::new(target));
+    vm.register_contract(mock_target_vault(), Rc::clone(&target), host.vm.clone());
+    target
+}
+
+#[test]
+fn test_erc4626_strategy_allocates_into_another_vault() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    let target = deploy_target_vault(&vm);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+
+    vm.set_sender(mock_admin());
+    assert_eq!(
+        vault.deploy_to_protocol("erc4626".into(), U256::from(600)),
+        Err(b"ERC-4626 vault address not set".to_vec())
+    );
+    vault.set_strategy_target("erc4626".into(), mock_target_vault()).unwrap();
+    vault.deploy_to_protocol("erc4626".into(), U256::from(600)).unwrap();
+    let shares = target.borrow().erc20.balance_of(vm.contract_address()).unwrap();
+    assert!(shares != U256::ZERO);
+    assert_eq!(vault.get_position_breakdown().unwrap(), breakdown(400, [0, 0, 0, 600]));
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_000));
+
+    // Yield in the target raises its share price and reaches this vault
+    // (600 of 1,600 gets 300 of 800; the target rounds the value down)
+    mint_token(&vm, mock_usdc(), mock_target_vault(), U256::from(800));
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_299));
+
+    assert_eq!(
+        vault.withdraw_from_protocol("erc4626".into(), U256::from(100)).unwrap(),
+        U256::from(100)
+    );
+    assert_eq!(vault.withdraw_all_from_protocol("erc4626".into()).unwrap(), U256::from(799));
+    assert_eq!(target.borrow().erc20.balance_of(vm.contract_address()).unwrap(), U256::ZERO);
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_299));
+
+    // Yield compounds in the target, so harvesting claims nothing
+    assert_eq!(vault.harvest_from_protocol("erc4626".into(), U256::ZERO).unwrap(), U256::ZERO);
+}
+
+#[test]
+fn test_erc4626_strategy_target_validation() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    deploy_target_vault(&vm);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+
+    vm.set_sender(mock_admin());
+    assert_eq!(
+        vault.set_strategy_target("aave".into(), mock_target_vault()),
+        Err(b"Target not configurable".to_vec())
+    );
+    assert_eq!(
+        vault.set_strategy_target("erc4626".into(), vm.contract_address()),
+        Err(b"Circular allocation".to_vec())
+    );
+
+    let weth_vault = Address::from([16u8; 20]);
+    vm.mock_static_call(
+        weth_vault,
+        IERC4626::assetCall {}.abi_encode(),
+        Ok(Address::from([17u8; 20]).into_word().to_vec()),
+    );
+    assert_eq!(
+        vault.set_strategy_target("erc4626".into(), weth_vault),
+        Err(b"Asset mismatch".to_vec())
+    );
+
+    vault.set_strategy_target("erc4626".into(), mock_target_vault()).unwrap();
+    vault.deploy_to_protocol("erc4626".into(), U256::from(100)).unwrap();
+    assert_eq!(
+        vault.set_strategy_target("erc4626".into(), Address::ZERO),
+        Err(b"Position not empty".to_vec())
+    );
+
+    vm.set_sender(mock_user());
+    assert!(vault
+        .set_strategy_target("erc4626".into(), mock_target_vault())
+        .is_err());
+}
+
+#[test]
+fn test_erc4626_strategy_refuses_allocation_cycles() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    deploy_target_vault(&vm);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+    vm.set_sender(mock_admin());
+
+    // C allocates into this vault, and D into C
+    let vault_c = Address::from([24u8; 20]);
+    let vault_d = Address::from([25u8; 20]);
+    let this = vm.contract_address();
+    deploy_vault_at(&vm, vault_c, |host, c| {
+        host.vm.mock_static_call(
+            this,
+            IERC4626::assetCall {}.abi_encode(),
+            Ok(mock_usdc().into_word().to_vec()),
+        );
+        c.set_strategy_target("erc4626".into(), this).unwrap();
+    });
+    deploy_vault_at(&vm, vault_d, |_, d| {
+        d.set_strategy_target("erc4626".into(), vault_c).unwrap();
+    });
+
+    assert_eq!(
+        vault.set_strategy_target("erc4626".into(), vault_c),
+        Err(b"Circular allocation".to_vec())
+    );
+    assert_eq!(
+        vault.set_strategy_target("erc4626".into(), vault_d),
+        Err(b"Circular allocation".to_vec())
+    );
+
+    // Holding this vault's shares is not a cycle, so sending some to the
+    // target can't block allocating into it
+    vault.set_strategy_target("erc4626".into(), mock_target_vault()).unwrap();
+    vm.set_sender(mock_user());
+    vault.erc20.transfer(mock_target_vault(), U256::from(1)).unwrap();
+    vm.set_sender(mock_admin());
+    vault.deploy_to_protocol("erc4626".into(), U256::from(100)).unwrap();
+}
+
+// ===== UNISWAP TESTS =====
+
+fn mock_pair_token() -> Address {
+    Address::from([18u8; 20])
+}
+
+fn mock_pool() -> Address {
+    Address::from([19u8; 20])
+}
+
+fn mock_position_manager() -> Address {
+    Address::from([20u8; 20])
+}
+
+/// Encodes a swap path through pools of the 0.05% tier
+fn encode_path(tokens: &[Address]) -> Bytes {
+    let mut path = tokens[0].to_vec();
+    for token in &tokens[1..] {
+        path.extend_from_slice(&500u32.to_be_bytes()[1..]);
+        path.extend_from_slice(token.as_slice());
     }
-    
-    fn mock_c_token() -> Address {
-        Address::from([4u8; 20])
+    Bytes::from(path)
+}
+
+/// Amounts the pool charges for adding `liquidity`, rounded up like
+/// `SqrtPriceMath.getAmount0Delta`/`getAmount1Delta` when minting
+fn amounts_to_mint(sqrt_price: U256, sqrt_a: U256, sqrt_b: U256, liquidity: U256) -> (U256, U256) {
+    let q96 = U256::ONE << 96;
+    let amount0 = |lower: U256| {
+        let numerator = mul_div(liquidity << 96, sqrt_b - lower, sqrt_b, Rounding::Ceil).unwrap();
+        mul_div(numerator, U256::ONE, lower, Rounding::Ceil).unwrap()
+    };
+    let amount1 = |upper: U256| mul_div(liquidity, upper - sqrt_a, q96, Rounding::Ceil).unwrap();
+    if sqrt_price <= sqrt_a {
+        (amount0(sqrt_a), U256::ZERO)
+    } else if sqrt_price < sqrt_b {
+        (amount0(sqrt_price), amount1(sqrt_price))
+    } else {
+        (U256::ZERO, amount1(sqrt_b))
     }
-    
-    fn mock_user() -> Address {
-        Address::from([5u8; 20])
+}
+
+/// The one position (token id 1) held by the mock position manager
+#[derive(Default)]
+struct MockPosition {
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity: U256,
+    owed0: U256,
+    owed1: U256,
+    /// (amount0Min, amount1Min) of the last liquidity change
+    last_min: (U256, U256),
+}
+
+/// Adds the liquidity `amount0`/`amount1` can provide, pulling the
+/// amounts it takes from `sender`; returns (liquidity, amount0, amount1)
+fn provide_liquidity(
+    host: &SimHost,
+    sender: Address,
+    sqrt_price: U256,
+    position: &mut MockPosition,
+    amount0: U256,
+    amount1: U256,
+    min: (U256, U256),
+) -> Result<(U256, U256, U256), Vec<u8>> {
+    let sqrt_a = sqrt_ratio_at_tick(position.tick_lower)?;
+    let sqrt_b = sqrt_ratio_at_tick(position.tick_upper)?;
+    let liquidity = liquidity_for_amounts(sqrt_price, sqrt_a, sqrt_b, amount0, amount1)?;
+    let (used0, used1) = amounts_to_mint(sqrt_price, sqrt_a, sqrt_b, liquidity);
+    if used0 < min.0 || used1 < min.1 {
+        return Err(b"Price slippage check".to_vec());
     }
-    
-    fn mock_admin() -> Address {
-        Address::from([6u8; 20])
+    position.last_min = min;
+    for (token, value) in [(mock_usdc(), used0), (mock_pair_token(), used1)] {
+        let pull = IERC20::transferFromCall {
+            from: sender,
+            to: mock_position_manager(),
+            value,
+        }
+        .abi_encode();
+        host.call_as(mock_position_manager(), token, &pull)?;
     }
+    position.liquidity += liquidity;
+    Ok((liquidity, used0, used1))
+}
 
-    #[test]
-    fn test_vault_factory_creation() {
-        // Test that VaultFactory can be created
-        // This is a basic compilation test
-        assert!(true);
-    }
+/// Registers a 0.05% USDC/pair pool trading at `tick` and a position
+/// manager; returns the pool's tick and the vault's position
+fn deploy_uniswap_pool(vm: &SimHost, tick: i32) -> (Rc<Cell<i32>>, Rc<RefCell<MockPosition>>) {
+    let pool_tick = Rc::new(Cell::new(tick));
+    let current = Rc::clone(&pool_tick);
+    vm.register(
+        mock_pool(),
+        Box::new(move |_, data| {
+            if IUniswapV3Pool::token0Call::abi_decode(data, true).is_ok() {
+                return Ok(mock_usdc().into_word().to_vec());
+            }
+            if IUniswapV3Pool::token1Call::abi_decode(data, true).is_ok() {
+                return Ok(mock_pair_token().into_word().to_vec());
+            }
+            if IUniswapV3Pool::feeCall::abi_decode(data, true).is_ok() {
+                return Ok(U256::from(500).to_be_bytes::<32>().to_vec());
+            }
+            if IUniswapV3Pool::tickSpacingCall::abi_decode(data, true).is_ok() {
+                return Ok(U256::from(10).to_be_bytes::<32>().to_vec());
+            }
+            // The price has sat at the current tick for as long as observed
+            if let Ok(call) = IUniswapV3Pool::observeCall::abi_decode(data, true) {
+                let cumulatives = call
+                    .secondsAgos
+                    .iter()
+                    .map(|ago| I56::try_from(i64::from(current.get()) * (100_000 - i64::from(*ago))).unwrap())
+                    .collect::<Vec<_>>();
+                let liquidity = vec![U160::ZERO; cumulatives.len()];
+                return Ok(IUniswapV3Pool::observeCall::abi_encode_returns(&(cumulatives, liquidity)));
+            }
+            IUniswapV3Pool::slot0Call::abi_decode(data, true).map_err(|_| Vec::new())?;
+            let sqrt_price = sqrt_ratio_at_tick(current.get())?;
+            Ok(IUniswapV3Pool::slot0Call::abi_encode_returns(&(
+                sqrt_price.to::<U160>(),
+                I24::try_from(current.get()).unwrap(),
+                0,
+                0,
+                0,
+                0,
+                true,
+            )))
+        }),
+    );
 
-    #[test]
-    fn test_user_vault_creation() {
-        // Test that UserVault can be created
-        // This is a basic compilation test
-        assert!(true);
-    }
+    let position = Rc::new(RefCell::new(MockPosition::default()));
+    let (host, state, current) = (vm.clone(), Rc::clone(&position), Rc::clone(&pool_tick));
+    vm.register(
+        mock_position_manager(),
+        Box::new(move |sender, data| {
+            let sqrt_price = sqrt_ratio_at_tick(current.get())?;
+            let mut position = state.borrow_mut();
+            if let Ok(call) = INonfungiblePositionManager::mintCall::abi_decode(data, true) {
+                let params = call.params;
+                position.tick_lower = params.tickLower.as_i32();
+                position.tick_upper = params.tickUpper.as_i32();
+                let (liquidity, used0, used1) = provide_liquidity(
+                    &host,
+                    sender,
+                    sqrt_price,
+                    &mut position,
+                    params.amount0Desired,
+                    params.amount1Desired,
+                    (params.amount0Min, params.amount1Min),
+                )?;
+                return Ok(INonfungiblePositionManager::mintCall::abi_encode_returns(&(
+                    U256::ONE,
+                    liquidity.to::<u128>(),
+                    used0,
+                    used1,
+                )));
+            }
+            if let Ok(call) = INonfungiblePositionManager::increaseLiquidityCall::abi_decode(data, true) {
+                let params = call.params;
+                assert_eq!(params.tokenId, U256::ONE);
+                let (liquidity, used0, used1) = provide_liquidity(
+                    &host,
+                    sender,
+                    sqrt_price,
+                    &mut position,
+                    params.amount0Desired,
+                    params.amount1Desired,
+                    (params.amount0Min, params.amount1Min),
+                )?;
+                return Ok(INonfungiblePositionManager::increaseLiquidityCall::abi_encode_returns(&(
+                    liquidity.to::<u128>(),
+                    used0,
+                    used1,
+                )));
+            }
+            if let Ok(call) = INonfungiblePositionManager::decreaseLiquidityCall::abi_decode(data, true) {
+                let liquidity = U256::from(call.params.liquidity);
+                let (amount0, amount1) = amounts_for_liquidity(
+                    sqrt_price,
+                    sqrt_ratio_at_tick(position.tick_lower)?,
+                    sqrt_ratio_at_tick(position.tick_upper)?,
+                    liquidity,
+                )?;
+                if amount0 < call.params.amount0Min || amount1 < call.params.amount1Min {
+                    return Err(b"Price slippage check".to_vec());
+                }
+                position.last_min = (call.params.amount0Min, call.params.amount1Min);
+                position.liquidity -= liquidity;
+                position.owed0 += amount0;
+                position.owed1 += amount1;
+                return Ok(INonfungiblePositionManager::decreaseLiquidityCall::abi_encode_returns(&(
+                    amount0, amount1,
+                )));
+            }
+            if let Ok(call) = INonfungiblePositionManager::collectCall::abi_decode(data, true) {
+                let owed = (position.owed0, position.owed1);
+                for (token, value) in [(mock_usdc(), owed.0), (mock_pair_token(), owed.1)] {
+                    let pay = IERC20::transferCall { to: call.params.recipient, value }.abi_encode();
+                    host.call_as(mock_position_manager(), token, &pay)?;
+                }
+                (position.owed0, position.owed1) = (U256::ZERO, U256::ZERO);
+                return Ok(INonfungiblePositionManager::collectCall::abi_encode_returns(&owed));
+            }
+            INonfungiblePositionManager::positionsCall::abi_decode(data, true).map_err(|_| Vec::new())?;
+            Ok(INonfungiblePositionManager::positionsCall::abi_encode_returns(&(
+                U96::ZERO,
+                Address::ZERO,
+                mock_usdc(),
+                mock_pair_token(),
+                U24::from(500),
+                I24::try_from(position.tick_lower).unwrap(),
+                I24::try_from(position.tick_upper).unwrap(),
+                position.liquidity.to::<u128>(),
+                U256::ZERO,
+                U256::ZERO,
+                position.owed0.to::<u128>(),
+                position.owed1.to::<u128>(),
+            )))
+        }),
+    );
+    (pool_tick, position)
+}
 
-    #[test]
-    fn test_strategy_creation() {
-        // Test that strategies can be created
-        // This is a basic compilation test
-        assert!(true);
-    }
+/// Accrues trading fees on the vault's position
+fn accrue_pool_fees(vm: &SimHost, position: &RefCell<MockPosition>, fee0: u64, fee1: u64) {
+    mint_token(vm, mock_usdc(), mock_position_manager(), U256::from(fee0));
+    mint_token(vm, mock_pair_token(), mock_position_manager(), U256::from(fee1));
+    let mut position = position.borrow_mut();
+    position.owed0 += U256::from(fee0);
+    position.owed1 += U256::from(fee1);
+}
 
-    #[test]
-    fn test_erc4626_functions_exist() {
-        // Test that all required ERC-4626 functions are implemented
-        // This is a compilation test to ensure all functions exist
-        
-        // Required ERC-4626 functions:
-        // - asset()
-        // - totalAssets()
-        // - convertToShares()
-        // - convertToAssets()
-        // - maxDeposit()
-        // - maxMint()
-        // - maxWithdraw()
-        // - maxRedeem()
-        // - deposit()
-        // - mint()
-        // - withdraw()
-        // - redeem()
-        
-        assert!(true);
-    }
+/// A vault holding 1,000 idle assets, with a 1:1 router and the mock
+/// pool at `tick` as its Uniswap strategy target
+fn uniswap_vault(vm: &SimHost, tick: i32) -> (UserVault, Rc<Cell<i32>>, Rc<RefCell<MockPosition>>) {
+    let mut vault = init_vault(vm);
+    register_stablecoin(vm, mock_pair_token());
+    register_router(vm, 1);
+    vault.protocol_addresses.setter("uniswap".into()).set(mock_router());
+    let (pool_tick, position) = deploy_uniswap_pool(vm, tick);
 
-    #[test]
-    fn test_erc20_functions_exist() {
-        // Test that all required ERC-20 functions are implemented
-        // This is a compilation test to ensure all functions exist
-        
-        // Required ERC-20 functions:
-        // - name()
-        // - symbol()
-        // - decimals()
-        // - totalSupply()
-        // - balanceOf()
-        // - transfer()
-        // - transferFrom()
-        // - approve()
-        // - allowance()
-        
-        assert!(true);
-    }
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+    vm.set_sender(mock_admin());
+    vault.set_strategy_target("uniswap".into(), mock_pool()).unwrap();
+    (vault, pool_tick, position)
+}
 
-    #[test]
-    fn test_strategy_functions_exist() {
-        // Test that all required strategy functions are implemented
-        // This is a compilation test to ensure all functions exist
-        
-        // Required strategy functions:
-        // - deposit()
-        // - withdraw()
-        // - get_balance()
-        // - harvest()
-        // - get_name()
-        // - get_apy()
-        
-        assert!(true);
-    }
+#[test]
+fn test_sqrt_ratio_at_tick_matches_uniswap() {
+    assert_eq!(sqrt_ratio_at_tick(0).unwrap(), U256::ONE << 96);
+    assert_eq!(sqrt_ratio_at_tick(MIN_TICK).unwrap(), U256::from(4_295_128_739u64));
+    assert_eq!(
+        sqrt_ratio_at_tick(MAX_TICK).unwrap(),
+        "1461446703485210103287273052203988822378723970342".parse::<U256>().unwrap()
+    );
+    assert_eq!(
+        sqrt_ratio_at_tick(100).unwrap(),
+        "79625275426524748796330556128".parse::<U256>().unwrap()
+    );
+    assert_eq!(sqrt_ratio_at_tick(MAX_TICK + 1), Err(b"Tick out of range".to_vec()));
+}
 
-    #[test]
-    fn test_vault_factory_functions_exist() {
-        // Test that all required vault factory functions are implemented
-        // This is a compilation test to ensure all functions exist
-        
-        // Required vault factory functions:
-        // - create_vault()
-        // - get_user_vaults()
-        // - get_vault_owner()
-        // - get_vault_asset()
-        // - get_vault_strategy()
-        // - get_vault_created_at()
-        // - get_vault_username_hash()
-        // - get_vault_bio_hash()
-        // - get_total_vaults()
-        
-        assert!(true);
-    }
+#[test]
+fn test_swap_exact_input_must_end_in_asset() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+    register_stablecoin(&vm, mock_reward_token());
+    mint_token(&vm, mock_reward_token(), vm.contract_address(), U256::from(100));
+    let to_asset = encode_path(&[mock_reward_token(), mock_usdc()]);
+    vm.set_block_timestamp(100);
+    let deadline = U256::from(vm.block_timestamp() + 60);
 
-    #[test]
-    fn test_user_vault_functions_exist() {
-        // Test that all required user vault functions are implemented
-        // This is a compilation test to ensure all functions exist
-        
-        // Required user vault functions:
-        // - initialize()
-        // - deposit()
-        // - withdraw()
-        // - set_strategy()
-        // - pause()
-        // - unpause()
-        // - get_vault_info()
-        // - balance_of()
-        // - total_assets()
-        // - total_supply()
-        
-        assert!(true);
-    }
+    vm.set_sender(mock_admin());
+    assert_eq!(
+        vault.swap_exact_input(to_asset.clone(), U256::from(100), U256::ZERO, deadline),
+        Err(b"Uniswap address not set".to_vec())
+    );
+    deploy_router(&vm, &mut vault);
 
-    #[test]
-    fn test_compilation_success() {
-        // Test that the entire project compiles successfully
-        // This is a basic compilation test
-        assert!(true);
-    }
+    assert_eq!(
+        vault.swap_exact_input(
+            encode_path(&[mock_usdc(), mock_reward_token()]),
+            U256::from(100),
+            U256::ZERO,
+            deadline,
+        ),
+        Err(b"Swap must end in the asset".to_vec())
+    );
+    assert_eq!(
+        vault.swap_exact_input(Bytes::from(mock_usdc().to_vec()), U256::from(100), U256::ZERO, deadline),
+        Err(b"Invalid swap path".to_vec())
+    );
+    assert_eq!(
+        vault.swap_exact_input(to_asset.clone(), U256::from(100), U256::ZERO, U256::from(99)),
+        Err(DeadlineExpired {
+            deadline: U256::from(99),
+            timestamp: U256::from(100),
+        }
+        .abi_encode())
+    );
+    assert_eq!(
+        vault.swap_exact_input(to_asset.clone(), U256::from(100), U256::from(201), deadline),
+        Err(b"Too little received".to_vec())
+    );
 
-    #[test]
-    fn test_contract_sizes() {
-        // Test that contracts are not too large
-        // This is a basic size test
-        assert!(true);
-    }
+    assert_eq!(
+        vault.swap_exact_input(to_asset.clone(), U256::from(100), U256::from(200), deadline).unwrap(),
+        U256::from(200)
+    );
+    assert_eq!(vault.total_assets().unwrap(), U256::from(200));
+    let logs = vm.get_emitted_logs();
+    let (topics, data) = logs.last().unwrap();
+    assert_eq!(topics[0], Swapped::SIGNATURE_HASH);
+    assert_eq!(Swapped::abi_decode_data(data, true).unwrap(), (U256::from(100), U256::from(200)));
 
-    #[test]
-    fn test_event_definitions() {
-        // Test that all required events are defined
-        // This is a compilation test to ensure all events exist
-        
-        // Required events:
-        // - VaultCreated
-        // - Deposit
-        // - Withdraw
-        // - StrategyChanged
-        // - VaultPaused
-        // - VaultUnpaused
-        // - Transfer
-        // - Approval
-        
-        assert!(true);
-    }
+    // A round trip away from the asset and back reports what came out of
+    // the router, not the idle balance net of the assets sold
+    let round_trip = encode_path(&[mock_usdc(), mock_reward_token(), mock_usdc()]);
+    assert_eq!(
+        vault.swap_exact_input(round_trip, U256::from(100), U256::ZERO, deadline).unwrap(),
+        U256::from(200)
+    );
+    assert_eq!(vault.total_assets().unwrap(), U256::from(300));
 
-    #[test]
-    fn test_error_handling() {
-        // Test that error handling is implemented
-        // This is a basic error handling test
-        assert!(true);
-    }
+    vm.set_sender(mock_user());
+    assert!(vault
+        .swap_exact_input(to_asset, U256::from(1), U256::ZERO, deadline)
+        .is_err());
+}
 
-    #[test]
-    fn test_access_control() {
-        // Test that access control is implemented
-        // This is a basic access control test
-        assert!(true);
-    }
+#[test]
+fn test_uniswap_strategy_provides_liquidity_and_collects_fees() {
+    let vm = SimHost::new();
+    let (mut vault, _, position) = uniswap_vault(&vm, 0);
 
-    #[test]
-    fn test_pause_functionality() {
-        // Test that pause functionality is implemented
-        // This is a basic pause test
-        assert!(true);
-    }
+    vault.deploy_to_protocol("uniswap".into(), U256::from(1_000)).unwrap();
+    assert!(position.borrow().liquidity != U256::ZERO);
+    assert_eq!((position.borrow().tick_lower, position.borrow().tick_upper), (-100, 100));
+    assert_eq!(token_balance(&vm, mock_pair_token(), vm.contract_address()), U256::ZERO);
+    // The pool rounds what it charges up and the position is valued
+    // rounding down, so the first mint costs a wei of each token
+    assert_eq!(vault.total_assets().unwrap(), U256::from(998));
 
-    #[test]
-    fn test_strategy_integration() {
-        // Test that strategy integration is implemented
-        // This is a basic strategy integration test
-        assert!(true);
-    }
+    // Fees in both tokens come back as assets and are compounded
+    accrue_pool_fees(&vm, &position, 10, 10);
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_018));
+    assert_eq!(vault.harvest_from_protocol("uniswap".into(), U256::ZERO).unwrap(), U256::from(20));
+    assert_eq!(vault.get_protocol_allocation("uniswap".into()).unwrap(), U256::from(1_020));
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_018));
 
-    #[test]
-    fn test_yield_generation() {
-        // Test that yield generation is implemented
-        // This is a basic yield generation test
-        assert!(true);
+    assert_eq!(
+        vault.withdraw_from_protocol("uniswap".into(), U256::from(300)).unwrap(),
+        U256::from(300)
+    );
+    assert_eq!(vault.withdraw_all_from_protocol("uniswap".into()).unwrap(), U256::from(718));
+    assert_eq!(position.borrow().liquidity, U256::ZERO);
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_018));
+}
+
+#[test]
+fn test_uniswap_allocation_records_only_what_was_deployed() {
+    let vm = SimHost::new();
+    let (mut vault, pool_tick, _) = uniswap_vault(&vm, 0);
+    vault.deploy_to_protocol("uniswap".into(), U256::from(500)).unwrap();
+
+    // Near the top of the range the position mostly wants the pair token, so
+    // much of the second deposit comes back and stays idle
+    pool_tick.set(45);
+    vault.deploy_to_protocol("uniswap".into(), U256::from(500)).unwrap();
+    let idle = token_balance(&vm, mock_usdc(), vm.contract_address());
+    assert!(idle > U256::from(100));
+    assert_eq!(
+        vault.get_protocol_allocation("uniswap".into()).unwrap(),
+        U256::from(1_000) - idle
+    );
+}
+
+/// Pins the mock pool's 30-minute TWAP at `tick`, whatever the spot price does
+fn mock_twap(vm: &SimHost, tick: i64) {
+    let call = IUniswapV3Pool::observeCall {
+        secondsAgos: vec![1_800, 0],
+    };
+    let cumulatives = vec![I56::ZERO, I56::try_from(tick * 1_800).unwrap()];
+    vm.mock_static_call(
+        mock_pool(),
+        call.abi_encode(),
+        Ok(IUniswapV3Pool::observeCall::abi_encode_returns(&(cumulatives, vec![U160::ZERO; 2]))),
+    );
+}
+
+#[test]
+fn test_uniswap_position_is_valued_at_the_twap() {
+    let vm = SimHost::new();
+    let (mut vault, pool_tick, position) = uniswap_vault(&vm, 0);
+    vault.deploy_to_protocol("uniswap".into(), U256::from(1_000)).unwrap();
+
+    // Liquidity only moves with minimums derived from the expected amounts
+    let (min0, min1) = position.borrow().last_min;
+    assert!(min0 != U256::ZERO && min1 != U256::ZERO);
+    vault.withdraw_from_protocol("uniswap".into(), U256::from(100)).unwrap();
+    let (min0, min1) = position.borrow().last_min;
+    assert!(min0 != U256::ZERO && min1 != U256::ZERO);
+    let value = vault.total_assets().unwrap();
+
+    // A flash-loaned spot price moves neither the valuation nor any
+    // liquidity: deposits are refused, and withdrawals expect the amounts at
+    // the TWAP
+    mock_twap(&vm, 0);
+    pool_tick.set(40);
+    assert_eq!(vault.total_assets().unwrap(), value);
+    assert_eq!(
+        vault.deploy_to_protocol("uniswap".into(), U256::from(100)),
+        Err(b"Pool price deviates from TWAP".to_vec())
+    );
+    assert_eq!(
+        vault.withdraw_from_protocol("uniswap".into(), U256::from(100)),
+        Err(b"Price slippage check".to_vec())
+    );
+
+    // Off peg, the pair token the position turned into counts at the TWAP
+    mock_twap(&vm, 120);
+    let off_peg = vault.total_assets().unwrap();
+    assert!(off_peg < value && off_peg > value * U256::from(98) / U256::from(100));
+}
+
+#[test]
+fn test_uniswap_depositors_can_leave_a_depegged_pool() {
+    let vm = SimHost::new();
+    let (mut vault, pool_tick, position) = uniswap_vault(&vm, 0);
+    vault.set_withdrawal_queue(vec!["uniswap".into()]).unwrap();
+    vault.deploy_to_protocol("uniswap".into(), U256::from(900)).unwrap();
+
+    // The pair token trades 1.2% below the asset, and has for a while:
+    // traders sold the pool pair tokens for its assets
+    pool_tick.set(120);
+    mint_token(&vm, mock_pair_token(), mock_position_manager(), U256::from(1_000));
+    assert_eq!(
+        vault.deploy_to_protocol("uniswap".into(), U256::from(1)),
+        Err(b"Pool off peg".to_vec())
+    );
+    // The position is all pair token now, worth about 1% less
+    assert_eq!(vault.total_assets().unwrap(), U256::from(990));
+
+    vm.set_sender(mock_user());
+    let shares = vault.erc20.balance_of(mock_user()).unwrap() / U256::from(2);
+    let expected = vault.preview_redeem(shares).unwrap();
+    assert_eq!(vault.redeem(shares, mock_user(), mock_user()).unwrap(), expected);
+    let (min0, min1) = position.borrow().last_min;
+    assert!(min0 == U256::ZERO && min1 != U256::ZERO);
+
+    vm.set_sender(mock_admin());
+    assert!(vault.emergency_exit("uniswap".into()).unwrap() != U256::ZERO);
+    assert_eq!(position.borrow().liquidity, U256::ZERO);
+    assert_eq!(vault.get_protocol_allocation("uniswap".into()).unwrap(), U256::ZERO);
+}
+
+#[test]
+fn test_uniswap_strategy_requires_a_stable_pool_on_the_asset() {
+    let vm = SimHost::new();
+    let (mut vault, pool_tick, _) = uniswap_vault(&vm, 0);
+
+    let other_pool = Address::from([21u8; 20]);
+    vm.mock_static_call(
+        other_pool,
+        IUniswapV3Pool::token0Call {}.abi_encode(),
+        Ok(mock_pair_token().into_word().to_vec()),
+    );
+    vm.mock_static_call(
+        other_pool,
+        IUniswapV3Pool::token1Call {}.abi_encode(),
+        Ok(mock_reward_token().into_word().to_vec()),
+    );
+    assert_eq!(
+        vault.set_strategy_target("uniswap".into(), other_pool),
+        Err(b"Asset mismatch".to_vec())
+    );
+
+    // Off peg the pool takes no new liquidity, though the vault stays usable
+    pool_tick.set(60);
+    assert_eq!(
+        vault.deploy_to_protocol("uniswap".into(), U256::from(100)),
+        Err(b"Pool off peg".to_vec())
+    );
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_000));
+
+    vault.set_strategy_target("uniswap".into(), Address::ZERO).unwrap();
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_000));
+    assert_eq!(
+        vault.withdraw_from_protocol("uniswap".into(), U256::from(1)),
+        Err(b"Uniswap pool not set".to_vec())
+    );
+}
+
+// ===== REBALANCING TESTS =====
+
+/// A vault holding 1,000 idle assets with Aave and Compound available
+fn rebalancing_vault(vm: &SimHost) -> (UserVault, Rc<RefCell<HashMap<Address, U256>>>) {
+    let mut vault = init_vault(vm);
+    deploy_aave(vm, &mut vault);
+    let comet = deploy_comet(vm, &mut vault);
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
+    vm.set_sender(mock_admin());
+    (vault, comet)
+}
+
+fn moves(items: &[(&str, i64)]) -> (Vec<String>, Vec<I256>) {
+    items
+        .iter()
+        .map(|(protocol, amount)| (protocol.to_string(), I256::try_from(*amount).unwrap()))
+        .unzip()
+}
+
+#[test]
+fn test_target_weights_cannot_exceed_the_vault() {
+    let vm = SimHost::new();
+    let (mut vault, _) = rebalancing_vault(&vm);
+
+    vault.set_target_weight("aave".into(), U256::from(6_000)).unwrap();
+    vault.set_idle_buffer(U256::from(1_000)).unwrap();
+    assert_eq!(
+        vault.set_target_weight("compound".into(), U256::from(3_001)),
+        Err(b"Weights exceed 100%".to_vec())
+    );
+    vault.set_target_weight("compound".into(), U256::from(3_000)).unwrap();
+    assert_eq!(vault.set_idle_buffer(U256::from(1_001)), Err(b"Weights exceed 100%".to_vec()));
+    // Lowering a weight frees room for the others
+    vault.set_target_weight("aave".into(), U256::from(5_000)).unwrap();
+    vault.set_idle_buffer(U256::from(2_000)).unwrap();
+    assert_eq!(vault.get_target_weight("aave".into()).unwrap(), U256::from(5_000));
+    assert_eq!(vault.idle_buffer().unwrap(), U256::from(2_000));
+
+    assert_eq!(
+        vault.set_target_weight("morpho".into(), U256::from(1)),
+        Err(b"Unsupported protocol".to_vec())
+    );
+    // Nothing could be deposited into a protocol without an address
+    assert_eq!(
+        vault.set_target_weight("uniswap".into(), U256::from(1)),
+        Err(b"Protocol not configured".to_vec())
+    );
+    vault.set_target_weight("uniswap".into(), U256::ZERO).unwrap();
+    assert_eq!(vault.set_rebalance_threshold(U256::from(10_001)), Err(b"Invalid threshold".to_vec()));
+
+    vm.set_sender(mock_user());
+    assert!(vault.set_target_weight("aave".into(), U256::ZERO).is_err());
+    assert!(vault.set_idle_buffer(U256::ZERO).is_err());
+    assert!(vault.set_rebalance_threshold(U256::ZERO).is_err());
+}
+
+#[test]
+fn test_rebalance_skips_protocols_whose_address_was_cleared() {
+    let vm = SimHost::new();
+    let (mut vault, _) = rebalancing_vault(&vm);
+    vault.set_target_weight("aave".into(), U256::from(3_000)).unwrap();
+    vault.set_target_weight("compound".into(), U256::from(3_000)).unwrap();
+
+    // The factory dropped Compound after the weights were set
+    vault.protocol_addresses.setter("compound".into()).set(Address::ZERO);
+    assert_eq!(vault.pending_work().unwrap(), WORK_REBALANCE);
+    assert_eq!(vault.rebalance().unwrap(), moves(&[("aave", 300)]));
+    assert_eq!(vault.pending_work().unwrap(), 0);
+}
+
+#[test]
+fn test_rebalance_moves_assets_to_target_weights() {
+    let vm = SimHost::new();
+    let (mut vault, _) = rebalancing_vault(&vm);
+    vault.set_target_weight("aave".into(), U256::from(5_000)).unwrap();
+    vault.set_target_weight("compound".into(), U256::from(3_000)).unwrap();
+    vault.set_idle_buffer(U256::from(1_000)).unwrap();
+
+    // Unweighted assets stay idle along with the buffer
+    assert_eq!(vault.preview_rebalance().unwrap(), moves(&[("aave", 500), ("compound", 300)]));
+    assert_eq!(vault.rebalance().unwrap(), moves(&[("aave", 500), ("compound", 300)]));
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(200, [500, 300, 0, 0])
+    );
+    let logs = vm.get_emitted_logs();
+    let (topics, data) = logs.last().unwrap();
+    assert_eq!(topics[0], Rebalanced::SIGNATURE_HASH);
+    assert_eq!(
+        Rebalanced::abi_decode_data(data, true).unwrap(),
+        moves(&[("aave", 500), ("compound", 300)])
+    );
+    assert_eq!(vault.rebalance(), Err(b"Nothing to rebalance".to_vec()));
+
+    // Over-weight protocols are unwound first to fund the under-weight ones
+    vault.set_target_weight("aave".into(), U256::from(2_000)).unwrap();
+    vault.set_target_weight("compound".into(), U256::from(7_000)).unwrap();
+    assert_eq!(vault.preview_rebalance().unwrap(), moves(&[("aave", -300), ("compound", 400)]));
+    vault.rebalance().unwrap();
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(100, [200, 700, 0, 0])
+    );
+    assert_eq!(vault.get_protocol_allocation("compound".into()).unwrap(), U256::from(700));
+
+    // A protocol weighted to zero is closed completely
+    vault.set_target_weight("aave".into(), U256::ZERO).unwrap();
+    accrue_aave_interest(&vm, U256::from(1));
+    assert_eq!(vault.rebalance().unwrap(), moves(&[("aave", -201)]));
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(301, [0, 700, 0, 0])
+    );
+
+    vm.set_sender(mock_user());
+    assert!(vault.rebalance().is_err());
+    vm.set_sender(mock_admin());
+    vault.pause().unwrap();
+    assert!(vault.rebalance().is_err());
+    assert!(!vault.reentrancy_guard.entered.get());
+}
+
+#[test]
+fn test_rebalance_ignores_drift_within_threshold() {
+    let vm = SimHost::new();
+    let (mut vault, comet) = rebalancing_vault(&vm);
+    vault.set_target_weight("aave".into(), U256::from(5_000)).unwrap();
+    vault.set_target_weight("compound".into(), U256::from(5_000)).unwrap();
+    vault.rebalance().unwrap();
+    vault.set_rebalance_threshold(U256::from(500)).unwrap();
+    assert_eq!(vault.rebalance_threshold().unwrap(), U256::from(500));
+
+    // Compound earns 100: total 1,100, so Compound sits 50 (4.5%) over its target
+    accrue_comet_interest(&vm, &comet, U256::from(100));
+    assert_eq!(vault.preview_rebalance().unwrap(), moves(&[]));
+    assert_eq!(vault.rebalance(), Err(b"Nothing to rebalance".to_vec()));
+
+    // Another 100 takes it 100 (8.3%) over, and Aave 100 under
+    accrue_comet_interest(&vm, &comet, U256::from(100));
+    assert_eq!(
+        vault.preview_rebalance().unwrap(),
+        moves(&[("compound", -100), ("aave", 100)])
+    );
+    vault.rebalance().unwrap();
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(0, [600, 600, 0, 0])
+    );
+}
+
+// ===== ALLOCATION LIST TESTS =====
+
+#[test]
+fn test_all_allocations_lists_active_protocols() {
+    let vm = SimHost::new();
+    let (mut vault, _) = rebalancing_vault(&vm);
+    deploy_target_vault(&vm);
+    vault.set_strategy_target("erc4626".into(), mock_target_vault()).unwrap();
+    assert!(vault.get_active_protocols().unwrap().is_empty());
+
+    vm.set_block_timestamp(100);
+    vault.deploy_to_compound(U256::from(300)).unwrap();
+    vm.set_block_timestamp(200);
+    vault.deploy_to_aave(U256::from(400)).unwrap();
+    vm.set_block_timestamp(300);
+    vault.set_target_weight("erc4626".into(), U256::from(1_000)).unwrap();
+    accrue_aave_interest(&vm, U256::from(10));
+
+    assert_eq!(
+        vault.get_active_protocols().unwrap(),
+        vec!["compound".to_string(), "aave".to_string(), "erc4626".to_string()]
+    );
+    assert_eq!(
+        vault.get_all_allocations().unwrap(),
+        (
+            vec!["compound".to_string(), "aave".to_string(), "erc4626".to_string()],
+            vec![U256::ZERO, U256::ZERO, U256::from(1_000)],
+            vec![U256::from(300), U256::from(400), U256::ZERO],
+            vec![U256::from(300), U256::from(410), U256::ZERO],
+            vec![U256::from(100), U256::from(200), U256::from(300)],
+        )
+    );
+
+    // Protocols leave the list once they have neither an allocation nor a weight
+    vm.set_block_timestamp(400);
+    vault.withdraw_from_compound(U256::from(300)).unwrap();
+    vault.set_target_weight("erc4626".into(), U256::ZERO).unwrap();
+    vault.withdraw_from_aave(U256::from(100)).unwrap();
+    assert_eq!(
+        vault.get_all_allocations().unwrap(),
+        (
+            vec!["aave".to_string()],
+            vec![U256::ZERO],
+            vec![U256::from(300)],
+            vec![U256::from(310)],
+            vec![U256::from(400)],
+        )
+    );
+    vault.emergency_exit("aave".into()).unwrap();
+    assert!(vault.get_active_protocols().unwrap().is_empty());
+}
+
+// ===== STRATEGIST TESTS =====
+
+fn mock_strategist() -> Address {
+    Address::from([22u8; 20])
+}
+
+#[test]
+fn test_strategist_manages_positions_within_target_weights() {
+    let vm = SimHost::new();
+    let (mut vault, _) = rebalancing_vault(&vm);
+    vault.set_target_weight("aave".into(), U256::from(4_000)).unwrap();
+    vault.set_target_weight("compound".into(), U256::from(4_000)).unwrap();
+
+    vm.set_sender(mock_strategist());
+    assert!(vault.set_strategist(mock_strategist()).is_err());
+    vm.set_sender(mock_admin());
+    vault.set_strategist(mock_strategist()).unwrap();
+    assert_eq!(vault.strategist().unwrap(), mock_strategist());
+    let logs = vm.get_emitted_logs();
+    let (topics, _) = logs.last().unwrap();
+    assert_eq!(topics[0], StrategistUpdated::SIGNATURE_HASH);
+
+    vm.set_sender(mock_strategist());
+    vault.deploy_to_aave(U256::from(300)).unwrap();
+    assert_eq!(vault.deploy_to_aave(U256::from(101)), Err(b"Exceeds target weight".to_vec()));
+    vault.deploy_to_aave(U256::from(100)).unwrap();
+    assert_eq!(
+        vault.deploy_to_protocol("erc4626".into(), U256::from(1)),
+        Err(b"Exceeds target weight".to_vec())
+    );
+    assert_eq!(vault.withdraw_from_aave(U256::from(150)).unwrap(), U256::from(150));
+    assert_eq!(vault.rebalance().unwrap(), moves(&[("aave", 150), ("compound", 400)]));
+
+    // Nothing that changes the targets or moves assets out of the vault
+    assert!(vault.set_target_weight("aave".into(), U256::from(9_000)).is_err());
+    assert!(vault.emergency_exit("aave".into()).is_err());
+    assert!(vault.set_rewards_config("aave".into(), mock_strategist(), mock_usdc()).is_err());
+    assert!(vault
+        .swap_exact_input(Bytes::from(mock_usdc().to_vec()), U256::from(1), U256::ZERO, U256::MAX)
+        .is_err());
+    assert!(vault
+        .withdraw(U256::from(1), mock_strategist(), mock_user())
+        .is_err());
+
+    // The owner is not bound by the weights, and can revoke the strategist
+    vm.set_sender(mock_admin());
+    vault.deploy_to_aave(U256::from(100)).unwrap();
+    vault.set_strategist(Address::ZERO).unwrap();
+    vm.set_sender(mock_strategist());
+    assert!(vault.withdraw_from_aave(U256::from(1)).is_err());
+    assert!(vault.rebalance().is_err());
+}
+
+#[test]
+fn test_strategist_can_harvest() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    deploy_rewards(&vm, &mut vault, mock_usdc(), 25);
+    vault.set_strategist(mock_strategist()).unwrap();
+
+    vm.set_sender(mock_strategist());
+    assert_eq!(vault.harvest_from_protocol("aave".into(), U256::ZERO).unwrap(), U256::from(25));
+    vm.set_sender(mock_user());
+    assert!(vault.harvest_from_protocol("aave".into(), U256::ZERO).is_err());
+}
+
+#[test]
+fn test_strategist_harvest_is_bounded_by_the_owner() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    register_stablecoin(&vm, mock_reward_token());
+    deploy_router(&vm, &mut vault);
+    deploy_rewards(&vm, &mut vault, mock_reward_token(), 25);
+    vault.set_strategist(mock_strategist()).unwrap();
+    vault.set_target_weight("aave".into(), U256::from(4_000)).unwrap();
+
+    // Without a reference price the strategist can't sell at all
+    vm.set_sender(mock_strategist());
+    assert_eq!(
+        vault.harvest_from_protocol("aave".into(), U256::ZERO),
+        Err(b"Reward price not set".to_vec())
+    );
+    assert!(vault.set_reward_price(mock_reward_token(), U256::from(1)).is_err());
+    assert!(vault.set_max_harvest_slippage(U256::from(10_000)).is_err());
+
+    // The router pays two assets per reward token; a price of three less 10%
+    // puts the floor at 68 whatever the strategist passes
+    vm.set_sender(mock_admin());
+    let price = U256::from(3) * U256::from(10).pow(U256::from(18));
+    vault.set_reward_price(mock_reward_token(), price).unwrap();
+    assert_eq!(vault.set_max_harvest_slippage(U256::from(10_001)), Err(b"Invalid slippage".to_vec()));
+    vault.set_max_harvest_slippage(U256::from(1_000)).unwrap();
+    assert_eq!(vault.get_reward_price(mock_reward_token()).unwrap(), price);
+    assert_eq!(vault.max_harvest_slippage().unwrap(), U256::from(1_000));
+    vm.set_sender(mock_strategist());
+    assert_eq!(
+        vault.harvest_from_protocol("aave".into(), U256::ZERO),
+        Err(b"Too little received".to_vec())
+    );
+
+    // The test host doesn't roll back the reverted calls, so the vault now
+    // holds 75 reward tokens, and sells all of them. At two apiece the 150
+    // proceeds clear the floor of 135. Aave's 75 of them only fit up to its
+    // weight (40% of 1,150) and Compound has no weight, so the rest stays idle.
+    vm.set_sender(mock_admin());
+    vault.set_reward_price(mock_reward_token(), price * U256::from(2) / U256::from(3)).unwrap();
+    vm.set_sender(mock_strategist());
+    assert_eq!(vault.harvest_from_protocol("aave".into(), U256::ZERO).unwrap(), U256::from(150));
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(290, [460, 400, 0, 0])
+    );
+}
+
+// ===== PERMISSIONLESS HARVEST TESTS =====
+
+fn mock_keeper() -> Address {
+    Address::from([23u8; 20])
+}
+
+#[test]
+fn test_harvest_config_is_owner_only_and_bounded() {
+    let vm = SimHost::new();
+    let mut vault = init_vault(&vm);
+
+    vm.set_sender(mock_keeper());
+    assert_eq!(vault.harvest(), Err(b"Harvest not enabled".to_vec()));
+    assert!(vault.set_harvest_config(U256::from(3_600), U256::from(100), U256::from(10)).is_err());
+
+    vm.set_sender(mock_admin());
+    assert_eq!(
+        vault.set_harvest_config(U256::from(3_600), U256::from(10_001), U256::from(10)),
+        Err(b"Invalid bounty".to_vec())
+    );
+    vault.set_harvest_config(U256::from(3_600), U256::from(100), U256::from(10)).unwrap();
+    assert_eq!(
+        vault.get_harvest_config().unwrap(),
+        (U256::from(3_600), U256::from(100), U256::from(10), U256::ZERO)
+    );
+}
+
+#[test]
+fn test_anyone_can_harvest_for_a_capped_bounty() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    register_stablecoin(&vm, mock_reward_token());
+    deploy_router(&vm, &mut vault);
+    deploy_rewards(&vm, &mut vault, mock_reward_token(), 25);
+    vault.set_target_weight("aave".into(), U256::from(4_500)).unwrap();
+    vault.set_target_weight("compound".into(), U256::from(4_500)).unwrap();
+    vault.set_reward_price(mock_reward_token(), U256::from(2) * U256::from(10).pow(U256::from(18))).unwrap();
+    // 10% of the proceeds, but never more than 8
+    vault.set_harvest_config(U256::from(3_600), U256::from(1_000), U256::from(8)).unwrap();
+
+    // Each protocol pays 25 reward tokens, sold at two assets apiece
+    vm.set_block_timestamp(10_000);
+    vm.set_sender(mock_keeper());
+    assert_eq!(vault.harvest().unwrap(), U256::from(8));
+    assert_eq!(token_balance(&vm, mock_usdc(), mock_keeper()), U256::from(8));
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_092));
+    assert_eq!(
+        vault.get_position_breakdown().unwrap(),
+        breakdown(200, [446, 446, 0, 0])
+    );
+    let logs = vm.get_emitted_logs();
+    let (topics, data) = logs.last().unwrap();
+    assert_eq!(topics[0], Harvested::SIGNATURE_HASH);
+    assert_eq!(Harvested::abi_decode_data(data, true).unwrap(), (U256::from(100), U256::from(8)));
+
+    vm.set_block_timestamp(13_599);
+    assert_eq!(vault.harvest(), Err(b"Harvest too soon".to_vec()));
+    vm.set_block_timestamp(13_600);
+    vault.harvest().unwrap();
+    assert_eq!(vault.get_harvest_config().unwrap().3, U256::from(13_600));
+
+    vm.set_block_timestamp(20_000);
+    vm.set_sender(mock_admin());
+    vault.pause().unwrap();
+    vm.set_sender(mock_keeper());
+    assert!(vault.harvest().is_err());
+    assert!(!vault.reentrancy_guard.entered.get());
+}
+
+#[test]
+fn test_harvest_skips_protocols_without_rewards() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    vault.set_harvest_config(U256::from(60), U256::from(1_000), U256::MAX).unwrap();
+
+    // Neither position has a rewards config, so nothing is claimed or paid,
+    // and the next harvest may come straight away
+    vm.set_block_timestamp(100);
+    vm.set_sender(mock_keeper());
+    assert_eq!(vault.harvest().unwrap(), U256::ZERO);
+    assert_eq!(vault.total_assets().unwrap(), U256::from(1_000));
+    assert_eq!(vault.get_harvest_config().unwrap().3, U256::ZERO);
+}
+
+#[test]
+fn test_harvest_leaves_unpriced_rewards_for_a_manager_to_sell() {
+    let vm = SimHost::new();
+    let mut vault = vault_with_positions(&vm);
+    register_stablecoin(&vm, mock_reward_token());
+    deploy_router(&vm, &mut vault);
+    deploy_rewards(&vm, &mut vault, mock_reward_token(), 25);
+    vault.set_harvest_config(U256::from(3_600), U256::from(1_000), U256::MAX).unwrap();
+
+    // Nothing is sold without a reference price, so there is no bounty either
+    vm.set_block_timestamp(10_000);
+    vm.set_sender(mock_keeper());
+    assert_eq!(vault.harvest().unwrap(), U256::ZERO);
+    assert_eq!(token_balance(&vm, mock_reward_token(), vm.contract_address()), U256::from(50));
+    assert_eq!(vault.get_harvest_config().unwrap().3, U256::from(10_000));
+
+    // The owner sells everything held along with the next claim
+    vm.set_sender(mock_admin());
+    assert_eq!(vault.harvest_from_protocol("aave".into(), U256::from(150)).unwrap(), U256::from(150));
+    assert_eq!(token_balance(&vm, mock_reward_token(), vm.contract_address()), U256::ZERO);
+}
+
+// ===== KEEPER RESOLVER TESTS =====
+
+/// Puts a vault holding 1,000 of the user's assets at `address`, letting
+/// the admin configure it before it is registered
+fn deploy_vault_at(vm: &SimHost, address: Address, configure: impl FnOnce(&SimHost, &mut UserVault)) {
+    let host = vm.at(address);
+    host.vm.set_sender(mock_admin());
+    let mut vault = UserVault::from(&host);
+    initialize_vault(&mut vault, mock_usdc(), 0);
+
+    let approve = IERC20::approveCall {
+        spender: address,
+        value: U256::MAX,
     }
+    .abi_encode();
+    vm.call_as(mock_user(), mock_usdc(), &approve).unwrap();
+    host.vm.set_sender(mock_user());
+    vault.deposit(U256::from(1_000), mock_user()).unwrap();
 
-    #[test]
-    fn test_share_calculations() {
-        // Test that share calculations are implemented
-        // This is a basic share calculation test
-        assert!(true);
+    host.vm.set_sender(mock_admin());
+    configure(&host, &mut vault);
+    vm.register_contract(address, Rc::new(RefCell::new(vault)), host.vm.clone());
+}
+
+/// Address of the `index`th vault a test deploys next to a factory
+fn deployed_vault(index: u8) -> Address {
+    Address::from([50 + index; 20])
+}
+
+/// Deploys a factory at `mock_factory()` that can call the vaults on `vm`,
+/// with the admin registered as a user; returns its host and the factory
+fn deploy_shared_factory(vm: &SimHost) -> (SimHost, Rc<RefCell<VaultFactory>>) {
+    let host = vm.at(mock_factory());
+    let factory = Rc::new(RefCell::new(VaultFactory::from(&host)));
+    host.vm.set_sender(mock_admin());
+    factory.borrow_mut().init().unwrap();
+    factory.borrow_mut().register_user("alice".into(), "saver".into()).unwrap();
+    vm.register_contract(mock_factory(), Rc::clone(&factory), host.vm.clone());
+    (host, factory)
+}
+
+/// Deploys a vault at `deployed_vault(index)` like `deploy_vault_at` and has
+/// the admin register it with the factory
+fn register_deployed_vault(
+    vm: &SimHost,
+    factory_host: &SimHost,
+    factory: &RefCell<VaultFactory>,
+    index: u8,
+    configure: impl FnOnce(&SimHost, &mut UserVault),
+) -> Address {
+    let address = deployed_vault(index);
+    deploy_vault_at(vm, address, configure);
+    factory_host.vm.set_sender(mock_admin());
+    factory.borrow_mut().register_vault(address).unwrap();
+    address
+}
+
+#[test]
+fn test_pending_work_flags_keeper_calls_that_would_succeed() {
+    let vm = SimHost::new();
+    let (mut vault, _) = rebalancing_vault(&vm);
+    assert_eq!(vault.pending_work().unwrap(), 0);
+
+    vault.set_target_weight("aave".into(), U256::from(6_000)).unwrap();
+    assert_eq!(vault.pending_work().unwrap(), WORK_REBALANCE);
+    vault.rebalance().unwrap();
+    assert_eq!(vault.pending_work().unwrap(), 0);
+
+    // Harvesting is due, but no protocol has rewards to claim yet
+    vault.set_harvest_config(U256::from(3_600), U256::ZERO, U256::ZERO).unwrap();
+    vm.set_block_timestamp(3_600);
+    assert_eq!(vault.pending_work().unwrap(), 0);
+
+    register_stablecoin(&vm, mock_reward_token());
+    deploy_rewards(&vm, &mut vault, mock_reward_token(), 100);
+    assert_eq!(vault.pending_work().unwrap(), WORK_HARVEST);
+    vm.set_block_timestamp(3_599);
+    assert_eq!(vault.pending_work().unwrap(), 0);
+    vm.set_block_timestamp(3_600);
+
+    // Nothing can run while paused
+    vault.pause().unwrap();
+    assert_eq!(vault.pending_work().unwrap(), 0);
+}
+
+#[test]
+fn test_idle_assets_above_buffer_are_put_to_work() {
+    let vm = SimHost::new();
+    let (mut vault, _) = rebalancing_vault(&vm);
+    vault.set_target_weight("aave".into(), U256::from(2_500)).unwrap();
+    vault.set_target_weight("compound".into(), U256::from(2_500)).unwrap();
+    vault.set_idle_buffer(U256::from(5_000)).unwrap();
+    vault.set_rebalance_threshold(U256::from(1_000)).unwrap();
+    vault.rebalance().unwrap();
+
+    // 300 more: each protocol is 75 short (5.8%), but idle is 150 over the buffer (11.5%)
+    vm.set_sender(mock_user());
+    vault.deposit(U256::from(300), mock_user()).unwrap();
+    assert_eq!(vault.pending_work().unwrap(), WORK_IDLE);
+
+    vm.set_sender(mock_admin());
+    assert_eq!(vault.rebalance().unwrap(), moves(&[("aave", 75), ("compound", 75)]));
+    assert_eq!(vault.pending_work().unwrap(), 0);
+}
+
+#[test]
+fn test_factory_registers_deployed_vaults_it_owns() {
+    let vm = SimHost::new();
+    deploy_usdc(&vm);
+    let (factory_host, factory) = deploy_shared_factory(&vm);
+    let vault = deployed_vault(0);
+    deploy_vault_at(&vm, vault, |_, _| {});
+
+    factory_host.vm.set_sender(mock_user());
+    assert_eq!(factory.borrow_mut().register_vault(vault), Err(b"User not registered".to_vec()));
+    factory.borrow_mut().register_user("bob".into(), "".into()).unwrap();
+    assert_eq!(factory.borrow_mut().register_vault(vault), Err(b"Not vault owner".to_vec()));
+
+    factory_host.vm.set_sender(mock_admin());
+    // A made-up address, like the ones `create_vault` hands out
+    assert_eq!(
+        factory.borrow_mut().register_vault(deployed_vault(1)),
+        Err(b"Vault has no code".to_vec())
+    );
+    assert_eq!(factory.borrow_mut().register_vault(vault).unwrap(), U256::ONE);
+    assert_eq!(
+        factory.borrow_mut().register_vault(vault),
+        Err(b"Vault already registered".to_vec())
+    );
+    let logs = factory_host.vm.get_emitted_logs();
+    assert_eq!(logs.last().unwrap().0[0], VaultRegistered::SIGNATURE_HASH);
+    assert!(factory.borrow().is_deployed_vault(vault).unwrap());
+    assert_eq!(factory.borrow().get_deployed_vault_count().unwrap(), U256::ONE);
+    assert_eq!(factory.borrow().get_user_vaults(mock_admin()).unwrap(), vec![vault]);
+    assert_eq!(factory.borrow().get_vault_owner(vault).unwrap(), mock_admin());
+
+    // The vault was initialized with the first factory
+    let other_host = vm.at(Address::from([27u8; 20]));
+    other_host.vm.set_sender(mock_admin());
+    let mut other = VaultFactory::from(&other_host);
+    other.init().unwrap();
+    other.register_user("alice".into(), "saver".into()).unwrap();
+    assert_eq!(other.register_vault(vault), Err(b"Vault has another factory".to_vec()));
+}
+
+#[test]
+fn test_factory_resolves_and_performs_upkeep() {
+    let vm = SimHost::new();
+    deploy_usdc(&vm);
+    register_stablecoin(&vm, mock_reward_token());
+    let (factory_host, factory) = deploy_shared_factory(&vm);
+    register_deployed_vault(&vm, &factory_host, &factory, 0, |_, _| {});
+    let harvestable = register_deployed_vault(&vm, &factory_host, &factory, 1, |host, vault| {
+        deploy_aave(&vm, vault);
+        deploy_router(&vm, vault);
+        deploy_rewards(&vm, vault, mock_reward_token(), 25);
+        vault.deploy_to_protocol("aave".into(), U256::from(400)).unwrap();
+        vault.set_target_weight("aave".into(), U256::from(4_000)).unwrap();
+        vault.set_reward_price(mock_reward_token(), U256::from(2) * U256::from(10).pow(U256::from(18))).unwrap();
+        vault.set_harvest_config(U256::from(60), U256::from(1_000), U256::from(100)).unwrap();
+        host.vm.set_block_timestamp(60);
+    });
+    // Drifted, but only its owner may rebalance it. Both use the Aave pool
+    // registered above, which holds the first vault's aTokens.
+    register_deployed_vault(&vm, &factory_host, &factory, 2, |_, vault| {
+        vault.protocol_addresses.setter("aave".into()).set(mock_aave_pool());
+        vault.set_target_weight("aave".into(), U256::from(5_000)).unwrap();
+    });
+    let delegated = register_deployed_vault(&vm, &factory_host, &factory, 3, |_, vault| {
+        vault.protocol_addresses.setter("aave".into()).set(mock_aave_pool());
+        vault.set_target_weight("aave".into(), U256::from(5_000)).unwrap();
+        vault.set_strategist(mock_factory()).unwrap();
+    });
+    let broken = register_deployed_vault(&vm, &factory_host, &factory, 4, |_, _| {});
+    let pending_work = IUserVault::pendingWorkCall {}.abi_encode();
+    factory_host.vm.mock_static_call(broken, pending_work, Err(b"boom".to_vec()));
+
+    let work = vec![harvestable, delegated];
+    let flags = vec![WORK_HARVEST, WORK_REBALANCE];
+    assert_eq!(
+        factory.borrow().vaults_needing_work(U256::ZERO, U256::from(10)).unwrap(),
+        (work.clone(), flags.clone(), vec![broken])
+    );
+    assert_eq!(
+        factory.borrow().vaults_needing_work(U256::from(2), U256::ONE).unwrap(),
+        (Vec::new(), Vec::new(), Vec::new())
+    );
+
+    let perform_data = PerformData::abi_encode_params(&(work.clone(), flags.clone()));
+    assert_eq!(
+        factory.borrow().check_upkeep(Vec::new().into()).unwrap(),
+        (true, perform_data.clone().into())
+    );
+    let first_vault = <(sol_data::Uint<256>, sol_data::Uint<256>)>::abi_encode_params(&(U256::ZERO, U256::ONE));
+    assert_eq!(
+        factory.borrow().check_upkeep(first_vault.into()).unwrap(),
+        (false, PerformData::abi_encode_params(&(Vec::<Address>::new(), Vec::<u8>::new())).into())
+    );
+    assert_eq!(
+        factory.borrow().check_upkeep(vec![1u8; 3].into()),
+        Err(b"Invalid check data".to_vec())
+    );
+    let payload = IKeeperFactory::performUpkeepCall { performData: perform_data.clone().into() }.abi_encode();
+    assert_eq!(factory.borrow().checker().unwrap(), (true, payload.into()));
+
+    factory_host.vm.set_sender(mock_keeper());
+    let unregistered = PerformData::abi_encode_params(&(vec![mock_user()], vec![WORK_HARVEST]));
+    assert_eq!(
+        factory.borrow_mut().perform_upkeep(unregistered.into()),
+        Err(b"Vault not registered".to_vec())
+    );
+
+    // The broken vault is logged and skipped; the others are done
+    let mut listed = work.clone();
+    listed.push(broken);
+    let mut listed_flags = flags.clone();
+    listed_flags.push(WORK_HARVEST);
+    let listed = PerformData::abi_encode_params(&(listed, listed_flags));
+    factory.borrow_mut().perform_upkeep(listed.into()).unwrap();
+    assert!(!factory.borrow().reentrancy_guard.entered.get());
+    let logs = factory_host.vm.get_emitted_logs();
+    let (topics, data) = logs.last().unwrap();
+    assert_eq!(topics[0], UpkeepFailed::SIGNATURE_HASH);
+    assert_eq!(topics[1], broken.into_word());
+    assert_eq!(UpkeepFailed::abi_decode_data(data, true).unwrap().0, WORK_HARVEST);
+    // 25 reward tokens sold at two assets apiece, 10% of which goes to the keeper
+    assert_eq!(token_balance(&vm, mock_usdc(), mock_keeper()), U256::from(5));
+    assert_eq!(token_balance(&vm, mock_usdc(), mock_factory()), U256::ZERO);
+    assert_eq!(
+        factory.borrow().vaults_needing_work(U256::ZERO, U256::from(10)).unwrap(),
+        (Vec::new(), Vec::new(), vec![broken])
+    );
+    assert_eq!(factory.borrow().checker().unwrap(), (false, b"Vault checks failed".to_vec().into()));
+
+    // A vault calling back into the factory mid-upkeep finds it locked
+    factory.borrow_mut().reentrancy_guard.entered.set(true);
+    assert_eq!(
+        factory.borrow_mut().perform_upkeep(perform_data.clone().into()),
+        Err(reentrant_call_error())
+    );
+    factory_host.vm.set_sender(mock_admin());
+    assert_eq!(
+        factory.borrow_mut().refresh_vault_protocol_addresses(U256::ZERO, U256::from(10)),
+        Err(reentrant_call_error())
+    );
+    factory.borrow_mut().reentrancy_guard.entered.set(false);
+
+    factory.borrow_mut().pause().unwrap();
+    assert!(factory.borrow_mut().perform_upkeep(perform_data.into()).is_err());
+}
+
+// ===== MATH TESTS =====
+
+mod mul_div_properties {
+    use super::*;
+    use num_bigint::BigUint;
+    use proptest::prelude::*;
+
+    fn to_big(value: U256) -> BigUint {
+        BigUint::from_bytes_be(&value.to_be_bytes::<32>())
     }
 
-    #[test]
-    fn test_asset_management() {
-        // Test that asset management is implemented
-        // This is a basic asset management test
-        assert!(true);
+    /// Reference implementation on arbitrary-precision integers
+    fn reference(x: U256, y: U256, denominator: U256, rounding: Rounding) -> Option<U256> {
+        let product = to_big(x) * to_big(y);
+        let denominator = to_big(denominator);
+        let mut quotient = &product / &denominator;
+        if rounding == Rounding::Ceil && product % &denominator != BigUint::ZERO {
+            quotient += 1u8;
+        }
+        let bytes = quotient.to_bytes_be();
+        (bytes.len() <= 32).then(|| U256::from_be_slice(&bytes))
     }
 
-    #[test]
-    fn test_user_metadata() {
-        // Test that user metadata is implemented
-        // This is a basic user metadata test
-        assert!(true);
+    fn any_u256() -> impl Strategy<Value = U256> {
+        prop_oneof![
+            any::<[u8; 32]>().prop_map(U256::from_be_bytes),
+            any::<u128>().prop_map(U256::from),
+            any::<u64>().prop_map(U256::from),
+            Just(U256::MAX),
+            Just(U256::ONE),
+        ]
     }
 
-    #[test]
-    fn test_vault_factory_metadata() {
-        // Test that vault factory metadata is implemented
-        // This is a basic vault factory metadata test
-        assert!(true);
+    fn any_rounding() -> impl Strategy<Value = Rounding> {
+        prop_oneof![Just(Rounding::Floor), Just(Rounding::Ceil)]
     }
 
-    #[test]
-    fn test_strategy_metadata() {
-        // Test that strategy metadata is implemented
-        // This is a basic strategy metadata test
-        assert!(true);
+    proptest! {
+        #[test]
+        fn matches_big_integer_reference(
+            x in any_u256(),
+            y in any_u256(),
+            denominator in any_u256(),
+            rounding in any_rounding(),
+        ) {
+            prop_assume!(denominator != U256::ZERO);
+            match reference(x, y, denominator, rounding) {
+                Some(expected) => prop_assert_eq!(mul_div(x, y, denominator, rounding).unwrap(), expected),
+                None => prop_assert!(mul_div(x, y, denominator, rounding).is_err()),
+            }
+        }
+
+        #[test]
+        fn ceil_is_floor_plus_remainder_bit(
+            x in any_u256(),
+            y in any_u256(),
+            denominator in any_u256(),
+        ) {
+            prop_assume!(denominator != U256::ZERO);
+            if let (Ok(floor), Ok(ceil)) = (
+                mul_div(x, y, denominator, Rounding::Floor),
+                mul_div(x, y, denominator, Rounding::Ceil),
+            ) {
+                prop_assert!(ceil == floor || ceil == floor + U256::ONE);
+            }
+        }
     }
 
     #[test]
-    fn test_integration_ready() {
-        // Test that the contracts are ready for integration
-        // This is a basic integration readiness test
-        assert!(true);
+    fn rejects_zero_denominator_and_overflow() {
+        assert!(matches!(
+            mul_div(U256::ONE, U256::ONE, U256::ZERO, Rounding::Floor),
+            Err(MathError::OverflowedMulDiv(_))
+        ));
+        assert!(mul_div(U256::MAX, U256::from(2), U256::ONE, Rounding::Floor).is_err());
+        // MAX * MAX / MAX fits even though the product needs 512 bits
+        assert_eq!(
+            mul_div(U256::MAX, U256::MAX, U256::MAX, Rounding::Ceil).unwrap(),
+            U256::MAX
+        );
     }
 }
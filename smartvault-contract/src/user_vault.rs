@@ -1,34 +1,51 @@
 use alloc::{vec::Vec, string::{String, ToString}};
 use stylus_sdk::{
-    alloy_primitives::{U256, Address},
-    alloy_sol_types::sol,
+    alloy_primitives::{address, I256, U256, Address},
+    alloy_sol_types::{sol, SolError},
+    abi::Bytes,
     prelude::*,
-    call::RawCall,
-    function_selector,
+    stylus_core::Host,
 };
 
+use crate::erc20::{Erc20, Erc20Params};
+use crate::math::{mul_div, Rounding};
+use crate::ownable::Ownable;
+use crate::pausable::Pausable;
+use crate::reentrancy_guard::ReentrancyGuard;
+use crate::safe_erc20;
+use crate::strategies::{self, uniswap, Strategy, StrategyHost};
+
+/// Compile-time defaults for the vault share token
+pub struct VaultShareParams;
+
+impl Erc20Params for VaultShareParams {
+    const NAME: &'static str = "Vault Shares";
+    const SYMBOL: &'static str = "VS";
+    const DECIMALS: u8 = 18;
+}
+
 sol_storage! {
     pub struct UserVault {
-        /// Vault owner
-        address owner;
+        /// Vault owner (two-step transferable)
+        #[borrow]
+        Ownable ownable;
         /// Underlying asset (USDC, USDT, ETH, etc.)
         address asset;
         /// Vault factory address (to get protocol addresses)
         address factory;
-        /// Total assets in vault
-        uint256 total_assets;
-        /// Total shares issued
-        uint256 total_supply;
-        /// User's share balance
-        mapping(address => uint256) balances;
-        /// User's allowances
-        mapping(address => mapping(address => uint256)) allowances;
+        /// Share token (balances, allowances and total supply)
+        #[borrow]
+        Erc20<VaultShareParams> erc20;
         /// Vault metadata
         bytes32 username_hash;
         bytes32 bio_hash;
         uint256 created_at;
-        /// Whether vault is paused
-        bool paused;
+        /// Emergency stop
+        #[borrow]
+        Pausable pausable;
+        /// Lock for entry points that call external contracts
+        #[borrow]
+        ReentrancyGuard reentrancy_guard;
         /// Vault name (configurable) - stored as hash
         bytes32 vault_name_hash;
         /// Vault symbol (configurable) - stored as hash
@@ -47,24 +64,174 @@ sol_storage! {
         mapping(string => uint256) protocol_allocations;
         /// Protocol addresses (cached from factory)
         mapping(string => address) protocol_addresses;
+
+        /// Inflation attack protection: extra share decimals (virtual shares = 10^offset)
+        uint8 decimals_offset;
+        /// Inflation attack protection: assets whose shares are burned on the first deposit
+        uint256 min_initial_deposit;
+
+        /// Maximum total assets the vault accepts (0 = no cap)
+        uint256 deposit_cap;
+        /// Maximum assets a single receiver may hold in the vault (0 = no cap)
+        uint256 per_depositor_cap;
+
+        /// Asset accounting mode (see `AssetMode`), fixed at initialization
+        uint8 asset_mode;
+
+        /// Protocols unwound in order when idle assets cannot cover a withdrawal
+        string[] withdrawal_queue;
+        /// Largest shortfall a withdrawal accepts from unwinding, in basis points of the assets
+        uint256 max_loss_bps;
+
+        /// Numbers strategies keep between calls (e.g. a position NFT id)
+        mapping(string => uint256) strategy_values;
+
+        /// Share of total assets each protocol should hold, in basis points
+        mapping(string => uint256) target_weights_bps;
+        /// Share of total assets rebalancing keeps idle, in basis points
+        uint256 idle_buffer_bps;
+        /// Drift from target, in basis points of total assets, a protocol needs before rebalancing moves it
+        uint256 rebalance_threshold_bps;
+
+        /// Protocols with an allocation or a target weight, in the order they became active
+        string[] active_protocols;
+        /// When each protocol's allocation or target weight last changed
+        mapping(string => uint256) allocation_updated_at;
+
+        /// Delegate that may deploy, withdraw from protocols, harvest and rebalance (zero = none)
+        address strategist;
+
+        /// Seconds between permissionless harvests (0 = only the owner and strategist harvest)
+        uint256 harvest_interval;
+        /// When `harvest` last ran
+        uint256 last_harvest;
+        /// Share of the harvest paid to its caller, in basis points
+        uint256 harvest_bounty_bps;
+        /// Most a single harvest pays its caller, in the asset
+        uint256 max_harvest_bounty;
+
+        /// Owner's reference price of each reward token: asset units per
+        /// reward unit, scaled by `PRICE_SCALE` (0 = not priced)
+        mapping(address => uint256) reward_prices;
+        /// How far below the reference price anyone but the owner may sell rewards, in basis points
+        uint256 max_harvest_slippage_bps;
+    }
+}
+
+/// How the vault accounts for its underlying asset, chosen at `initialize`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetMode {
+    /// Plain ERC-20: deposits are credited as requested
+    Standard = 0,
+    /// Fee-on-transfer or rebasing: deposits are credited by the measured
+    /// balance increase. `mint` is disabled, since the pull that delivers the
+    /// assets for exact shares isn't known up front.
+    FeeOnTransfer = 1,
+}
+
+impl AssetMode {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Standard),
+            1 => Some(Self::FeeOnTransfer),
+            _ => None,
+        }
     }
 }
 
+/// Receives the shares burned on the first deposit
+const DEAD_ADDRESS: Address = address!("000000000000000000000000000000000000dEaD");
+
+/// Largest accepted decimals offset; keeps `10^offset` far from overflow
+const MAX_DECIMALS_OFFSET: u8 = 18;
+
+/// Basis-point denominator for loss bounds and target weights
+const MAX_BPS: u64 = 10_000;
+
+/// Uniswap pool fee tier used to sell harvested rewards (0.3%)
+const REWARD_SWAP_FEE: u32 = 3_000;
+
+/// Fixed-point scale of reward prices
+const PRICE_SCALE: u128 = 1_000_000_000_000_000_000;
+
+/// `pending_work` flag: a protocol drifted past the rebalance threshold
+pub const WORK_REBALANCE: u8 = 1;
+
+/// `pending_work` flag: the harvest interval has elapsed
+pub const WORK_HARVEST: u8 = 2;
+
+/// `pending_work` flag: idle assets exceed the buffer by more than the threshold
+pub const WORK_IDLE: u8 = 4;
+
+/// (owner, asset, factory, total_assets, total_supply, username_hash, bio_hash, created_at, paused)
+type VaultInfo = (Address, Address, Address, U256, U256, [u8; 32], [u8; 32], U256, bool);
+
+/// (idle, protocols, values): every registered strategy's position, each valued in the underlying asset
+type PositionBreakdown = (U256, Vec<String>, Vec<U256>);
+
+/// (protocols, amounts): negative amounts are withdrawn, positive ones deposited
+type RebalanceMoves = (Vec<String>, Vec<I256>);
+
+/// (moves, `WORK_*` flags): the signed move for each strategy and why they are needed
+type RebalancePlan = (Vec<(&'static dyn Strategy, I256)>, u8);
+
+/// (protocols, target weights in bps, deployed amounts, current values, last-updated timestamps)
+type AllocationList = (Vec<String>, Vec<U256>, Vec<U256>, Vec<U256>, Vec<U256>);
+
 sol! {
-    event Deposit(address indexed user, uint256 assets, uint256 shares);
-    event Withdraw(address indexed user, uint256 assets, uint256 shares);
-    event ProtocolAllocationChanged(string protocol, uint256 old_amount, uint256 new_amount);
-    event VaultPaused(address indexed vault);
-    event VaultUnpaused(address indexed vault);
-    event Transfer(address indexed from, address indexed to, uint256 value);
-    event Approval(address indexed owner, address indexed spender, uint256 value);
+    event Deposit(address indexed sender, address indexed owner, uint256 assets, uint256 shares);
+    event Withdraw(address indexed sender, address indexed receiver, address indexed owner, uint256 assets, uint256 shares);
     event ProtocolDeployed(string protocol, uint256 amount);
-    event ProtocolHarvested(string protocol, uint256 rewards);
+    event ProtocolHarvested(string protocol, uint256 rewards, uint256 proceeds, uint256 share_price);
+    event RewardsConfigUpdated(string protocol, address rewards, address reward_token);
+    event StrategyTargetUpdated(string protocol, address target);
+    event Swapped(address indexed token_in, uint256 amount_in, uint256 amount_out);
+    event ProtocolWithdrawn(string protocol, uint256 amount);
+    event DepositCapsUpdated(uint256 total_cap, uint256 per_depositor_cap);
+    event WithdrawalQueueUpdated(string[] queue);
+    event MaxLossUpdated(uint256 max_loss_bps);
+    event ProtocolAddressesRefreshed(string[] protocols, address[] addresses);
+    event TargetWeightUpdated(string protocol, uint256 weight_bps);
+    event IdleBufferUpdated(uint256 idle_buffer_bps);
+    event RebalanceThresholdUpdated(uint256 threshold_bps);
+    event Rebalanced(string[] protocols, int256[] amounts);
+    event StrategistUpdated(address indexed previous_strategist, address indexed new_strategist);
+    event HarvestConfigUpdated(uint256 interval, uint256 bounty_bps, uint256 max_bounty);
+    event Harvested(address indexed caller, uint256 proceeds, uint256 bounty);
+    event RewardPriceUpdated(address indexed reward_token, uint256 price);
+    event MaxHarvestSlippageUpdated(uint256 slippage_bps);
+
+    error ERC4626ExceededMaxDeposit(address receiver, uint256 assets, uint256 max);
+    error ERC4626ExceededMaxMint(address receiver, uint256 shares, uint256 max);
+    error ERC4626ExceededMaxWithdraw(address owner, uint256 assets, uint256 max);
+    error ERC4626ExceededMaxRedeem(address owner, uint256 shares, uint256 max);
+
+    error DeadlineExpired(uint256 deadline, uint256 timestamp);
+    error ERC5143InsufficientShares(uint256 shares, uint256 min_shares);
+    error ERC5143ExcessiveAssets(uint256 assets, uint256 max_assets);
+    error ERC5143ExcessiveShares(uint256 shares, uint256 max_shares);
+    error ERC5143InsufficientAssets(uint256 assets, uint256 min_assets);
+
+    error ExceededMaxLoss(uint256 loss, uint256 max_loss);
+    error InsufficientHarvestProceeds(uint256 proceeds, uint256 min_proceeds);
+    error InsufficientSwapOutput(uint256 amount_out, uint256 min_amount_out);
+
+    // Typed factory getters. Called through `safe_erc20::view` on `self.vm()`:
+    // the calls `sol_interface!` generates in this SDK go through the
+    // deprecated global hostio instead, which the host can't route.
+    interface IVaultFactory {
+        function getAaveAddress() external view returns (address);
+        function getCompoundAddress() external view returns (address);
+        function getUniswapAddress() external view returns (address);
+        function getWethAddress() external view returns (address);
+    }
 }
 
 #[public]
+#[inherit(Erc20<VaultShareParams>, Ownable, Pausable)]
 impl UserVault {
     /// Initializes the vault
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize(
         &mut self,
         owner: Address,
@@ -77,6 +244,7 @@ impl UserVault {
         vault_decimals: u8,
         username_limit: U256,
         bio_limit: U256,
+        asset_mode: u8,
     ) -> Result<(), Vec<u8>> {
         if owner == Address::ZERO || asset == Address::ZERO || factory == Address::ZERO {
             return Err("Invalid addresses".into());
@@ -86,6 +254,8 @@ impl UserVault {
             return Err("Already initialized".into());
         }
 
+        self.reentrancy_guard.when_not_entered()?;
+
         if username.len() > username_limit.as_limbs()[0] as usize {
             return Err("Username too long".into());
         }
@@ -94,8 +264,13 @@ impl UserVault {
             return Err("Bio too long".into());
         }
 
-        self.owner.set(owner);
+        if AssetMode::from_u8(asset_mode).is_none() {
+            return Err("Invalid asset mode".into());
+        }
+
+        self.ownable.transfer_ownership_impl(owner);
         self.asset.set(asset);
+        self.asset_mode.set(alloy_primitives::Uint::from(asset_mode));
         self.factory.set(factory);
         
         // Store vault name and symbol as hashes
@@ -114,7 +289,6 @@ impl UserVault {
         self.username_hash.set(username_hash);
         self.bio_hash.set(bio_hash);
         self.created_at.set(U256::from(self.vm().block_timestamp()));
-        self.paused.set(false);
         self.initialized.set(true);
 
         // Cache protocol addresses from factory
@@ -125,9 +299,8 @@ impl UserVault {
 
     /// Updates vault name (owner only)
     pub fn set_vault_name(&mut self, new_name: String) -> Result<(), Vec<u8>> {
-        if self.vm().msg_sender() != self.owner.get() {
-            return Err("Not owner".into());
-        }
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
 
         let vault_name_hash = _hash_string(&new_name).into();
         self.vault_name_hash.set(vault_name_hash);
@@ -136,9 +309,8 @@ impl UserVault {
 
     /// Updates vault symbol (owner only)
     pub fn set_vault_symbol(&mut self, new_symbol: String) -> Result<(), Vec<u8>> {
-        if self.vm().msg_sender() != self.owner.get() {
-            return Err("Not owner".into());
-        }
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
 
         let vault_symbol_hash = _hash_string(&new_symbol).into();
         self.vault_symbol_hash.set(vault_symbol_hash);
@@ -147,167 +319,251 @@ impl UserVault {
 
     /// Updates vault decimals (owner only)
     pub fn set_vault_decimals(&mut self, new_decimals: u8) -> Result<(), Vec<u8>> {
-        if self.vm().msg_sender() != self.owner.get() {
-            return Err("Not owner".into());
-        }
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
 
         self.vault_decimals.set(alloy_primitives::Uint::from(new_decimals));
         Ok(())
     }
 
-    /// Deposits assets and mints shares
-    pub fn deposit(&mut self, amount: U256) -> Result<U256, Vec<u8>> {
-        if self.paused.get() {
-            return Err("Vault is paused".into());
+    /// Configures inflation attack protection (owner only, before the first deposit)
+    pub fn set_inflation_protection(
+        &mut self,
+        decimals_offset: u8,
+        min_initial_deposit: U256,
+    ) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        if self.erc20.total_supply.get() != U256::ZERO {
+            return Err("Vault already has shares".into());
         }
 
-        if amount == U256::ZERO {
-            return Err("Invalid amount".into());
+        if decimals_offset > MAX_DECIMALS_OFFSET {
+            return Err("Decimals offset too large".into());
         }
 
-        let user = self.vm().msg_sender();
-        
-        // Calculate shares to mint
-        let shares = self._calculate_shares(amount)?;
-        
-        // Transfer assets from user to vault
-        self._transfer_assets_from_user(user, amount)?;
-        
-        // Update balances
-        let mut user_balance = self.balances.setter(user);
-        let current_balance = user_balance.get();
-        user_balance.set(current_balance + shares);
-        
-        // Update totals
-        self.total_assets.set(self.total_assets.get() + amount);
-        self.total_supply.set(self.total_supply.get() + shares);
+        self.decimals_offset.set(alloy_primitives::Uint::from(decimals_offset));
+        self.min_initial_deposit.set(min_initial_deposit);
+        Ok(())
+    }
 
-        // Emit event
-        log(self.vm(), Deposit {
-            user,
-            assets: amount,
-            shares,
+    /// Gets the decimals offset used for virtual shares
+    pub fn decimals_offset(&self) -> Result<u8, Vec<u8>> {
+        Ok(self.decimals_offset.get().as_limbs()[0] as u8)
+    }
+
+    /// Gets the minimum first deposit, whose shares are burned
+    pub fn min_initial_deposit(&self) -> Result<U256, Vec<u8>> {
+        Ok(self.min_initial_deposit.get())
+    }
+
+    /// Sets the total and per-depositor deposit caps in assets, 0 meaning no cap (owner only)
+    pub fn set_deposit_caps(&mut self, total_cap: U256, per_depositor_cap: U256) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        self.deposit_cap.set(total_cap);
+        self.per_depositor_cap.set(per_depositor_cap);
+
+        log(self.vm(), DepositCapsUpdated {
+            total_cap,
+            per_depositor_cap,
         });
 
-        Ok(shares)
+        Ok(())
     }
 
-    /// Withdraws assets by burning shares
-    pub fn withdraw(&mut self, amount: U256) -> Result<U256, Vec<u8>> {
-        if self.paused.get() {
-            return Err("Vault is paused".into());
-        }
+    /// Gets the total deposit cap (0 = no cap)
+    pub fn deposit_cap(&self) -> Result<U256, Vec<u8>> {
+        Ok(self.deposit_cap.get())
+    }
 
-        if amount == U256::ZERO {
-            return Err("Invalid amount".into());
+    /// Gets the per-depositor cap (0 = no cap)
+    pub fn per_depositor_cap(&self) -> Result<U256, Vec<u8>> {
+        Ok(self.per_depositor_cap.get())
+    }
+
+    /// Sets the order in which protocols are unwound to fund withdrawals (owner only)
+    pub fn set_withdrawal_queue(&mut self, queue: Vec<String>) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        for (i, protocol) in queue.iter().enumerate() {
+            strategy(protocol)?;
+            if queue[..i].contains(protocol) {
+                return Err("Duplicate protocol".into());
+            }
         }
 
-        let user = self.vm().msg_sender();
-        let user_balance = self.balances.get(user);
-        
-        if user_balance == U256::ZERO {
-            return Err("No shares to withdraw".into());
+        self.withdrawal_queue.erase();
+        for protocol in &queue {
+            self.withdrawal_queue.grow().set_str(protocol);
         }
 
-        // Calculate shares to burn based on amount
-        let shares_to_burn = self._calculate_shares(amount)?;
-        
-        if shares_to_burn > user_balance {
-            return Err("Insufficient shares".into());
+        log(self.vm(), WithdrawalQueueUpdated { queue });
+        Ok(())
+    }
+
+    /// Returns the protocols unwound for withdrawals, in order
+    pub fn get_withdrawal_queue(&self) -> Result<Vec<String>, Vec<u8>> {
+        Ok(self._withdrawal_queue())
+    }
+
+    /// Sets the largest shortfall a redemption accepts from unwinding, in basis
+    /// points (owner only); `withdraw` always pays the exact assets or reverts
+    pub fn set_max_loss(&mut self, max_loss_bps: U256) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        if max_loss_bps > U256::from(MAX_BPS) {
+            return Err("Invalid max loss".into());
         }
 
-        // Transfer assets from vault to user
-        self._transfer_assets_to_user(user, amount)?;
+        self.max_loss_bps.set(max_loss_bps);
+        log(self.vm(), MaxLossUpdated { max_loss_bps });
+        Ok(())
+    }
 
-        // Update balances
-        let mut user_balance_setter = self.balances.setter(user);
-        user_balance_setter.set(user_balance - shares_to_burn);
-        
-        // Update totals
-        self.total_assets.set(self.total_assets.get() - amount);
-        self.total_supply.set(self.total_supply.get() - shares_to_burn);
+    /// Returns the redemption max loss in basis points
+    pub fn max_loss(&self) -> Result<U256, Vec<u8>> {
+        Ok(self.max_loss_bps.get())
+    }
 
-        // Emit event
-        log(self.vm(), Withdraw {
-            user,
-            assets: amount,
-            shares: shares_to_burn,
+    /// Appoints the strategist, who manages protocol positions within the
+    /// target weights but cannot move assets out of the vault (owner only)
+    pub fn set_strategist(&mut self, strategist: Address) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        let previous_strategist = self.strategist.get();
+        self.strategist.set(strategist);
+        log(self.vm(), StrategistUpdated {
+            previous_strategist,
+            new_strategist: strategist,
         });
+        Ok(())
+    }
 
-        Ok(shares_to_burn)
+    /// Returns the strategist (zero if none)
+    pub fn strategist(&self) -> Result<Address, Vec<u8>> {
+        Ok(self.strategist.get())
     }
 
-    /// Sets allocation for a specific protocol (owner only)
-    pub fn set_protocol_allocation(&mut self, protocol: String, amount: U256) -> Result<(), Vec<u8>> {
-        if self.vm().msg_sender() != self.owner.get() {
-            return Err("Not owner".into());
+    /// Sets the share of total assets a protocol should hold, in basis points (owner only)
+    pub fn set_target_weight(&mut self, protocol: String, weight_bps: U256) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        let strategy = strategy(&protocol)?;
+        if weight_bps != U256::ZERO && !strategy.is_configured(self) {
+            return Err("Protocol not configured".into());
+        }
+        let others = self._total_target_bps() - self.target_weights_bps.get(protocol.clone());
+        if weight_bps > U256::from(MAX_BPS) - others {
+            return Err("Weights exceed 100%".into());
         }
 
-        if amount > self.total_assets.get() {
-            return Err("Insufficient assets".into());
+        self.target_weights_bps.setter(protocol.clone()).set(weight_bps);
+        self._touch_allocation(&protocol);
+        log(self.vm(), TargetWeightUpdated { protocol, weight_bps });
+        Ok(())
+    }
+
+    /// Returns a protocol's target weight in basis points
+    pub fn get_target_weight(&self, protocol: String) -> Result<U256, Vec<u8>> {
+        Ok(self.target_weights_bps.get(protocol))
+    }
+
+    /// Sets the share of total assets rebalancing keeps idle, in basis points (owner only)
+    pub fn set_idle_buffer(&mut self, idle_buffer_bps: U256) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        let weights = self._total_target_bps() - self.idle_buffer_bps.get();
+        if idle_buffer_bps > U256::from(MAX_BPS) - weights {
+            return Err("Weights exceed 100%".into());
         }
 
-        let old_amount = self.protocol_allocations.get(protocol.clone());
-        self.protocol_allocations.setter(protocol.clone()).set(amount);
+        self.idle_buffer_bps.set(idle_buffer_bps);
+        log(self.vm(), IdleBufferUpdated { idle_buffer_bps });
+        Ok(())
+    }
 
-        // Emit event
-        log(self.vm(), ProtocolAllocationChanged {
-            protocol: protocol.clone(),
-            old_amount,
-            new_amount: amount,
-        });
+    /// Returns the idle buffer target in basis points
+    pub fn idle_buffer(&self) -> Result<U256, Vec<u8>> {
+        Ok(self.idle_buffer_bps.get())
+    }
+
+    /// Sets the drift, in basis points of total assets, a protocol needs before rebalancing moves it (owner only)
+    pub fn set_rebalance_threshold(&mut self, threshold_bps: U256) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        if threshold_bps > U256::from(MAX_BPS) {
+            return Err("Invalid threshold".into());
+        }
 
+        self.rebalance_threshold_bps.set(threshold_bps);
+        log(self.vm(), RebalanceThresholdUpdated { threshold_bps });
         Ok(())
     }
 
+    /// Returns the rebalance drift threshold in basis points
+    pub fn rebalance_threshold(&self) -> Result<U256, Vec<u8>> {
+        Ok(self.rebalance_threshold_bps.get())
+    }
+
+    /// Deposits assets from the caller and mints shares to receiver
+    pub fn deposit(&mut self, assets: U256, receiver: Address) -> Result<U256, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._deposit(assets, receiver);
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    /// Burns shares from owner and sends exactly `assets` to receiver,
+    /// reverting if unwinding protocols falls short
+    pub fn withdraw(&mut self, assets: U256, receiver: Address, owner: Address) -> Result<U256, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._withdraw(assets, receiver, owner);
+        self.reentrancy_guard.exit();
+        result
+    }
+
     /// Pauses the vault (owner only)
     pub fn pause(&mut self) -> Result<(), Vec<u8>> {
-        if self.vm().msg_sender() != self.owner.get() {
-            return Err("Not owner".into());
-        }
-
-        self.paused.set(true);
-        log(self.vm(), VaultPaused {
-            vault: self.vm().contract_address(),
-        });
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
 
+        self.pausable.pause_impl()?;
         Ok(())
     }
 
     /// Unpauses the vault (owner only)
     pub fn unpause(&mut self) -> Result<(), Vec<u8>> {
-        if self.vm().msg_sender() != self.owner.get() {
-            return Err("Not owner".into());
-        }
-
-        self.paused.set(false);
-        log(self.vm(), VaultUnpaused {
-            vault: self.vm().contract_address(),
-        });
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
 
+        self.pausable.unpause_impl()?;
         Ok(())
     }
 
     /// Gets vault information
-    pub fn get_vault_info(&self) -> Result<(Address, Address, Address, U256, U256, [u8; 32], [u8; 32], U256, bool), Vec<u8>> {
+    pub fn get_vault_info(&self) -> Result<VaultInfo, Vec<u8>> {
         Ok((
-            self.owner.get(),
+            self.ownable.owner.get(),
             self.asset.get(),
             self.factory.get(),
-            self.total_assets.get(),
-            self.total_supply.get(),
+            self._total_assets()?,
+            self.erc20.total_supply.get(),
             self.username_hash.get().into(),
             self.bio_hash.get().into(),
             self.created_at.get(),
-            self.paused.get(),
+            self.pausable.paused.get(),
         ))
     }
 
-    /// Gets user's share balance
-    pub fn balance_of(&self, user: Address) -> Result<U256, Vec<u8>> {
-        Ok(self.balances.get(user))
-    }
-
     // ERC-4626 Standard Functions
 
     /// Returns the address of the underlying asset
@@ -317,443 +573,1445 @@ impl UserVault {
 
     /// Returns the total amount of the underlying asset that is "managed" by this vault
     pub fn total_assets(&self) -> Result<U256, Vec<u8>> {
-        Ok(self.total_assets.get())
+        self._total_assets()
+    }
+
+    /// Returns where the assets are: idle, then one entry per registered
+    /// strategy, each valued in the underlying asset
+    pub fn get_position_breakdown(&self) -> Result<PositionBreakdown, Vec<u8>> {
+        let mut protocols = Vec::new();
+        let mut values = Vec::new();
+        for strategy in strategies::STRATEGIES {
+            protocols.push(strategy.id().to_string());
+            values.push(self._protocol_position(strategy.id())?);
+        }
+        Ok((self._idle_assets()?, protocols, values))
+    }
+
+    /// Returns the asset accounting mode (0 = standard, 1 = fee-on-transfer or rebasing)
+    pub fn asset_mode(&self) -> Result<u8, Vec<u8>> {
+        Ok(self.asset_mode.get().as_limbs()[0] as u8)
     }
 
     /// Returns the amount of shares that the vault would exchange for the amount of assets provided
     pub fn convert_to_shares(&self, assets: U256) -> Result<U256, Vec<u8>> {
-        let total_supply = self.total_supply.get();
-        if total_supply == U256::ZERO {
-            return Ok(assets);
-        }
-        let total_assets = self.total_assets.get();
-        if total_assets == U256::ZERO {
-            return Ok(U256::ZERO);
-        }
-        Ok((assets * total_supply) / total_assets)
+        self._convert_to_shares(assets, Rounding::Floor)
     }
 
     /// Returns the amount of assets that the vault would exchange for the amount of shares provided
     pub fn convert_to_assets(&self, shares: U256) -> Result<U256, Vec<u8>> {
-        let total_supply = self.total_supply.get();
-        if total_supply == U256::ZERO {
-            return Ok(U256::ZERO);
+        self._convert_to_assets(shares, Rounding::Floor)
+    }
+
+    // Previews round in the vault's favour: callers receive rounded-down
+    // amounts and pay rounded-up amounts
+
+    // On the first deposit the shares of `min_initial_deposit` go to the dead
+    // address: deposit hands the receiver fewer shares, mint charges more assets
+
+    /// Returns the exact shares `deposit(assets, ..)` would mint right now
+    pub fn preview_deposit(&self, assets: U256) -> Result<U256, Vec<u8>> {
+        let shares = self._convert_to_shares(assets, Rounding::Floor)?;
+        Ok(shares.saturating_sub(self._dead_shares()?))
+    }
+
+    /// Returns the exact assets `mint(shares, ..)` would pull right now
+    pub fn preview_mint(&self, shares: U256) -> Result<U256, Vec<u8>> {
+        let assets = self._convert_to_assets(shares, Rounding::Ceil)?;
+        if self._dead_shares()? == U256::ZERO {
+            return Ok(assets);
         }
-        let total_assets = self.total_assets.get();
-        Ok((shares * total_assets) / total_supply)
+        Ok(assets + self.min_initial_deposit.get())
+    }
+
+    /// Returns the exact shares `withdraw(assets, ..)` would burn right now
+    pub fn preview_withdraw(&self, assets: U256) -> Result<U256, Vec<u8>> {
+        self._convert_to_shares(assets, Rounding::Ceil)
+    }
+
+    /// Returns the exact assets `redeem(shares, ..)` would pay out right now
+    pub fn preview_redeem(&self, shares: U256) -> Result<U256, Vec<u8>> {
+        self._convert_to_assets(shares, Rounding::Floor)
     }
 
     /// Returns the maximum amount of the underlying asset that can be deposited into the vault for the receiver
-    pub fn max_deposit(&self, _receiver: Address) -> Result<U256, Vec<u8>> {
-        // For now, return a large number (no limit)
-        Ok(U256::from(2).pow(U256::from(256)) - U256::ONE)
+    pub fn max_deposit(&self, receiver: Address) -> Result<U256, Vec<u8>> {
+        if self.pausable.paused.get() {
+            return Ok(U256::ZERO);
+        }
+
+        let mut max = U256::MAX;
+        let total_cap = self.deposit_cap.get();
+        if total_cap != U256::ZERO {
+            max = max.min(total_cap.saturating_sub(self._total_assets()?));
+        }
+        let depositor_cap = self.per_depositor_cap.get();
+        if depositor_cap != U256::ZERO {
+            let position = self.convert_to_assets(self.erc20.balances.get(receiver))?;
+            max = max.min(depositor_cap.saturating_sub(position));
+        }
+        Ok(max)
     }
 
     /// Returns the maximum amount of shares that can be minted for the receiver
-    pub fn max_mint(&self, _receiver: Address) -> Result<U256, Vec<u8>> {
-        // For now, return a large number (no limit)
-        Ok(U256::from(2).pow(U256::from(256)) - U256::ONE)
+    pub fn max_mint(&self, receiver: Address) -> Result<U256, Vec<u8>> {
+        if self._asset_mode() != AssetMode::Standard {
+            return Ok(U256::ZERO);
+        }
+        let max_assets = self.max_deposit(receiver)?;
+        if max_assets == U256::MAX {
+            return Ok(U256::MAX);
+        }
+
+        // The first mint also pays for the dead shares
+        let mut spendable = max_assets;
+        if self._dead_shares()? != U256::ZERO {
+            spendable = spendable.saturating_sub(self.min_initial_deposit.get());
+        }
+        self._convert_to_shares(spendable, Rounding::Floor)
     }
 
     /// Returns the maximum amount of the underlying asset that can be withdrawn from the owner balance
     pub fn max_withdraw(&self, owner: Address) -> Result<U256, Vec<u8>> {
-        let shares = self.balances.get(owner);
-        self.convert_to_assets(shares)
+        if self.pausable.paused.get() {
+            return Ok(U256::ZERO);
+        }
+
+        let owned = self.convert_to_assets(self.erc20.balances.get(owner))?;
+        Ok(owned.min(self._withdrawable_assets()?))
     }
 
     /// Returns the maximum amount of shares that can be redeemed from the owner balance
     pub fn max_redeem(&self, owner: Address) -> Result<U256, Vec<u8>> {
-        Ok(self.balances.get(owner))
+        if self.pausable.paused.get() {
+            return Ok(U256::ZERO);
+        }
+
+        let liquid_shares = self._convert_to_shares(self._withdrawable_assets()?, Rounding::Floor)?;
+        Ok(self.erc20.balances.get(owner).min(liquid_shares))
     }
 
-    /// Mints exactly amount of vault shares to receiver by depositing assets
+    /// Mints exactly `shares` to receiver by pulling the required assets from the caller
     pub fn mint(&mut self, shares: U256, receiver: Address) -> Result<U256, Vec<u8>> {
-        if self.paused.get() {
-            return Err("Vault is paused".into());
+        self.reentrancy_guard.enter()?;
+        let result = self._mint(shares, receiver);
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    /// Burns exactly `shares` from owner and sends the assets to receiver
+    pub fn redeem(&mut self, shares: U256, receiver: Address, owner: Address) -> Result<U256, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._redeem(shares, receiver, owner);
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    // ERC-5143 overloads: the same flows with a slippage bound and a deadline
+
+    /// Deposits like `deposit`, reverting if fewer than `min_shares` would be minted
+    #[selector(name = "deposit")]
+    pub fn deposit_bounded(
+        &mut self,
+        assets: U256,
+        receiver: Address,
+        min_shares: U256,
+        deadline: U256,
+    ) -> Result<U256, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._deposit_bounded(assets, receiver, min_shares, deadline);
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    /// Mints like `mint`, reverting if more than `max_assets` would be pulled
+    #[selector(name = "mint")]
+    pub fn mint_bounded(
+        &mut self,
+        shares: U256,
+        receiver: Address,
+        max_assets: U256,
+        deadline: U256,
+    ) -> Result<U256, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._mint_bounded(shares, receiver, max_assets, deadline);
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    /// Withdraws like `withdraw`, reverting if more than `max_shares` would be burned
+    #[selector(name = "withdraw")]
+    pub fn withdraw_bounded(
+        &mut self,
+        assets: U256,
+        receiver: Address,
+        owner: Address,
+        max_shares: U256,
+        deadline: U256,
+    ) -> Result<U256, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._withdraw_bounded(assets, receiver, owner, max_shares, deadline);
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    /// Redeems like `redeem`, reverting if fewer than `min_assets` would be paid out
+    #[selector(name = "redeem")]
+    pub fn redeem_bounded(
+        &mut self,
+        shares: U256,
+        receiver: Address,
+        owner: Address,
+        min_assets: U256,
+        deadline: U256,
+    ) -> Result<U256, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._redeem_bounded(shares, receiver, owner, min_assets, deadline);
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    // ERC-20 Functions for Vault Shares
+    // name, symbol, balances, transfers and allowances are inherited from the shared Erc20 component
+
+    /// Returns the decimals of the vault share token, including the virtual share offset
+    pub fn decimals(&self) -> Result<u8, Vec<u8>> {
+        Ok((self.vault_decimals.get().as_limbs()[0] as u8).saturating_add(self.decimals_offset()?))
+    }
+
+    // ===== PROTOCOL INTEGRATION FUNCTIONS =====
+
+    /// Deploys idle assets to a registered protocol ("aave", "compound", ...)
+    pub fn deploy_to_protocol(&mut self, protocol: String, amount: U256) -> Result<(), Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._deploy_to_protocol(&protocol, amount);
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    /// Withdraws assets from a registered protocol back to the vault, returning the amount received
+    pub fn withdraw_from_protocol(&mut self, protocol: String, amount: U256) -> Result<U256, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._withdraw_from_protocol(&protocol, amount);
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    /// Pulls the whole position out of a protocol through its emergency path
+    /// (owner only, also while paused), returning the amount received
+    pub fn emergency_exit(&mut self, protocol: String) -> Result<U256, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._emergency_exit(&protocol);
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    /// Deploys assets to Aave lending protocol
+    pub fn deploy_to_aave(&mut self, amount: U256) -> Result<(), Vec<u8>> {
+        self.deploy_to_protocol("aave".to_string(), amount)
+    }
+
+    /// Deploys assets to Compound protocol
+    pub fn deploy_to_compound(&mut self, amount: U256) -> Result<(), Vec<u8>> {
+        self.deploy_to_protocol("compound".to_string(), amount)
+    }
+
+    /// Withdraws assets from Aave back to the vault, returning the amount received
+    pub fn withdraw_from_aave(&mut self, amount: U256) -> Result<U256, Vec<u8>> {
+        self.withdraw_from_protocol("aave".to_string(), amount)
+    }
+
+    /// Withdraws assets from Compound back to the vault, returning the amount received
+    pub fn withdraw_from_compound(&mut self, amount: U256) -> Result<U256, Vec<u8>> {
+        self.withdraw_from_protocol("compound".to_string(), amount)
+    }
+
+    /// Withdraws the whole position in a protocol, returning the amount received
+    pub fn withdraw_all_from_protocol(&mut self, protocol: String) -> Result<U256, Vec<u8>> {
+        self.withdraw_from_protocol(protocol, U256::MAX)
+    }
+
+    /// Sets where a protocol's incentive rewards are claimed and which token they pay (owner only)
+    pub fn set_rewards_config(
+        &mut self,
+        protocol: String,
+        rewards: Address,
+        reward_token: Address,
+    ) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        strategy(&protocol)?;
+
+        self.protocol_addresses.setter(strategies::rewards_key(&protocol)).set(rewards);
+        self.protocol_addresses.setter(strategies::reward_token_key(&protocol)).set(reward_token);
+
+        log(self.vm(), RewardsConfigUpdated {
+            protocol,
+            rewards,
+            reward_token,
+        });
+        Ok(())
+    }
+
+    /// Points a configurable strategy (e.g. "erc4626") at a target contract
+    /// (owner only). The current position must be closed first.
+    pub fn set_strategy_target(&mut self, protocol: String, target: Address) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        let strategy = strategy(&protocol)?;
+        if strategy.total_value(self)? != U256::ZERO {
+            return Err("Position not empty".into());
+        }
+        strategy.set_target(self, target)?;
+
+        log(self.vm(), StrategyTargetUpdated { protocol, target });
+        Ok(())
+    }
+
+    /// Returns a protocol's (rewards contract, reward token)
+    pub fn get_rewards_config(&self, protocol: String) -> Result<(Address, Address), Vec<u8>> {
+        Ok(self._rewards_config(&protocol))
+    }
+
+    /// Sets the reference price of a reward token, in asset units per reward
+    /// unit scaled by 1e18 (owner only; 0 clears it). Rewards are only sold
+    /// by anyone but the owner against this price.
+    pub fn set_reward_price(&mut self, reward_token: Address, price: U256) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        self.reward_prices.setter(reward_token).set(price);
+        log(self.vm(), RewardPriceUpdated { reward_token, price });
+        Ok(())
+    }
+
+    /// Returns a reward token's reference price (0 if not priced)
+    pub fn get_reward_price(&self, reward_token: Address) -> Result<U256, Vec<u8>> {
+        Ok(self.reward_prices.get(reward_token))
+    }
+
+    /// Sets how far below the reference price the strategist and
+    /// permissionless harvests may sell rewards, in basis points (owner only)
+    pub fn set_max_harvest_slippage(&mut self, slippage_bps: U256) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        if slippage_bps > U256::from(MAX_BPS) {
+            return Err("Invalid slippage".into());
+        }
+
+        self.max_harvest_slippage_bps.set(slippage_bps);
+        log(self.vm(), MaxHarvestSlippageUpdated { slippage_bps });
+        Ok(())
+    }
+
+    /// Returns the harvest slippage limit in basis points
+    pub fn max_harvest_slippage(&self) -> Result<U256, Vec<u8>> {
+        Ok(self.max_harvest_slippage_bps.get())
+    }
+
+    /// Claims a protocol's rewards, sells the vault's whole balance of the
+    /// reward token for the asset (at least `min_amount_out`, and for the
+    /// strategist at least the reference price less the slippage limit) and
+    /// redeploys the proceeds; returns the proceeds
+    pub fn harvest_from_protocol(&mut self, protocol: String, min_amount_out: U256) -> Result<U256, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._harvest_from_protocol(protocol, min_amount_out);
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    /// Configures permissionless harvesting: how often anyone may call
+    /// `harvest` (0 disables it) and the caller's bounty (owner only)
+    pub fn set_harvest_config(&mut self, interval: U256, bounty_bps: U256, max_bounty: U256) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        if bounty_bps > U256::from(MAX_BPS) {
+            return Err("Invalid bounty".into());
+        }
+
+        self.harvest_interval.set(interval);
+        self.harvest_bounty_bps.set(bounty_bps);
+        self.max_harvest_bounty.set(max_bounty);
+        log(self.vm(), HarvestConfigUpdated {
+            interval,
+            bounty_bps,
+            max_bounty,
+        });
+        Ok(())
+    }
+
+    /// Returns (interval, bounty_bps, max_bounty, last_harvest)
+    pub fn get_harvest_config(&self) -> Result<(U256, U256, U256, U256), Vec<u8>> {
+        Ok((
+            self.harvest_interval.get(),
+            self.harvest_bounty_bps.get(),
+            self.max_harvest_bounty.get(),
+            self.last_harvest.get(),
+        ))
+    }
+
+    /// Harvests every protocol with something to claim, pays the caller the
+    /// bounty and compounds the rest; anyone may call once the interval has
+    /// passed. Rewards are sold no lower than their reference price less the
+    /// slippage limit; rewards without a reference price are only claimed and
+    /// left for `harvest_from_protocol`. Returns the bounty paid.
+    pub fn harvest(&mut self) -> Result<U256, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._harvest();
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    /// Swaps `amount_in` of the path's first token along an encoded Uniswap
+    /// path that must end in the asset (owner only); returns the assets received
+    pub fn swap_exact_input(
+        &mut self,
+        path: Bytes,
+        amount_in: U256,
+        min_amount_out: U256,
+        deadline: U256,
+    ) -> Result<U256, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._swap_exact_input(&path, amount_in, min_amount_out, deadline);
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    /// Re-reads the protocol addresses from the factory (owner only), e.g.
+    /// after an admin changed one; returns the protocols that changed
+    pub fn refresh_protocol_addresses(&mut self) -> Result<Vec<String>, Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.reentrancy_guard.when_not_entered()?;
+
+        self._cache_protocol_addresses()
+    }
+
+    /// Applies protocol addresses pushed by the factory (factory only);
+    /// returns the protocols that changed. The factory passes them in because
+    /// reading them back from it mid-call would re-enter it.
+    pub fn update_protocol_addresses(
+        &mut self,
+        aave: Address,
+        compound: Address,
+        uniswap: Address,
+        weth: Address,
+    ) -> Result<Vec<String>, Vec<u8>> {
+        if self.vm().msg_sender() != self.factory.get() {
+            return Err("Not factory".into());
+        }
+        self.reentrancy_guard.when_not_entered()?;
+
+        self._apply_protocol_addresses([("aave", aave), ("compound", compound), ("uniswap", uniswap), ("weth", weth)])
+    }
+
+    /// Moves protocols that drifted past the threshold back to their target
+    /// weights, withdrawing from over-weight ones before depositing into
+    /// under-weight ones, and puts idle assets past the threshold above the
+    /// buffer to work (owner or strategist); returns the moves made
+    pub fn rebalance(&mut self) -> Result<RebalanceMoves, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._rebalance();
+        self.reentrancy_guard.exit();
+        result
+    }
+
+    /// Returns the moves `rebalance` would make now
+    pub fn preview_rebalance(&self) -> Result<RebalanceMoves, Vec<u8>> {
+        Ok(self
+            ._plan_rebalance()?
+            .0
+            .into_iter()
+            .map(|(strategy, amount)| (strategy.id().to_string(), amount))
+            .unzip())
+    }
+
+    /// Returns the `WORK_*` flags for the keeper calls that would succeed
+    /// now: `rebalance` for drift or excess idle assets, `harvest` once due
+    /// and some protocol has rewards to claim. Zero while paused, since
+    /// neither can run.
+    pub fn pending_work(&self) -> Result<u8, Vec<u8>> {
+        if self.pausable.paused() {
+            return Ok(0);
+        }
+        let (_, mut work) = self._plan_rebalance()?;
+
+        let interval = self.harvest_interval.get();
+        let now = U256::from(self.vm().block_timestamp());
+        if interval != U256::ZERO && now >= self.last_harvest.get().saturating_add(interval) {
+            for strategy in strategies::STRATEGIES {
+                if strategy.is_harvestable(self)? {
+                    work |= WORK_HARVEST;
+                    break;
+                }
+            }
+        }
+        Ok(work)
+    }
+
+    /// Returns the protocols with an allocation or a target weight
+    pub fn get_active_protocols(&self) -> Result<Vec<String>, Vec<u8>> {
+        Ok(self._active_protocols())
+    }
+
+    /// Returns every active protocol's target weight next to what was
+    /// deployed into it (the cost basis), what it is worth now and when its
+    /// allocation or target weight last changed
+    pub fn get_all_allocations(&self) -> Result<AllocationList, Vec<u8>> {
+        let protocols = self._active_protocols();
+        let mut weights = Vec::new();
+        let mut deployed = Vec::new();
+        let mut values = Vec::new();
+        let mut updated_at = Vec::new();
+        for protocol in &protocols {
+            weights.push(self.target_weights_bps.get(protocol.clone()));
+            deployed.push(self.protocol_allocations.get(protocol.clone()));
+            values.push(self._protocol_position(protocol)?);
+            updated_at.push(self.allocation_updated_at.get(protocol.clone()));
+        }
+        Ok((protocols, weights, deployed, values, updated_at))
+    }
+
+    /// Gets the amount deployed into a protocol (its cost basis)
+    pub fn get_protocol_allocation(&self, protocol: String) -> Result<U256, Vec<u8>> {
+        Ok(self.protocol_allocations.get(protocol.clone()))
+    }
+
+    /// Gets protocol address
+    pub fn get_protocol_address(&self, protocol: String) -> Result<Address, Vec<u8>> {
+        Ok(self.protocol_addresses.get(protocol.clone()))
+    }
+}
+
+// These methods aren't exposed to other contracts
+impl UserVault {
+    /// Internal function to deposit assets and mint shares
+    fn _deposit(&mut self, assets: U256, receiver: Address) -> Result<U256, Vec<u8>> {
+        if assets == U256::ZERO {
+            return Err("Invalid amount".into());
+        }
+
+        self.pausable.when_not_paused()?;
+        if receiver == Address::ZERO {
+            return Err("Invalid receiver".into());
         }
 
+        let max = self.max_deposit(receiver)?;
+        if assets > max {
+            return Err(ERC4626ExceededMaxDeposit { receiver, assets, max }.abi_encode());
+        }
+
+        if self.erc20.total_supply.get() == U256::ZERO && assets < self.min_initial_deposit.get() {
+            return Err("Initial deposit too small".into());
+        }
+
+        // Price the deposit against the totals before the assets arrive, then
+        // credit only what was actually received
+        let total_assets = self._total_assets()?;
+        let dead_shares = self._dead_shares()?;
+        let caller = self.vm().msg_sender();
+        let received = self._transfer_assets_from_user(caller, assets)?;
+
+        let shares = self
+            ._shares_for(received, total_assets, Rounding::Floor)?
+            .saturating_sub(dead_shares);
+        if shares == U256::ZERO {
+            return Err("Zero shares".into());
+        }
+
+        self._deposit_impl(caller, receiver, received, shares, dead_shares);
+        Ok(shares)
+    }
+
+    /// Internal function to mint exact shares for assets
+    fn _mint(&mut self, shares: U256, receiver: Address) -> Result<U256, Vec<u8>> {
         if shares == U256::ZERO {
             return Err("Invalid amount".into());
         }
 
-        let assets = self.convert_to_assets(shares)?;
-        
-        // Update balances
-        let mut receiver_balance = self.balances.setter(receiver);
-        let current_balance = receiver_balance.get();
-        receiver_balance.set(current_balance + shares);
-        
-        // Update totals
-        self.total_assets.set(self.total_assets.get() + assets);
-        self.total_supply.set(self.total_supply.get() + shares);
+        self.pausable.when_not_paused()?;
+        if receiver == Address::ZERO {
+            return Err("Invalid receiver".into());
+        }
 
-        // Emit event
-        log(self.vm(), Deposit {
-            user: receiver,
-            assets,
-            shares,
-        });
+        let max = self.max_mint(receiver)?;
+        if shares > max {
+            return Err(ERC4626ExceededMaxMint { receiver, shares, max }.abi_encode());
+        }
 
+        let assets = self.preview_mint(shares)?;
+        let dead_shares = self._dead_shares()?;
+        let caller = self.vm().msg_sender();
+        self._transfer_assets_from_user(caller, assets)?;
+
+        self._deposit_impl(caller, receiver, assets, shares, dead_shares);
         Ok(assets)
     }
 
-    /// Redeems a specific amount of shares for assets
-    pub fn redeem(&mut self, shares: U256, receiver: Address, owner: Address) -> Result<U256, Vec<u8>> {
-        if self.paused.get() {
-            return Err("Vault is paused".into());
+    /// Internal function to withdraw exact assets by burning shares
+    fn _withdraw(&mut self, assets: U256, receiver: Address, owner: Address) -> Result<U256, Vec<u8>> {
+        if assets == U256::ZERO {
+            return Err("Invalid amount".into());
         }
 
+        self.pausable.when_not_paused()?;
+        let max = self.max_withdraw(owner)?;
+        if assets > max {
+            return Err(ERC4626ExceededMaxWithdraw { owner, assets, max }.abi_encode());
+        }
+
+        let shares = self.preview_withdraw(assets)?;
+        self._withdraw_impl(self.vm().msg_sender(), receiver, owner, assets, shares, U256::ZERO)?;
+        Ok(shares)
+    }
+
+    /// Internal function to redeem exact shares for assets
+    fn _redeem(&mut self, shares: U256, receiver: Address, owner: Address) -> Result<U256, Vec<u8>> {
         if shares == U256::ZERO {
             return Err("Invalid amount".into());
         }
 
-        let owner_balance = self.balances.get(owner);
-        if shares > owner_balance {
+        self.pausable.when_not_paused()?;
+        let max = self.max_redeem(owner)?;
+        if shares > max {
+            return Err(ERC4626ExceededMaxRedeem { owner, shares, max }.abi_encode());
+        }
+
+        let assets = self.preview_redeem(shares)?;
+        let max_loss = mul_div(assets, self.max_loss_bps.get(), U256::from(MAX_BPS), Rounding::Floor)?;
+        self._withdraw_impl(self.vm().msg_sender(), receiver, owner, assets, shares, max_loss)
+    }
+
+    // Bounds are checked against the exact previews before any tokens move
+
+    /// Internal function to deposit with a minimum share bound
+    fn _deposit_bounded(
+        &mut self,
+        assets: U256,
+        receiver: Address,
+        min_shares: U256,
+        deadline: U256,
+    ) -> Result<U256, Vec<u8>> {
+        self._check_deadline(deadline)?;
+        let shares = self.preview_deposit(assets)?;
+        if shares < min_shares {
+            return Err(ERC5143InsufficientShares { shares, min_shares }.abi_encode());
+        }
+
+        // Fee-on-transfer assets can mint less than previewed
+        let shares = self._deposit(assets, receiver)?;
+        if shares < min_shares {
+            return Err(ERC5143InsufficientShares { shares, min_shares }.abi_encode());
+        }
+        Ok(shares)
+    }
+
+    /// Internal function to mint with a maximum asset bound
+    fn _mint_bounded(
+        &mut self,
+        shares: U256,
+        receiver: Address,
+        max_assets: U256,
+        deadline: U256,
+    ) -> Result<U256, Vec<u8>> {
+        self._check_deadline(deadline)?;
+        let assets = self.preview_mint(shares)?;
+        if assets > max_assets {
+            return Err(ERC5143ExcessiveAssets { assets, max_assets }.abi_encode());
+        }
+        self._mint(shares, receiver)
+    }
+
+    /// Internal function to withdraw with a maximum share bound
+    fn _withdraw_bounded(
+        &mut self,
+        assets: U256,
+        receiver: Address,
+        owner: Address,
+        max_shares: U256,
+        deadline: U256,
+    ) -> Result<U256, Vec<u8>> {
+        self._check_deadline(deadline)?;
+        let shares = self.preview_withdraw(assets)?;
+        if shares > max_shares {
+            return Err(ERC5143ExcessiveShares { shares, max_shares }.abi_encode());
+        }
+        self._withdraw(assets, receiver, owner)
+    }
+
+    /// Internal function to redeem with a minimum asset bound
+    fn _redeem_bounded(
+        &mut self,
+        shares: U256,
+        receiver: Address,
+        owner: Address,
+        min_assets: U256,
+        deadline: U256,
+    ) -> Result<U256, Vec<u8>> {
+        self._check_deadline(deadline)?;
+        let assets = self.preview_redeem(shares)?;
+        if assets < min_assets {
+            return Err(ERC5143InsufficientAssets { assets, min_assets }.abi_encode());
+        }
+
+        // Unwinding losses can pay less than previewed
+        let assets = self._redeem(shares, receiver, owner)?;
+        if assets < min_assets {
+            return Err(ERC5143InsufficientAssets { assets, min_assets }.abi_encode());
+        }
+        Ok(assets)
+    }
+
+    /// Internal function reverting once the block timestamp is past `deadline`
+    fn _check_deadline(&self, deadline: U256) -> Result<(), Vec<u8>> {
+        let timestamp = U256::from(self.vm().block_timestamp());
+        if timestamp > deadline {
+            return Err(DeadlineExpired { deadline, timestamp }.abi_encode());
+        }
+        Ok(())
+    }
+
+    /// Shared deposit/mint flow: mints `shares` to receiver once `assets` have
+    /// been received, plus the dead shares on the first deposit
+    fn _deposit_impl(
+        &mut self,
+        caller: Address,
+        receiver: Address,
+        assets: U256,
+        shares: U256,
+        dead_shares: U256,
+    ) {
+        if dead_shares != U256::ZERO {
+            self.erc20.mint(DEAD_ADDRESS, dead_shares);
+        }
+        self.erc20.mint(receiver, shares);
+
+        log(self.vm(), Deposit {
+            sender: caller,
+            owner: receiver,
+            assets,
+            shares,
+        });
+    }
+
+    /// Shared withdraw/redeem flow: burns `shares` from owner and sends `assets`
+    /// to receiver, unwinding protocols if idle assets fall short. Returns the
+    /// assets actually sent.
+    fn _withdraw_impl(
+        &mut self,
+        caller: Address,
+        receiver: Address,
+        owner: Address,
+        assets: U256,
+        shares: U256,
+        max_loss: U256,
+    ) -> Result<U256, Vec<u8>> {
+        if receiver == Address::ZERO {
+            return Err("Invalid receiver".into());
+        }
+
+        if shares > self.erc20.balances.get(owner) {
             return Err("Insufficient shares".into());
         }
 
-        let assets = self.convert_to_assets(shares)?;
-        
-        // Update balances
-        let mut owner_balance_setter = self.balances.setter(owner);
-        owner_balance_setter.set(owner_balance - shares);
-        
-        // Update totals
-        self.total_assets.set(self.total_assets.get() - assets);
-        self.total_supply.set(self.total_supply.get() - shares);
+        // Third parties need a share allowance from the owner
+        if caller != owner {
+            self.erc20.spend_allowance(owner, caller, shares)?;
+        }
+
+        // Burn shares before any tokens leave the vault
+        self.erc20.burn(owner, shares)?;
+
+        let assets = self._unwind_for_withdrawal(assets, max_loss)?;
 
-        // Emit event
         log(self.vm(), Withdraw {
-            user: receiver,
+            sender: caller,
+            receiver,
+            owner,
             assets,
             shares,
         });
 
+        self._transfer_assets_to_user(receiver, assets)?;
+
         Ok(assets)
     }
 
-    // ERC-20 Functions for Vault Shares
+    /// Pulls the part of `assets` that idle funds cannot cover from the
+    /// withdrawal queue, in order. Whatever the queue fails to deliver is a
+    /// loss, which must stay within `max_loss`; returns the assets payable.
+    fn _unwind_for_withdrawal(&mut self, assets: U256, max_loss: U256) -> Result<U256, Vec<u8>> {
+        let idle = self._idle_assets()?;
+        if idle >= assets {
+            return Ok(assets);
+        }
+
+        let mut shortfall = assets - idle;
+        for protocol in self._withdrawal_queue() {
+            if shortfall == U256::ZERO {
+                break;
+            }
+            let position = self._protocol_position(&protocol)?;
+            if position == U256::ZERO {
+                continue;
+            }
+            let received = self._unwind_protocol(&protocol, shortfall.min(position))?;
+            shortfall = shortfall.saturating_sub(received);
+        }
 
-    /// Returns the name of the vault share token
-    pub fn name(&self) -> Result<String, Vec<u8>> {
-        // For now, return a default name since we store as hash
-        // In production, you might want to store the actual string or use a mapping
-        Ok("Vault Shares".into())
+        if shortfall > max_loss {
+            return Err(ExceededMaxLoss { loss: shortfall, max_loss }.abi_encode());
+        }
+        Ok(assets - shortfall)
     }
 
-    /// Returns the symbol of the vault share token
-    pub fn symbol(&self) -> Result<String, Vec<u8>> {
-        // For now, return a default symbol since we store as hash
-        // In production, you might want to store the actual string or use a mapping
-        Ok("VS".into())
+    /// Reverts unless the caller is the owner or the strategist; returns
+    /// whether it is the owner
+    fn _only_manager(&self) -> Result<bool, Vec<u8>> {
+        let strategist = self.strategist.get();
+        if strategist != Address::ZERO && self.vm().msg_sender() == strategist {
+            return Ok(self.ownable.owner.get() == strategist);
+        }
+        self.ownable.only_owner()?;
+        Ok(true)
     }
 
-    /// Returns the decimals of the vault share token
-    pub fn decimals(&self) -> Result<u8, Vec<u8>> {
-        Ok(self.vault_decimals.get().as_limbs()[0] as u8)
+    /// Internal function to deploy idle assets to a protocol
+    fn _deploy_to_protocol(&mut self, protocol: &str, amount: U256) -> Result<(), Vec<u8>> {
+        let is_owner = self._only_manager()?;
+
+        self.pausable.when_not_paused()?;
+
+        let strategy = strategy(protocol)?;
+        if amount > self._idle_assets()? {
+            return Err("Insufficient assets".into());
+        }
+
+        // The strategist cannot take a protocol past the weight the owner gave it
+        if !is_owner {
+            let weight = self.target_weights_bps.get(protocol.to_string());
+            let cap = mul_div(self._total_assets()?, weight, U256::from(MAX_BPS), Rounding::Floor)?;
+            if strategy.total_value(self)? + amount > cap {
+                return Err("Exceeds target weight".into());
+            }
+        }
+
+        self._supply(strategy, amount)?;
+        Ok(())
     }
 
-    /// Returns the total supply of vault shares
-    pub fn total_supply(&self) -> Result<U256, Vec<u8>> {
-        Ok(self.total_supply.get())
+    /// Supplies idle assets to a strategy and records the allocation, returning the assets deployed
+    fn _supply(&mut self, strategy: &dyn Strategy, amount: U256) -> Result<U256, Vec<u8>> {
+        let protocol = strategy.id().to_string();
+
+        // Adapters can leave part of the amount idle (Uniswap swaps half and
+        // mints what fits), so record what actually left the vault
+        let before = self._idle_assets()?;
+        strategy.deposit(self, amount)?;
+        let deployed = before.saturating_sub(self._idle_assets()?);
+
+        let allocation = self.protocol_allocations.get(protocol.clone());
+        self._set_allocation(&protocol, allocation + deployed);
+
+        // Emit event
+        log(self.vm(), ProtocolDeployed { protocol, amount: deployed });
+        Ok(deployed)
     }
 
-    /// Transfers vault shares from caller to recipient
-    pub fn transfer(&mut self, to: Address, amount: U256) -> Result<bool, Vec<u8>> {
-        let from = self.vm().msg_sender();
-        self._transfer_shares(from, to, amount)?;
-        Ok(true)
+    /// Internal function to withdraw assets from a protocol
+    fn _withdraw_from_protocol(&mut self, protocol: &str, amount: U256) -> Result<U256, Vec<u8>> {
+        self._only_manager()?;
+        self._unwind_protocol(protocol, amount)
     }
 
-    /// Transfers vault shares from one account to another
-    pub fn transfer_from(&mut self, from: Address, to: Address, amount: U256) -> Result<bool, Vec<u8>> {
-        let spender = self.vm().msg_sender();
-        
-        // Check allowance
-        let allowance = self.allowances.getter(from).get(spender);
-        if allowance < amount {
-            return Err("Insufficient allowance".into());
-        }
-        
-        // Update allowance
-        let mut from_allowances = self.allowances.setter(from);
-        let mut spender_allowance = from_allowances.setter(spender);
-        spender_allowance.set(allowance - amount);
-        
-        // Transfer shares
-        self._transfer_shares(from, to, amount)?;
-        
-        Ok(true)
+    /// Internal function to pull everything out of a protocol. Stays
+    /// available while paused, and clears the allocation whatever comes back.
+    fn _emergency_exit(&mut self, protocol: &str) -> Result<U256, Vec<u8>> {
+        self.ownable.only_owner()?;
+
+        let strategy = strategy(protocol)?;
+        let before = self._idle_assets()?;
+        strategy.emergency_exit(self)?;
+        let received = self._idle_assets()?.saturating_sub(before);
+
+        self._record_withdrawal(protocol, received);
+        self._set_allocation(protocol, U256::ZERO);
+        Ok(received)
     }
 
-    /// Approves spender to spend vault shares
-    pub fn approve(&mut self, spender: Address, amount: U256) -> Result<bool, Vec<u8>> {
-        let owner = self.vm().msg_sender();
-        self.allowances.setter(owner).setter(spender).set(amount);
-        
-        // Emit Approval event
-        log(self.vm(), Approval {
-            owner,
-            spender,
-            value: amount,
-        });
-        
-        Ok(true)
+    /// Internal function returning a protocol's position valued in the asset
+    fn _protocol_position(&self, protocol: &str) -> Result<U256, Vec<u8>> {
+        match strategies::get(protocol) {
+            Some(strategy) => strategy.total_value(self),
+            None => Ok(U256::ZERO),
+        }
     }
 
-    /// Returns the allowance for spender
-    pub fn allowance(&self, owner: Address, spender: Address) -> Result<U256, Vec<u8>> {
-        Ok(self.allowances.getter(owner).get(spender))
+    /// Pulls `amount` (or everything, for `U256::MAX`) out of a protocol and returns what arrived
+    fn _unwind_protocol(&mut self, protocol: &str, amount: U256) -> Result<U256, Vec<u8>> {
+        let strategy = strategy(protocol)?;
+        if amount == U256::ZERO {
+            return Err("Amount must be greater than 0".into());
+        }
+
+        let before = self._idle_assets()?;
+        strategy.withdraw(self, amount)?;
+        let received = self._idle_assets()?.saturating_sub(before);
+
+        self._record_withdrawal(protocol, received);
+        Ok(received)
     }
 
-    // ===== PROTOCOL INTEGRATION FUNCTIONS =====
+    /// Internal function returning the withdrawal queue
+    fn _withdrawal_queue(&self) -> Vec<String> {
+        (0..self.withdrawal_queue.len())
+            .filter_map(|i| self.withdrawal_queue.getter(i))
+            .map(|protocol| protocol.get_string())
+            .collect()
+    }
 
-    /// Deploys assets to Aave lending protocol
-    pub fn deploy_to_aave(&mut self, amount: U256) -> Result<(), Vec<u8>> {
-        if self.vm().msg_sender() != self.owner.get() {
-            return Err("Not owner".into());
+    /// Internal function returning the assets a withdrawal can reach: idle
+    /// assets plus the positions in queued protocols
+    fn _withdrawable_assets(&self) -> Result<U256, Vec<u8>> {
+        let mut assets = self._idle_assets()?;
+        for protocol in self._withdrawal_queue() {
+            assets += self._protocol_position(&protocol)?;
         }
+        Ok(assets)
+    }
 
-        if self.paused.get() {
-            return Err("Vault is paused".into());
-        }
+    /// Records a protocol's allocation
+    fn _set_allocation(&mut self, protocol: &str, amount: U256) {
+        self.protocol_allocations.setter(protocol.to_string()).set(amount);
+        self._touch_allocation(protocol);
+    }
 
-        if amount > self.total_assets.get() {
-            return Err("Insufficient assets".into());
+    /// Stamps a change to a protocol's allocation or target weight and keeps
+    /// the active list to the protocols that still have either
+    fn _touch_allocation(&mut self, protocol: &str) {
+        let timestamp = U256::from(self.vm().block_timestamp());
+        self.allocation_updated_at.setter(protocol.to_string()).set(timestamp);
+
+        let active = self.protocol_allocations.get(protocol.to_string()) != U256::ZERO
+            || self.target_weights_bps.get(protocol.to_string()) != U256::ZERO;
+        let mut protocols = self._active_protocols();
+        let listed = protocols.iter().any(|listed| listed == protocol);
+        if active == listed {
+            return;
+        }
+        if active {
+            protocols.push(protocol.to_string());
+        } else {
+            protocols.retain(|listed| listed != protocol);
         }
 
-        let aave_address = self.protocol_addresses.get("aave".to_string());
-        if aave_address == Address::ZERO {
-            return Err("Aave address not set".into());
+        self.active_protocols.erase();
+        for protocol in &protocols {
+            self.active_protocols.grow().set_str(protocol);
         }
+    }
 
-        // Approve Aave to spend our assets
-        self._approve_protocol(aave_address, amount)?;
+    /// Internal function returning the active protocols
+    fn _active_protocols(&self) -> Vec<String> {
+        (0..self.active_protocols.len())
+            .filter_map(|i| self.active_protocols.getter(i))
+            .map(|protocol| protocol.get_string())
+            .collect()
+    }
 
-        // Call Aave supply function
-        let selector = function_selector!("supply(address,uint256,address,uint16)");
-        let data = [
-            &selector[..],
-            &self.asset.get().into_array(),
-            &amount.to_be_bytes::<32>(),
-            &self.vm().contract_address().into_array(),
-            &U256::ZERO.to_be_bytes::<32>(), // referral code
-        ].concat();
+    /// Reduces a protocol's allocation by the assets that came back; yield
+    /// can bring back more than was deployed
+    fn _record_withdrawal(&mut self, protocol: &str, received: U256) {
+        let allocation = self.protocol_allocations.get(protocol.to_string());
+        self._set_allocation(protocol, allocation.saturating_sub(received));
 
-        unsafe {
-            let _ = RawCall::new().call(aave_address, &data);
+        log(self.vm(), ProtocolWithdrawn {
+            protocol: protocol.to_string(),
+            amount: received,
+        });
+    }
+
+    /// Internal function to harvest rewards from a protocol
+    fn _harvest_from_protocol(&mut self, protocol: String, min_amount_out: U256) -> Result<U256, Vec<u8>> {
+        let is_owner = self._only_manager()?;
+
+        self.pausable.when_not_paused()?;
+
+        let (reward_token, rewards) = strategy(&protocol)?.harvest(self)?;
+        // Include rewards an unpriced permissionless harvest claimed but didn't sell
+        let rewards = if reward_token == self.asset.get() {
+            rewards
+        } else {
+            safe_erc20::balance_of(self.vm(), reward_token, self.vm().contract_address())?
+        };
+        let proceeds = if reward_token == self.asset.get() || rewards == U256::ZERO {
+            rewards
+        } else {
+            // Only the owner may sell without a floor
+            let floor = if is_owner {
+                U256::ZERO
+            } else {
+                self._reward_floor(reward_token, rewards, self.max_harvest_slippage_bps.get())?
+            };
+            self._swap_to_asset(reward_token, rewards, min_amount_out.max(floor))?
+        };
+        if proceeds < min_amount_out {
+            return Err(InsufficientHarvestProceeds {
+                proceeds,
+                min_proceeds: min_amount_out,
+            }
+            .abi_encode());
         }
 
-        // Update allocation
-        self.protocol_allocations.setter("aave".to_string()).set(amount);
+        self._redeploy(proceeds, !is_owner)?;
 
-        // Emit event
-        log(self.vm(), ProtocolDeployed {
-            protocol: "aave".to_string(),
-            amount,
+        let one_share = U256::from(10).pow(U256::from(self.decimals()?));
+        log(self.vm(), ProtocolHarvested {
+            protocol,
+            rewards,
+            proceeds,
+            share_price: self._convert_to_assets(one_share, Rounding::Floor)?,
         });
 
-        Ok(())
+        Ok(proceeds)
     }
 
-    /// Deploys assets to Compound protocol
-    pub fn deploy_to_compound(&mut self, amount: U256) -> Result<(), Vec<u8>> {
-        if self.vm().msg_sender() != self.owner.get() {
-            return Err("Not owner".into());
-        }
+    /// Internal function to harvest all protocols for a bounty
+    fn _harvest(&mut self) -> Result<U256, Vec<u8>> {
+        self.pausable.when_not_paused()?;
 
-        if self.paused.get() {
-            return Err("Vault is paused".into());
+        let interval = self.harvest_interval.get();
+        if interval == U256::ZERO {
+            return Err("Harvest not enabled".into());
+        }
+        let now = U256::from(self.vm().block_timestamp());
+        if now < self.last_harvest.get().saturating_add(interval) {
+            return Err("Harvest too soon".into());
         }
 
-        if amount > self.total_assets.get() {
-            return Err("Insufficient assets".into());
+        let asset = self.asset.get();
+        let slippage = self.max_harvest_slippage_bps.get();
+        let mut claimed = false;
+        let mut proceeds = U256::ZERO;
+        for strategy in strategies::STRATEGIES {
+            if !strategy.is_harvestable(self)? {
+                continue;
+            }
+            let (reward_token, rewards) = strategy.harvest(self)?;
+            claimed |= rewards != U256::ZERO;
+            if reward_token == asset || rewards == U256::ZERO {
+                proceeds += rewards;
+            } else if self.reward_prices.get(reward_token) != U256::ZERO {
+                let floor = self._reward_floor(reward_token, rewards, slippage)?;
+                proceeds += self._swap_to_asset(reward_token, rewards, floor)?;
+            }
+        }
+        // A harvest that found nothing doesn't hold off the next one
+        if claimed {
+            self.last_harvest.set(now);
         }
 
-        let compound_address = self.protocol_addresses.get("compound".to_string());
-        if compound_address == Address::ZERO {
-            return Err("Compound address not set".into());
+        let bounty = mul_div(proceeds, self.harvest_bounty_bps.get(), U256::from(MAX_BPS), Rounding::Floor)?
+            .min(self.max_harvest_bounty.get());
+        let caller = self.vm().msg_sender();
+        if bounty != U256::ZERO {
+            safe_erc20::safe_transfer(self.vm(), asset, caller, bounty)?;
         }
+        self._redeploy(proceeds - bounty, true)?;
+
+        log(self.vm(), Harvested { caller, proceeds, bounty });
+        Ok(bounty)
+    }
 
-        // Approve Compound to spend our assets
-        self._approve_protocol(compound_address, amount)?;
+    /// Internal function to swap into the asset on the cached Uniswap router
+    fn _swap_exact_input(
+        &mut self,
+        path: &[u8],
+        amount_in: U256,
+        min_amount_out: U256,
+        deadline: U256,
+    ) -> Result<U256, Vec<u8>> {
+        self.ownable.only_owner()?;
 
-        // Call Compound supply function
-        let selector = function_selector!("supply(address,uint256)");
-        let data = [
-            &selector[..],
-            &self.asset.get().into_array(),
-            &amount.to_be_bytes::<32>(),
-        ].concat();
+        self.pausable.when_not_paused()?;
 
-        unsafe {
-            let _ = RawCall::new().call(compound_address, &data);
+        self._check_deadline(deadline)?;
+
+        if amount_in == U256::ZERO {
+            return Err("Amount must be greater than 0".into());
         }
 
-        // Update allocation
-        self.protocol_allocations.setter("compound".to_string()).set(amount);
+        // Whatever is sold, the vault ends up holding its asset
+        let asset = self.asset.get();
+        let (token_in, token_out) = uniswap::path_ends(path)?;
+        if token_out != asset {
+            return Err("Swap must end in the asset".into());
+        }
 
-        // Emit event
-        log(self.vm(), ProtocolDeployed {
-            protocol: "compound".to_string(),
-            amount,
+        let before = self._idle_assets()?;
+        uniswap::swap_exact_input(self, path, amount_in, min_amount_out)?;
+        // A round trip from the asset spends `amount_in` of it
+        let spent = if token_in == asset { amount_in } else { U256::ZERO };
+        let amount_out = (self._idle_assets()? + spent).saturating_sub(before);
+        if amount_out < min_amount_out {
+            return Err(InsufficientSwapOutput {
+                amount_out,
+                min_amount_out,
+            }
+            .abi_encode());
+        }
+
+        log(self.vm(), Swapped {
+            token_in,
+            amount_in,
+            amount_out,
         });
+        Ok(amount_out)
+    }
 
-        Ok(())
+    /// Internal function returning a protocol's (rewards contract, reward token)
+    fn _rewards_config(&self, protocol: &str) -> (Address, Address) {
+        (
+            self.protocol_addresses.get(strategies::rewards_key(protocol)),
+            self.protocol_addresses.get(strategies::reward_token_key(protocol)),
+        )
     }
 
-    /// Harvests rewards from a protocol
-    pub fn harvest_from_protocol(&mut self, protocol: String) -> Result<U256, Vec<u8>> {
-        if self.vm().msg_sender() != self.owner.get() {
-            return Err("Not owner".into());
+    /// Least a sale of `rewards` may bring: their value at the owner's
+    /// reference price, less `slippage_bps`
+    fn _reward_floor(&self, reward_token: Address, rewards: U256, slippage_bps: U256) -> Result<U256, Vec<u8>> {
+        let price = self.reward_prices.get(reward_token);
+        if price == U256::ZERO {
+            return Err("Reward price not set".into());
         }
+        let value = mul_div(rewards, price, U256::from(PRICE_SCALE), Rounding::Floor)?;
+        let max_bps = U256::from(MAX_BPS);
+        Ok(mul_div(value, max_bps - slippage_bps.min(max_bps), max_bps, Rounding::Ceil)?)
+    }
 
-        let protocol_address = self.protocol_addresses.get(protocol.clone());
-        if protocol_address == Address::ZERO {
-            return Err("Protocol address not set".into());
-        }
+    /// Sells `amount_in` of `token_in` for the asset on the cached Uniswap
+    /// router, returning the assets received
+    fn _swap_to_asset(&mut self, token_in: Address, amount_in: U256, min_amount_out: U256) -> Result<U256, Vec<u8>> {
+        let asset = self.asset.get();
+        let before = self._idle_assets()?;
+        uniswap::swap_exact_input_single(self, token_in, asset, REWARD_SWAP_FEE, amount_in, min_amount_out)?;
+        Ok(self._idle_assets()?.saturating_sub(before))
+    }
 
-        // This is a simplified implementation
-        // In practice, you'd call the specific harvest function for each protocol
-        let rewards = U256::ZERO; // Placeholder - would calculate actual rewards
+    /// Internal function to execute the planned rebalance
+    fn _rebalance(&mut self) -> Result<RebalanceMoves, Vec<u8>> {
+        self._only_manager()?;
 
-        // Emit event
-        log(self.vm(), ProtocolHarvested {
-            protocol: protocol.clone(),
-            rewards,
+        self.pausable.when_not_paused()?;
+
+        let (plan, _) = self._plan_rebalance()?;
+        if plan.is_empty() {
+            return Err("Nothing to rebalance".into());
+        }
+        let buffer = self._idle_buffer_target(self._total_assets()?)?;
+
+        let mut protocols = Vec::new();
+        let mut amounts = Vec::new();
+        for (strategy, amount) in plan {
+            let protocol = strategy.id().to_string();
+            let moved = if amount.is_negative() {
+                // A protocol weighted to zero is closed rather than left with dust
+                let amount = if self.target_weights_bps.get(protocol.clone()) == U256::ZERO {
+                    U256::MAX
+                } else {
+                    amount.unsigned_abs()
+                };
+                -_signed(self._unwind_protocol(&protocol, amount)?)?
+            } else {
+                // Withdrawals can bring back less than planned, so never dip into the buffer
+                let amount = amount.unsigned_abs().min(self._idle_assets()?.saturating_sub(buffer));
+                if amount == U256::ZERO {
+                    continue;
+                }
+                _signed(self._supply(strategy, amount)?)?
+            };
+            protocols.push(protocol);
+            amounts.push(moved);
+        }
+
+        log(self.vm(), Rebalanced {
+            protocols: protocols.clone(),
+            amounts: amounts.clone(),
         });
+        Ok((protocols, amounts))
+    }
+
+    /// Internal function planning a rebalance: the signed move for each
+    /// protocol whose drift exceeds the threshold, withdrawals first. Deposits
+    /// are funded by idle assets above the buffer plus what the withdrawals free;
+    /// when that excess alone exceeds the threshold, every under-weight protocol
+    /// is topped up. Also returns the `WORK_*` flags behind the plan.
+    fn _plan_rebalance(&self) -> Result<RebalancePlan, Vec<u8>> {
+        let total = self._total_assets()?;
+        if total == U256::ZERO {
+            return Ok((Vec::new(), 0));
+        }
+        let threshold = self.rebalance_threshold_bps.get();
+        let idle = self._idle_assets()?;
+        let buffer = self._idle_buffer_target(total)?;
+        let excess_idle = idle.saturating_sub(buffer);
+        let idle_drifted = excess_idle != U256::ZERO
+            && mul_div(excess_idle, U256::from(MAX_BPS), total, Rounding::Floor)? > threshold;
+
+        let mut withdrawals = Vec::new();
+        let mut deposits = Vec::new();
+        let mut available = idle;
+        for strategy in strategies::STRATEGIES {
+            let weight = self.target_weights_bps.get(strategy.id().to_string());
+            let target = mul_div(total, weight, U256::from(MAX_BPS), Rounding::Floor)?;
+            let current = strategy.total_value(self)?;
+            let drift = current.abs_diff(target);
+            if drift == U256::ZERO {
+                continue;
+            }
+            let drifted = mul_div(drift, U256::from(MAX_BPS), total, Rounding::Floor)? > threshold;
+            if current > target {
+                if drifted {
+                    available += drift;
+                    withdrawals.push((*strategy, -_signed(drift)?));
+                }
+            } else if (drifted || idle_drifted) && strategy.is_configured(self) {
+                // A protocol whose address was cleared keeps its weight but gets nothing
+                deposits.push((*strategy, drift, drifted));
+            }
+        }
 
-        Ok(rewards)
+        let mut work = if withdrawals.is_empty() { 0 } else { WORK_REBALANCE };
+        let mut available = available.saturating_sub(buffer);
+        let mut plan = withdrawals;
+        for (strategy, wanted, drifted) in deposits {
+            let amount = wanted.min(available);
+            if amount == U256::ZERO {
+                continue;
+            }
+            available -= amount;
+            work |= if drifted { WORK_REBALANCE } else { WORK_IDLE };
+            plan.push((strategy, _signed(amount)?));
+        }
+        Ok((plan, work))
     }
 
-    /// Gets protocol allocation
-    pub fn get_protocol_allocation(&self, protocol: String) -> Result<U256, Vec<u8>> {
-        Ok(self.protocol_allocations.get(protocol.clone()))
+    /// Internal function returning the assets rebalancing keeps idle out of `total_assets`
+    fn _idle_buffer_target(&self, total_assets: U256) -> Result<U256, Vec<u8>> {
+        Ok(mul_div(total_assets, self.idle_buffer_bps.get(), U256::from(MAX_BPS), Rounding::Floor)?)
     }
 
-    /// Gets protocol address
-    pub fn get_protocol_address(&self, protocol: String) -> Result<Address, Vec<u8>> {
-        Ok(self.protocol_addresses.get(protocol.clone()))
+    /// Internal function returning the idle buffer plus every protocol's target weight
+    fn _total_target_bps(&self) -> U256 {
+        strategies::STRATEGIES
+            .iter()
+            .map(|strategy| self.target_weights_bps.get(strategy.id().to_string()))
+            .fold(self.idle_buffer_bps.get(), |total, weight| total + weight)
     }
 
-    /// Internal function to transfer shares
-    fn _transfer_shares(&mut self, from: Address, to: Address, amount: U256) -> Result<(), Vec<u8>> {
-        if from == to {
+    /// Splits `assets` between protocols in proportion to their current
+    /// allocations; without allocations they stay idle. When `capped` (anyone
+    /// but the owner harvested), no protocol is taken past its target weight
+    /// and whatever doesn't fit stays idle for the next rebalance.
+    fn _redeploy(&mut self, assets: U256, capped: bool) -> Result<(), Vec<u8>> {
+        let allocations: Vec<(&dyn Strategy, U256)> = strategies::STRATEGIES
+            .iter()
+            .map(|strategy| (*strategy, self.protocol_allocations.get(strategy.id().to_string())))
+            .filter(|(_, allocation)| *allocation != U256::ZERO)
+            .collect();
+        let total: U256 = allocations.iter().map(|(_, allocation)| *allocation).sum();
+        if assets == U256::ZERO || total == U256::ZERO {
             return Ok(());
         }
-        
-        let from_balance = self.balances.get(from);
-        if from_balance < amount {
-            return Err("Insufficient balance".into());
+
+        // The last protocol takes the rounding remainder
+        let total_assets = self._total_assets()?;
+        let mut remaining = assets;
+        for (i, (strategy, allocation)) in allocations.iter().enumerate() {
+            let mut share = if i + 1 == allocations.len() {
+                remaining
+            } else {
+                mul_div(assets, *allocation, total, Rounding::Floor)?
+            };
+            remaining -= share;
+            if capped {
+                let weight = self.target_weights_bps.get(strategy.id().to_string());
+                let cap = mul_div(total_assets, weight, U256::from(MAX_BPS), Rounding::Floor)?;
+                share = share.min(cap.saturating_sub(strategy.total_value(self)?));
+            }
+            if share != U256::ZERO {
+                self._supply(*strategy, share)?;
+            }
         }
-        
-        // Update balances
-        let mut from_balance_setter = self.balances.setter(from);
-        from_balance_setter.set(from_balance - amount);
-        
-        let mut to_balance = self.balances.setter(to);
-        let to_current = to_balance.get();
-        to_balance.set(to_current + amount);
-        
-        // Emit Transfer event
-        log(self.vm(), Transfer {
-            from,
-            to,
-            value: amount,
-        });
-        
         Ok(())
     }
 
-    /// Internal function to calculate shares
-    fn _calculate_shares(&self, assets: U256) -> Result<U256, Vec<u8>> {
-        let total_supply = self.total_supply.get();
-        
-        if total_supply == U256::ZERO {
-            // First deposit - 1:1 ratio
-            return Ok(assets);
-        }
+    // Conversions use virtual shares (10^offset) and one virtual asset, so an
+    // empty vault still has a price and a donation cannot round a victim's
+    // deposit down to nothing without costing the donor far more
 
-        let total_assets = self.total_assets.get();
-        if total_assets == U256::ZERO {
-            return Ok(U256::ZERO);
+    /// Internal function to convert assets to shares with the given rounding
+    fn _convert_to_shares(&self, assets: U256, rounding: Rounding) -> Result<U256, Vec<u8>> {
+        self._shares_for(assets, self._total_assets()?, rounding)
+    }
+
+    /// Internal function to convert assets to shares at a given `total_assets`
+    fn _shares_for(&self, assets: U256, total_assets: U256, rounding: Rounding) -> Result<U256, Vec<u8>> {
+        let total_supply = self.erc20.total_supply.get() + self._virtual_shares();
+        Ok(mul_div(assets, total_supply, total_assets + U256::ONE, rounding)?)
+    }
+
+    /// Internal function to convert shares to assets with the given rounding
+    fn _convert_to_assets(&self, shares: U256, rounding: Rounding) -> Result<U256, Vec<u8>> {
+        let total_supply = self.erc20.total_supply.get() + self._virtual_shares();
+        let total_assets = self._total_assets()? + U256::ONE;
+        Ok(mul_div(shares, total_assets, total_supply, rounding)?)
+    }
+
+    /// Internal function returning the assets under management: idle balance
+    /// plus protocol positions, so protocol yield reaches the share price
+    fn _total_assets(&self) -> Result<U256, Vec<u8>> {
+        let mut assets = self._idle_assets()?;
+        for strategy in strategies::STRATEGIES {
+            assets += strategy.total_value(self)?;
         }
+        Ok(assets)
+    }
 
-        // Calculate shares: (assets * total_supply) / total_assets
-        Ok((assets * total_supply) / total_assets)
+    /// Internal function returning assets held by the vault and not deployed to protocols
+    fn _idle_assets(&self) -> Result<U256, Vec<u8>> {
+        safe_erc20::balance_of(self.vm(), self.asset.get(), self.vm().contract_address())
     }
 
-    /// Internal function to cache protocol addresses from factory
-    fn _cache_protocol_addresses(&mut self) -> Result<(), Vec<u8>> {
-        let _factory = self.factory.get();
-        
-        // Call factory to get protocol addresses
-        // This is a simplified implementation - in practice you'd call specific getter functions
-        let aave_address = Address::ZERO; // Would call factory.get_aave_address()
-        let compound_address = Address::ZERO; // Would call factory.get_compound_address()
-        let uniswap_address = Address::ZERO; // Would call factory.get_uniswap_address()
-        let weth_address = Address::ZERO; // Would call factory.get_weth_address()
-        
-        self.protocol_addresses.setter("aave".to_string()).set(aave_address);
-        self.protocol_addresses.setter("compound".to_string()).set(compound_address);
-        self.protocol_addresses.setter("uniswap".to_string()).set(uniswap_address);
-        self.protocol_addresses.setter("weth".to_string()).set(weth_address);
-        
-        Ok(())
+    /// Internal function returning the asset accounting mode
+    fn _asset_mode(&self) -> AssetMode {
+        AssetMode::from_u8(self.asset_mode.get().as_limbs()[0] as u8).unwrap_or(AssetMode::Standard)
     }
 
-    /// Internal function to approve protocol to spend assets
-    fn _approve_protocol(&mut self, protocol: Address, amount: U256) -> Result<(), Vec<u8>> {
-        // Call asset's approve function
-        let selector = function_selector!("approve(address,uint256)");
-        let data = [
-            &selector[..],
-            &protocol.into_array(),
-            &amount.to_be_bytes::<32>(),
-        ].concat();
+    /// Internal function returning `10^decimals_offset`
+    fn _virtual_shares(&self) -> U256 {
+        U256::from(10).pow(U256::from(self.decimals_offset.get()))
+    }
 
-        unsafe {
-            let _ = RawCall::new().call(self.asset.get(), &data);
+    /// Internal function returning the shares the next deposit burns (non-zero only for the first)
+    fn _dead_shares(&self) -> Result<U256, Vec<u8>> {
+        if self.erc20.total_supply.get() != U256::ZERO {
+            return Ok(U256::ZERO);
         }
+        self._convert_to_shares(self.min_initial_deposit.get(), Rounding::Floor)
+    }
 
-        Ok(())
+    /// Internal function to cache protocol addresses from factory, logging
+    /// and returning the protocols whose address changed
+    fn _cache_protocol_addresses(&mut self) -> Result<Vec<String>, Vec<u8>> {
+        let factory = self.factory.get();
+        let entries = [
+            ("aave", safe_erc20::view(self.vm(), factory, IVaultFactory::getAaveAddressCall {})?._0),
+            ("compound", safe_erc20::view(self.vm(), factory, IVaultFactory::getCompoundAddressCall {})?._0),
+            ("uniswap", safe_erc20::view(self.vm(), factory, IVaultFactory::getUniswapAddressCall {})?._0),
+            ("weth", safe_erc20::view(self.vm(), factory, IVaultFactory::getWethAddressCall {})?._0),
+        ];
+        self._apply_protocol_addresses(entries)
     }
 
-    /// Internal function to transfer assets from user to vault
-    fn _transfer_assets_from_user(&mut self, user: Address, amount: U256) -> Result<(), Vec<u8>> {
-        // Call asset's transferFrom function
-        let selector = function_selector!("transferFrom(address,address,uint256)");
-        let data = [
-            &selector[..],
-            &user.into_array(),
-            &self.vm().contract_address().into_array(),
-            &amount.to_be_bytes::<32>(),
-        ].concat();
+    /// Internal function to cache (protocol, address) pairs, logging and
+    /// returning the protocols whose address changed
+    fn _apply_protocol_addresses(&mut self, entries: [(&str, Address); 4]) -> Result<Vec<String>, Vec<u8>> {
+        let mut protocols = Vec::new();
+        let mut addresses = Vec::new();
+        for (protocol, address) in entries {
+            let cached = self.protocol_addresses.get(protocol.to_string());
+            if address == cached {
+                continue;
+            }
+            // Positions are found through the entry point, so one cannot be moved under them
+            if cached != Address::ZERO {
+                if let Some(strategy) = strategies::get(protocol) {
+                    if strategy.total_value(self)? != U256::ZERO {
+                        return Err("Position not empty".into());
+                    }
+                }
+            }
+            self.protocol_addresses.setter(protocol.to_string()).set(address);
+            if let Some(strategy) = strategies::get(protocol) {
+                strategy.forget_entry_point(self);
+            }
+            protocols.push(protocol.to_string());
+            addresses.push(address);
+        }
 
-        unsafe {
-            let _ = RawCall::new().call(self.asset.get(), &data);
+        if !protocols.is_empty() {
+            log(self.vm(), ProtocolAddressesRefreshed {
+                protocols: protocols.clone(),
+                addresses,
+            });
         }
+        Ok(protocols)
+    }
 
-        Ok(())
+    /// Internal function to transfer assets from user to vault, returning the amount received
+    fn _transfer_assets_from_user(&mut self, user: Address, amount: U256) -> Result<U256, Vec<u8>> {
+        let asset = self.asset.get();
+        let vault = self.vm().contract_address();
+        if self._asset_mode() == AssetMode::Standard {
+            safe_erc20::safe_transfer_from(self.vm(), asset, user, vault, amount)?;
+            return Ok(amount);
+        }
+
+        // Fee-on-transfer and rebasing tokens can deliver other than requested
+        let before = safe_erc20::balance_of(self.vm(), asset, vault)?;
+        safe_erc20::safe_transfer_from(self.vm(), asset, user, vault, amount)?;
+        let after = safe_erc20::balance_of(self.vm(), asset, vault)?;
+        Ok(after.saturating_sub(before))
     }
 
     /// Internal function to transfer assets from vault to user
     fn _transfer_assets_to_user(&mut self, user: Address, amount: U256) -> Result<(), Vec<u8>> {
-        // Call asset's transfer function
-        let selector = function_selector!("transfer(address,uint256)");
-        let data = [
-            &selector[..],
-            &user.into_array(),
-            &amount.to_be_bytes::<32>(),
-        ].concat();
+        safe_erc20::safe_transfer(self.vm(), self.asset.get(), user, amount)
+    }
+}
 
-        unsafe {
-            let _ = RawCall::new().call(self.asset.get(), &data);
-        }
+impl StrategyHost for UserVault {
+    fn host(&self) -> &dyn Host {
+        self.vm()
+    }
 
-        Ok(())
+    fn asset_token(&self) -> Address {
+        self.asset.get()
+    }
+
+    fn vault_address(&self) -> Address {
+        self.vm().contract_address()
+    }
+
+    fn cached_address(&self, key: &str) -> Address {
+        self.protocol_addresses.get(key.to_string())
+    }
+
+    fn cache_address(&mut self, key: &str, value: Address) {
+        self.protocol_addresses.setter(key.to_string()).set(value);
     }
 
+    fn cached_value(&self, key: &str) -> U256 {
+        self.strategy_values.get(key.to_string())
+    }
+
+    fn cache_value(&mut self, key: &str, value: U256) {
+        self.strategy_values.setter(key.to_string()).set(value);
+    }
+}
+
+/// Looks up the adapter registered for a protocol id
+fn strategy(protocol: &str) -> Result<&'static dyn Strategy, Vec<u8>> {
+    strategies::get(protocol).ok_or_else(|| b"Unsupported protocol".to_vec())
+}
+
+/// Converts an asset amount for a signed rebalance move
+fn _signed(amount: U256) -> Result<I256, Vec<u8>> {
+    I256::try_from(amount).map_err(|_| b"Amount too large".to_vec())
 }
 
 /// Internal function to hash strings
@@ -1,12 +1,28 @@
 use alloc::{vec::Vec, string::{String, ToString}};
 use stylus_sdk::{
+    abi::Bytes,
     alloy_primitives::{U256, Address},
-    alloy_sol_types::sol,
+    alloy_sol_types::{sol, sol_data, SolCall, SolError, SolType},
     prelude::*,
+    stylus_core::calls::context::Call,
 };
 
+use crate::ownable::Ownable;
+use crate::pausable::Pausable;
+use crate::reentrancy_guard::ReentrancyGuard;
+use crate::safe_erc20::{self, FailedCall};
+use crate::user_vault::{WORK_HARVEST, WORK_IDLE, WORK_REBALANCE};
+
 sol_storage! {
-    #[entrypoint]
+    #[cfg_attr(
+        not(any(
+            feature = "mock-stablecoin",
+            feature = "mock-weth",
+            feature = "mock-fee-token",
+            feature = "mock-rebasing-token"
+        )),
+        entrypoint
+    )]
     pub struct VaultFactory {
         /// Mapping of user to their vaults
         mapping(address => address[]) user_vaults;
@@ -36,8 +52,14 @@ sol_storage! {
         address uniswap_router;
         address weth_address;
         
-        /// Admin system
-        address deployer_admin;
+        /// Admin system: the owner is always an admin, the list holds the others
+        #[borrow]
+        Ownable ownable;
+        #[borrow]
+        Pausable pausable;
+        /// Lock for entry points that call into vaults
+        #[borrow]
+        ReentrancyGuard reentrancy_guard;
         mapping(address => bool) admin_list;
         uint256 admin_count;
         
@@ -46,6 +68,12 @@ sol_storage! {
         mapping(address => bytes32) user_username_hashes;
         mapping(address => bytes32) user_bio_hashes;
         mapping(address => uint256) user_registration_timestamps;
+
+        /// Deployed vaults by registration ID (from 1), for keepers and address pushes
+        mapping(uint256 => address) vaults_by_id;
+        /// Number of deployed vaults registered with `register_vault`
+        uint256 deployed_vaults;
+        mapping(address => bool) is_deployed_vault;
     }
 }
 
@@ -87,16 +115,56 @@ sol! {
         string protocol,
         uint256 allocation
     );
+    event VaultRefreshFailed(
+        address indexed vault
+    );
+    event VaultRegistered(
+        address indexed owner,
+        address indexed vault,
+        uint256 id
+    );
+    event UpkeepFailed(
+        address indexed vault,
+        uint8 work,
+        bytes reason
+    );
+
+    interface IKeeperFactory {
+        function performUpkeep(bytes performData) external;
+    }
+
+    interface IUserVault {
+        // Stylus exports the `[u8; 32]` hashes as `uint8[32]`
+        function getVaultInfo() external view returns (address owner, address asset, address factory, uint256 totalAssets, uint256 totalSupply, uint8[32] usernameHash, uint8[32] bioHash, uint256 createdAt, bool paused);
+        function asset() external view returns (address);
+        function strategist() external view returns (address);
+        function pendingWork() external view returns (uint8);
+        function harvest() external returns (uint256);
+        function rebalance() external returns (string[] memory, int256[] memory);
+        function updateProtocolAddresses(address aave, address compound, address uniswap, address weth) external returns (string[] memory);
+    }
 }
 
+/// (vaults, work, failed): the vaults with work for a keeper and their
+/// `WORK_*` flags, then the vaults that couldn't be asked
+type VaultScan = (Vec<Address>, Vec<u8>, Vec<Address>);
+
+/// `abi.encode(address[] vaults, uint8[] work)`, the upkeep perform data
+pub type PerformData = (sol_data::Array<sol_data::Address>, sol_data::Array<sol_data::Uint<8>>);
+
+/// Work done by calling a vault's `rebalance`
+const WORK_REBALANCING: u8 = WORK_REBALANCE | WORK_IDLE;
+
 #[public]
+#[inherit(Ownable, Pausable)]
 impl VaultFactory {
-    /// Constructor - automatically sets deployer as admin
+    /// Constructor - automatically sets deployer as owner, and so as admin
     pub fn init(&mut self) -> Result<(), Vec<u8>> {
+        if self.ownable.owner.get() != Address::ZERO {
+            return Err("Already initialized".into());
+        }
         let deployer = self.vm().msg_sender();
-        self.deployer_admin.set(deployer);
-        self.admin_list.insert(deployer, true);
-        self.admin_count.set(U256::from(1));
+        self.ownable.transfer_ownership_impl(deployer);
         Ok(())
     }
 
@@ -106,6 +174,8 @@ impl VaultFactory {
         username: String,
         bio: String,
     ) -> Result<(), Vec<u8>> {
+        self.pausable.when_not_paused()?;
+
         if username.len() > 20 {
             return Err("Username too long".into());
         }
@@ -141,8 +211,11 @@ impl VaultFactory {
 
     /// Creates a new vault for a registered user
     /// Note: This creates a vault entry in the factory, not a separate contract
-    /// The vault functionality is handled by the factory itself
+    /// The vault functionality is handled by the factory itself; keepers only
+    /// reach deployed vaults added with `register_vault`
     pub fn create_vault(&mut self) -> Result<Address, Vec<u8>> {
+        self.pausable.when_not_paused()?;
+
         let user = self.vm().msg_sender();
         
         // Check if user is registered
@@ -153,37 +226,55 @@ impl VaultFactory {
         // Generate a unique vault ID for this user
         let vault_id = self.total_vaults.get() + U256::ONE;
         let vault_address = self._generate_vault_address(user, vault_id);
-        
-        // Store vault info
-        let timestamp = U256::from(self.vm().block_timestamp());
-        
-        self.vault_owners.setter(vault_address).set(user);
-        self.vault_created_at.setter(vault_address).set(timestamp);
-        self.vault_ids.setter(vault_address).set(vault_id);
-        
-        // Get user's profile data from registration
-        let username_hash = self.user_username_hashes.get(user);
-        let bio_hash = self.user_bio_hashes.get(user);
-        self.vault_username_hashes.setter(vault_address).set(username_hash);
-        self.vault_bio_hashes.setter(vault_address).set(bio_hash);
-        
-        // Add to user's vault list
-        let mut vaults = self.user_vaults.setter(user);
-        vaults.push(vault_address);
-        
-        // Update total vaults
-        self.total_vaults.set(vault_id);
-        
-        // Emit event
-        log(self.vm(), VaultCreated {
-            owner: user,
-            vault: vault_address,
-            timestamp,
-        });
+        self._record_vault(user, vault_address);
 
         Ok(vault_address)
     }
 
+    /// Registers a deployed `UserVault` owned by the caller and initialized
+    /// with this factory, so keepers and protocol address pushes reach it
+    pub fn register_vault(&mut self, vault: Address) -> Result<U256, Vec<u8>> {
+        self.pausable.when_not_paused()?;
+
+        let user = self.vm().msg_sender();
+        if !self.registered_users.get(user) {
+            return Err("User not registered".into());
+        }
+        if self.is_deployed_vault.get(vault) {
+            return Err("Vault already registered".into());
+        }
+        // Views on accounts without code succeed with no data
+        if self.vm().code_size(vault) == 0 {
+            return Err("Vault has no code".into());
+        }
+        let info = safe_erc20::view(self.vm(), vault, IUserVault::getVaultInfoCall {})?;
+        if info.factory != self.vm().contract_address() {
+            return Err("Vault has another factory".into());
+        }
+        if info.owner != user {
+            return Err("Not vault owner".into());
+        }
+
+        let id = self.deployed_vaults.get() + U256::ONE;
+        self.deployed_vaults.set(id);
+        self.vaults_by_id.setter(id).set(vault);
+        self.is_deployed_vault.setter(vault).set(true);
+        self._record_vault(user, vault);
+        log(self.vm(), VaultRegistered { owner: user, vault, id });
+
+        Ok(id)
+    }
+
+    /// Returns how many deployed vaults were registered
+    pub fn get_deployed_vault_count(&self) -> Result<U256, Vec<u8>> {
+        Ok(self.deployed_vaults.get())
+    }
+
+    /// Returns whether a vault was registered with `register_vault`
+    pub fn is_deployed_vault(&self, vault: Address) -> Result<bool, Vec<u8>> {
+        Ok(self.is_deployed_vault.get(vault))
+    }
+
     /// Gets all vaults for a user
     pub fn get_user_vaults(&self, user: Address) -> Result<Vec<Address>, Vec<u8>> {
         let vaults = self.user_vaults.get(user);
@@ -227,9 +318,18 @@ impl VaultFactory {
 
     // ===== ADMIN FUNCTIONS =====
 
-    /// Check if address is admin
-    fn is_admin(&self, addr: Address) -> bool {
-        self.admin_list.get(addr)
+    /// Pauses user registration, vault creation, deposits and upkeep (owner only)
+    pub fn pause(&mut self) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.pausable.pause_impl()?;
+        Ok(())
+    }
+
+    /// Unpauses the factory (owner only)
+    pub fn unpause(&mut self) -> Result<(), Vec<u8>> {
+        self.ownable.only_owner()?;
+        self.pausable.unpause_impl()?;
+        Ok(())
     }
 
     /// Add admin to admin list
@@ -259,8 +359,8 @@ impl VaultFactory {
             return Err("Not authorized".into());
         }
         
-        if admin_to_remove == self.deployer_admin.get() {
-            return Err("Cannot remove deployer admin".into());
+        if admin_to_remove == self.ownable.owner.get() {
+            return Err("Cannot remove owner".into());
         }
         
         if !self.is_admin(admin_to_remove) {
@@ -330,6 +430,17 @@ impl VaultFactory {
         Ok(())
     }
 
+    /// Pushes the protocol addresses to registered vaults `offset + 1` to
+    /// `offset + limit` (admin only), so a change reaches existing vaults;
+    /// returns the vaults that refused it, e.g. because a position is still
+    /// open on the old entry point
+    pub fn refresh_vault_protocol_addresses(&mut self, offset: U256, limit: U256) -> Result<Vec<Address>, Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._refresh_vault_protocol_addresses(offset, limit);
+        self.reentrancy_guard.exit();
+        result
+    }
+
     /// Gets protocol addresses
     pub fn get_aave_address(&self) -> Result<Address, Vec<u8>> {
         Ok(self.aave_lending_pool.get())
@@ -349,13 +460,15 @@ impl VaultFactory {
 
     /// Gets deployer admin address
     pub fn get_deployer_admin(&self) -> Result<Address, Vec<u8>> {
-        Ok(self.deployer_admin.get())
+        Ok(self.ownable.owner.get())
     }
 
     // ===== VAULT MANAGEMENT FUNCTIONS =====
 
     /// Deposits assets into a vault
     pub fn deposit_to_vault(&mut self, vault_address: Address, amount: U256) -> Result<U256, Vec<u8>> {
+        self.pausable.when_not_paused()?;
+
         let user = self.vm().msg_sender();
         
         // Check if user owns this vault
@@ -414,9 +527,11 @@ impl VaultFactory {
         Ok(self.is_admin(addr))
     }
 
-    /// Get admin count
+    /// Get admin count, including the owner
     pub fn get_admin_count(&self) -> Result<U256, Vec<u8>> {
-        Ok(self.admin_count.get())
+        let owner = self.ownable.owner.get();
+        let owner_unlisted = owner != Address::ZERO && !self.admin_list.get(owner);
+        Ok(self.admin_count.get() + U256::from(owner_unlisted as u8))
     }
 
     // ===== USER REGISTRATION FUNCTIONS =====
@@ -450,7 +565,202 @@ impl VaultFactory {
         Ok(self.user_bio_hashes.get(user).into())
     }
 
-    // ===== INTERNAL FUNCTIONS =====
+    // ===== KEEPER FUNCTIONS =====
+
+    /// Asks registered vaults `offset + 1` to `offset + limit` for their
+    /// `pending_work`; returns the vaults with work a keeper can do through
+    /// `perform_upkeep`, their flags, and the vaults whose calls failed.
+    /// Rebalancing only counts where the factory is the vault's strategist.
+    pub fn vaults_needing_work(&self, offset: U256, limit: U256) -> Result<VaultScan, Vec<u8>> {
+        let end = offset.saturating_add(limit).min(self.deployed_vaults.get());
+        let mut vaults = Vec::new();
+        let mut works = Vec::new();
+        let mut failed = Vec::new();
+        let mut id = offset;
+        while id < end {
+            id += U256::ONE;
+            let vault = self.vaults_by_id.get(id);
+            match self._keeper_work(vault) {
+                Ok(0) => {}
+                Ok(work) => {
+                    vaults.push(vault);
+                    works.push(work);
+                }
+                Err(_) => failed.push(vault),
+            }
+        }
+        Ok((vaults, works, failed))
+    }
+
+    /// Chainlink Automation `checkUpkeep`: `check_data` is empty to scan every
+    /// registered vault, or `abi.encode(uint256 offset, uint256 limit)`. The
+    /// perform data is `abi.encode(address[] vaults, uint8[] work)`.
+    pub fn check_upkeep(&self, check_data: Bytes) -> Result<(bool, Bytes), Vec<u8>> {
+        let (offset, limit) = if check_data.is_empty() {
+            (U256::ZERO, U256::MAX)
+        } else {
+            <(sol_data::Uint<256>, sol_data::Uint<256>)>::abi_decode_params(&check_data, true)
+                .map_err(|_| b"Invalid check data".to_vec())?
+        };
+        let (vaults, works, _) = self.vaults_needing_work(offset, limit)?;
+        Ok((!vaults.is_empty(), PerformData::abi_encode_params(&(vaults, works)).into()))
+    }
+
+    /// Gelato `checker`: scans every registered vault; the payload is the
+    /// `performUpkeep` calldata, or the reason there is nothing to execute
+    pub fn checker(&self) -> Result<(bool, Bytes), Vec<u8>> {
+        let (vaults, works, failed) = self.vaults_needing_work(U256::ZERO, U256::MAX)?;
+        if vaults.is_empty() {
+            let reason: &[u8] = if failed.is_empty() { b"No vault needs work" } else { b"Vault checks failed" };
+            return Ok((false, reason.to_vec().into()));
+        }
+        let perform_data = PerformData::abi_encode_params(&(vaults, works));
+        let payload = IKeeperFactory::performUpkeepCall { performData: perform_data.into() }.abi_encode();
+        Ok((true, payload.into()))
+    }
+
+    /// Chainlink Automation `performUpkeep`: does the work listed in
+    /// `perform_data` that registered vaults still report, paying harvest
+    /// bounties to the caller. A vault that fails is logged with
+    /// `UpkeepFailed` and skipped.
+    pub fn perform_upkeep(&mut self, perform_data: Bytes) -> Result<(), Vec<u8>> {
+        self.reentrancy_guard.enter()?;
+        let result = self._perform_upkeep(perform_data);
+        self.reentrancy_guard.exit();
+        result
+    }
+}
+
+// ===== INTERNAL FUNCTIONS =====
+// These methods aren't exposed to other contracts
+impl VaultFactory {
+    /// Check if address is admin (the owner always is)
+    fn is_admin(&self, addr: Address) -> bool {
+        addr == self.ownable.owner.get() || self.admin_list.get(addr)
+    }
+
+    /// Internal function pushing the protocol addresses to registered vaults
+    fn _refresh_vault_protocol_addresses(&mut self, offset: U256, limit: U256) -> Result<Vec<Address>, Vec<u8>> {
+        if !self.is_admin(self.vm().msg_sender()) {
+            return Err("Not authorized".into());
+        }
+
+        let data = IUserVault::updateProtocolAddressesCall {
+            aave: self.aave_lending_pool.get(),
+            compound: self.compound_comptroller.get(),
+            uniswap: self.uniswap_router.get(),
+            weth: self.weth_address.get(),
+        }
+        .abi_encode();
+        let end = offset.saturating_add(limit).min(self.deployed_vaults.get());
+        let mut failed = Vec::new();
+        let mut id = offset;
+        while id < end {
+            id += U256::ONE;
+            let vault = self.vaults_by_id.get(id);
+            // Calls to accounts without code succeed without doing anything
+            if self.vm().code_size(vault) == 0 || self.vm().call(&Call::new(), vault, &data).is_err() {
+                log(self.vm(), VaultRefreshFailed { vault });
+                failed.push(vault);
+            }
+        }
+        Ok(failed)
+    }
+
+    /// Internal function doing the listed upkeep work
+    fn _perform_upkeep(&mut self, perform_data: Bytes) -> Result<(), Vec<u8>> {
+        self.pausable.when_not_paused()?;
+
+        let (vaults, works) = PerformData::abi_decode_params(&perform_data, true)
+            .map_err(|_| b"Invalid perform data".to_vec())?;
+        if vaults.len() != works.len() {
+            return Err("Invalid perform data".into());
+        }
+
+        let keeper = self.vm().msg_sender();
+        for (vault, requested) in vaults.into_iter().zip(works) {
+            if !self.is_deployed_vault.get(vault) {
+                return Err("Vault not registered".into());
+            }
+            // Perform data can be stale or made up: only do what is due now
+            let work = match self._keeper_work(vault) {
+                Ok(work) => work & requested,
+                Err(reason) => {
+                    log(self.vm(), UpkeepFailed { vault, work: requested, reason: reason.into() });
+                    continue;
+                }
+            };
+            if work & WORK_HARVEST != 0 {
+                if let Err(reason) = self._harvest_for(vault, keeper) {
+                    log(self.vm(), UpkeepFailed { vault, work: WORK_HARVEST, reason: reason.into() });
+                }
+            }
+            if work & WORK_REBALANCING != 0 {
+                let data = IUserVault::rebalanceCall {}.abi_encode();
+                if let Err(reason) = safe_erc20::function_call(self.vm(), vault, &data) {
+                    log(self.vm(), UpkeepFailed { vault, work: work & WORK_REBALANCING, reason: reason.into() });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a vault for `user` with their profile and logs its creation
+    fn _record_vault(&mut self, user: Address, vault_address: Address) {
+        let vault_id = self.total_vaults.get() + U256::ONE;
+
+        // Store vault info
+        let timestamp = U256::from(self.vm().block_timestamp());
+        
+        self.vault_owners.setter(vault_address).set(user);
+        self.vault_created_at.setter(vault_address).set(timestamp);
+        self.vault_ids.setter(vault_address).set(vault_id);
+        
+        // Get user's profile data from registration
+        let username_hash = self.user_username_hashes.get(user);
+        let bio_hash = self.user_bio_hashes.get(user);
+        self.vault_username_hashes.setter(vault_address).set(username_hash);
+        self.vault_bio_hashes.setter(vault_address).set(bio_hash);
+        
+        // Add to user's vault list
+        let mut vaults = self.user_vaults.setter(user);
+        vaults.push(vault_address);
+        
+        // Update total vaults
+        self.total_vaults.set(vault_id);
+        
+        // Emit event
+        log(self.vm(), VaultCreated {
+            owner: user,
+            vault: vault_address,
+            timestamp,
+        });
+    }
+
+    /// Reads a vault's `pending_work` flags for the work the factory can do:
+    /// anyone may harvest, but rebalancing needs the factory as strategist
+    fn _keeper_work(&self, vault: Address) -> Result<u8, Vec<u8>> {
+        let mut work = safe_erc20::view(self.vm(), vault, IUserVault::pendingWorkCall {})?._0;
+        if work & WORK_REBALANCING != 0
+            && safe_erc20::view(self.vm(), vault, IUserVault::strategistCall {})?._0 != self.vm().contract_address()
+        {
+            work &= !WORK_REBALANCING;
+        }
+        Ok(work)
+    }
+
+    /// Harvests a vault and passes its bounty, paid in the vault's asset, on to the keeper
+    fn _harvest_for(&mut self, vault: Address, keeper: Address) -> Result<(), Vec<u8>> {
+        let asset = safe_erc20::view(self.vm(), vault, IUserVault::assetCall {})?._0;
+        let returned = safe_erc20::function_call(self.vm(), vault, &IUserVault::harvestCall {}.abi_encode())?;
+        let bounty = IUserVault::harvestCall::abi_decode_returns(&returned, true)
+            .map_err(|_| FailedCall { target: vault }.abi_encode())?
+            ._0;
+        if bounty != U256::ZERO {
+            safe_erc20::safe_transfer(self.vm(), asset, keeper, bounty)?;
+        }
+        Ok(())
+    }
 
     /// Generates a deterministic vault address for a user
     fn _generate_vault_address(&self, user: Address, vault_id: U256) -> Address {